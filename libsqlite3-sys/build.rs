@@ -121,12 +121,18 @@ mod build_bundled {
         if cfg!(feature = "unlock_notify") {
             cfg.flag("-DSQLITE_ENABLE_UNLOCK_NOTIFY");
         }
+        if cfg!(feature = "scanstatus") {
+            cfg.flag("-DSQLITE_ENABLE_STMT_SCANSTATUS");
+        }
         if cfg!(feature = "preupdate_hook") {
             cfg.flag("-DSQLITE_ENABLE_PREUPDATE_HOOK");
         }
         if cfg!(feature = "session") {
             cfg.flag("-DSQLITE_ENABLE_SESSION");
         }
+        if cfg!(feature = "deserialize") {
+            cfg.flag("-DSQLITE_ENABLE_DESERIALIZE");
+        }
 
         if let Ok(limit) = env::var("SQLITE_MAX_VARIABLE_NUMBER") {
             cfg.flag(&format!("-DSQLITE_MAX_VARIABLE_NUMBER={}", limit));
@@ -390,6 +396,9 @@ mod bindings {
         if cfg!(feature = "session") {
             bindings = bindings.clang_arg("-DSQLITE_ENABLE_SESSION");
         }
+        if cfg!(feature = "deserialize") {
+            bindings = bindings.clang_arg("-DSQLITE_ENABLE_DESERIALIZE");
+        }
         if cfg!(all(windows, feature = "winsqlite3")) {
             bindings = bindings
                 .clang_arg("-DBINDGEN_USE_WINSQLITE3")
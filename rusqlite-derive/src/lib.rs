@@ -0,0 +1,338 @@
+//! Derive macros for the `FromSql`, `ToSql` and `FromRow` traits of
+//! [rusqlite](https://docs.rs/rusqlite), re-exported by rusqlite when its
+//! `derive` feature is enabled.
+//!
+//! `#[derive(FromSql, ToSql)]` supports:
+//!
+//! * newtypes (structs with a single field), which are stored like the
+//!   wrapped value,
+//! * enums with unit variants only, stored as the variant name (text), or
+//!   as the variant discriminant with `#[sql(integer)]` on the enum. Variants
+//!   may be stored under another name with `#[sql(rename = "...")]`.
+//!
+//! `#[derive(FromRow)]` supports structs, whose fields are read with
+//! `Row::get` from the column of the same name (named fields) or at the same
+//! position (tuple fields). Field attributes:
+//!
+//! * `#[sql(rename = "...")]`: read the field from the column named `...`,
+//! * `#[sql(index = N)]`: read the field from the column at index `N`,
+//! * `#[sql(default)]`: use `Default::default()` if the column is missing,
+//! * `#[sql(flatten)]`: read the field with its own `FromRow` implementation,
+//!   from the same row.
+#![deny(missing_docs)]
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, Ident, Lit, Meta, NestedMeta,
+    Result,
+};
+
+/// Derive `rusqlite::types::FromSql` for a newtype or a unit-only enum.
+#[proc_macro_derive(FromSql, attributes(sql))]
+pub fn derive_from_sql(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(from_sql(&input))
+}
+
+/// Derive `rusqlite::types::ToSql` for a newtype or a unit-only enum.
+#[proc_macro_derive(ToSql, attributes(sql))]
+pub fn derive_to_sql(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(to_sql(&input))
+}
+
+/// Derive `rusqlite::FromRow` for a struct.
+#[proc_macro_derive(FromRow, attributes(sql))]
+pub fn derive_from_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(from_row(&input))
+}
+
+fn expand(result: Result<TokenStream2>) -> TokenStream {
+    result.unwrap_or_else(|err| err.to_compile_error()).into()
+}
+
+/// Parsed `#[sql(...)]` attributes.
+#[derive(Default)]
+struct SqlAttrs {
+    rename: Option<String>,
+    index: Option<usize>,
+    default: bool,
+    flatten: bool,
+    integer: bool,
+}
+
+impl SqlAttrs {
+    /// Parse the `#[sql(...)]` attributes, rejecting the ones not in
+    /// `allowed`.
+    fn parse(attrs: &[Attribute], allowed: &[&str]) -> Result<SqlAttrs> {
+        let mut sql_attrs = SqlAttrs::default();
+        for attr in attrs.iter().filter(|attr| attr.path.is_ident("sql")) {
+            let list = match attr.parse_meta()? {
+                Meta::List(list) => list,
+                meta => return Err(Error::new_spanned(meta, "expected `sql(...)`")),
+            };
+            for nested in list.nested {
+                let meta = match nested {
+                    NestedMeta::Meta(meta) => meta,
+                    lit => return Err(Error::new_spanned(lit, "unexpected literal")),
+                };
+                let name = meta
+                    .path()
+                    .get_ident()
+                    .map(Ident::to_string)
+                    .unwrap_or_default();
+                if !allowed.contains(&name.as_str()) {
+                    return Err(Error::new_spanned(
+                        meta.path(),
+                        format!(
+                            "unsupported sql attribute, expected one of: {}",
+                            allowed.join(", ")
+                        ),
+                    ));
+                }
+                match (name.as_str(), &meta) {
+                    ("rename", Meta::NameValue(nv)) => match nv.lit {
+                        Lit::Str(ref s) => sql_attrs.rename = Some(s.value()),
+                        ref lit => return Err(Error::new_spanned(lit, "expected a string")),
+                    },
+                    ("index", Meta::NameValue(nv)) => match nv.lit {
+                        Lit::Int(ref i) => sql_attrs.index = Some(i.base10_parse()?),
+                        ref lit => return Err(Error::new_spanned(lit, "expected an integer")),
+                    },
+                    ("default", Meta::Path(_)) => sql_attrs.default = true,
+                    ("flatten", Meta::Path(_)) => sql_attrs.flatten = true,
+                    ("integer", Meta::Path(_)) => sql_attrs.integer = true,
+                    _ => return Err(Error::new_spanned(meta, "malformed sql attribute")),
+                }
+            }
+        }
+        Ok(sql_attrs)
+    }
+}
+
+/// The single field of a newtype: its accessor and type.
+fn newtype_field(fields: &Fields) -> Option<(TokenStream2, &syn::Type)> {
+    match fields {
+        Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+            Some((quote!(0), &fields.unnamed[0].ty))
+        }
+        Fields::Named(fields) if fields.named.len() == 1 => {
+            let field = &fields.named[0];
+            let ident = field.ident.as_ref().unwrap();
+            Some((quote!(#ident), &field.ty))
+        }
+        _ => None,
+    }
+}
+
+/// The unit variants of an enum, with the name they are stored as.
+fn unit_variants(input: &DeriveInput, data: &syn::DataEnum) -> Result<Vec<(Ident, String)>> {
+    let integer = SqlAttrs::parse(&input.attrs, &["integer"])?.integer;
+    let allowed: &[&str] = if integer { &[] } else { &["rename"] };
+    data.variants
+        .iter()
+        .map(|variant| {
+            if !matches!(variant.fields, Fields::Unit) {
+                return Err(Error::new_spanned(
+                    variant,
+                    "only enums with unit variants are supported",
+                ));
+            }
+            let attrs = SqlAttrs::parse(&variant.attrs, allowed)?;
+            let name = attrs.rename.unwrap_or_else(|| variant.ident.to_string());
+            Ok((variant.ident.clone(), name))
+        })
+        .collect()
+}
+
+fn unsupported(input: &DeriveInput, trait_name: &str) -> Error {
+    Error::new(
+        input.ident.span(),
+        format!(
+            "{} can only be derived for newtypes and enums with unit variants",
+            trait_name
+        ),
+    )
+}
+
+fn from_sql(input: &DeriveInput) -> Result<TokenStream2> {
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let body = match input.data {
+        Data::Struct(ref data) => {
+            SqlAttrs::parse(&input.attrs, &[])?;
+            let (field, ty) =
+                newtype_field(&data.fields).ok_or_else(|| unsupported(input, "FromSql"))?;
+            let constructor = match data.fields {
+                Fields::Unnamed(_) => quote!(#ident(value)),
+                _ => quote!(#ident { #field: value }),
+            };
+            quote! {
+                <#ty as ::rusqlite::types::FromSql>::column_result(value)
+                    .map(|value| #constructor)
+            }
+        }
+        Data::Enum(ref data) => {
+            let variants = unit_variants(input, data)?;
+            let idents = variants.iter().map(|(variant, _)| variant);
+            if SqlAttrs::parse(&input.attrs, &["integer"])?.integer {
+                quote! {
+                    let value = <i64 as ::rusqlite::types::FromSql>::column_result(value)?;
+                    #(
+                        if value == #ident::#idents as i64 {
+                            return Ok(#ident::#idents);
+                        }
+                    )*
+                    Err(::rusqlite::types::FromSqlError::OutOfRange(value))
+                }
+            } else {
+                let names = variants.iter().map(|(_, name)| name);
+                let message = format!("unknown {} variant: {{:?}}", ident);
+                quote! {
+                    match value.as_str()? {
+                        #(#names => Ok(#ident::#idents),)*
+                        value => Err(::rusqlite::types::FromSqlError::Other(
+                            format!(#message, value).into(),
+                        )),
+                    }
+                }
+            }
+        }
+        Data::Union(_) => return Err(unsupported(input, "FromSql")),
+    };
+    Ok(quote! {
+        impl #impl_generics ::rusqlite::types::FromSql for #ident #ty_generics #where_clause {
+            fn column_result(
+                value: ::rusqlite::types::ValueRef<'_>,
+            ) -> ::rusqlite::types::FromSqlResult<Self> {
+                #body
+            }
+        }
+    })
+}
+
+fn to_sql(input: &DeriveInput) -> Result<TokenStream2> {
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let body = match input.data {
+        Data::Struct(ref data) => {
+            SqlAttrs::parse(&input.attrs, &[])?;
+            let (field, _) =
+                newtype_field(&data.fields).ok_or_else(|| unsupported(input, "ToSql"))?;
+            quote! {
+                ::rusqlite::types::ToSql::to_sql(&self.#field)
+            }
+        }
+        Data::Enum(ref data) => {
+            let variants = unit_variants(input, data)?;
+            let idents = variants.iter().map(|(variant, _)| variant);
+            if SqlAttrs::parse(&input.attrs, &["integer"])?.integer {
+                quote! {
+                    let value = match *self {
+                        #(#ident::#idents => #ident::#idents as i64,)*
+                    };
+                    Ok(::rusqlite::types::ToSqlOutput::from(value))
+                }
+            } else {
+                let names = variants.iter().map(|(_, name)| name);
+                quote! {
+                    let value = match *self {
+                        #(#ident::#idents => #names,)*
+                    };
+                    Ok(::rusqlite::types::ToSqlOutput::from(value))
+                }
+            }
+        }
+        Data::Union(_) => return Err(unsupported(input, "ToSql")),
+    };
+    Ok(quote! {
+        impl #impl_generics ::rusqlite::types::ToSql for #ident #ty_generics #where_clause {
+            fn to_sql(&self) -> ::rusqlite::Result<::rusqlite::types::ToSqlOutput<'_>> {
+                #body
+            }
+        }
+    })
+}
+
+fn from_row(input: &DeriveInput) -> Result<TokenStream2> {
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    SqlAttrs::parse(&input.attrs, &[])?;
+    let fields = match input.data {
+        Data::Struct(ref data) => &data.fields,
+        _ => {
+            return Err(Error::new(
+                ident.span(),
+                "FromRow can only be derived for structs",
+            ))
+        }
+    };
+    let values = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let attrs = SqlAttrs::parse(&field.attrs, &["rename", "index", "default", "flatten"])?;
+            let ty = &field.ty;
+            if attrs.flatten {
+                if attrs.rename.is_some() || attrs.index.is_some() || attrs.default {
+                    return Err(Error::new_spanned(
+                        field,
+                        "`flatten` cannot be combined with other sql attributes",
+                    ));
+                }
+                return Ok(quote!(<#ty as ::rusqlite::FromRow>::from_row(row)?));
+            }
+            let column = match (attrs.index, attrs.rename, &field.ident) {
+                (Some(_), Some(_), _) => {
+                    return Err(Error::new_spanned(
+                        field,
+                        "`index` and `rename` are mutually exclusive",
+                    ))
+                }
+                (Some(index), None, _) => quote!(#index),
+                (None, Some(name), _) => quote!(#name),
+                (None, None, Some(ident)) => {
+                    let name = ident.to_string();
+                    let name = name.trim_start_matches("r#");
+                    quote!(#name)
+                }
+                (None, None, None) => quote!(#i),
+            };
+            let value = if attrs.default {
+                quote! {
+                    match row.get::<_, #ty>(#column) {
+                        Ok(value) => value,
+                        Err(::rusqlite::Error::InvalidColumnName(_))
+                        | Err(::rusqlite::Error::InvalidColumnIndex(_)) => {
+                            ::std::default::Default::default()
+                        }
+                        Err(err) => return Err(err),
+                    }
+                }
+            } else {
+                quote!(row.get::<_, #ty>(#column)?)
+            };
+            Ok(value)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let constructor = match fields {
+        Fields::Named(_) => {
+            let names = fields.iter().map(|field| &field.ident);
+            quote!(#ident { #(#names: #values,)* })
+        }
+        Fields::Unnamed(_) => quote!(#ident(#(#values,)*)),
+        Fields::Unit => quote!(#ident),
+    };
+    Ok(quote! {
+        impl #impl_generics ::rusqlite::FromRow for #ident #ty_generics #where_clause {
+            fn from_row(row: &::rusqlite::Row<'_>) -> ::rusqlite::Result<Self> {
+                Ok(#constructor)
+            }
+        }
+    })
+}
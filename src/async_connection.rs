@@ -0,0 +1,370 @@
+//! `feature = "async"` Async access to a [`Connection`] owned by a dedicated
+//! worker thread.
+//!
+//! [`AsyncConnection`] moves a [`Connection`] to a background thread and
+//! runs closures on it one at a time, in submission order. Each call returns
+//! a [`CallFuture`] which completes when the closure has run. The futures only
+//! rely on [`Waker`]s, so they can be awaited from any executor.
+//!
+//! Dropping a [`CallFuture`] before it completes cancels the call: if it has
+//! not started yet, it is skipped, otherwise the statement currently
+//! executing is interrupted with [`InterruptHandle`].
+//!
+//! ```rust,no_run
+//! # use rusqlite::{async_connection::AsyncConnection, Result};
+//! async fn count_people(db: &AsyncConnection) -> Result<i64> {
+//!     db.execute("INSERT INTO people (name) VALUES (?)", ["Bob"]).await?;
+//!     db.call(|conn| conn.query_row("SELECT count(*) FROM people", [], |r| r.get(0)))
+//!         .await
+//! }
+//! ```
+use std::fmt;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::error::error_from_sqlite_code;
+use crate::ffi;
+use crate::{Connection, InterruptHandle, OpenFlags, Params, Result, Row};
+
+type Job = Box<dyn FnOnce(&mut Connection) + Send>;
+
+/// `feature = "async"` Handle to a [`Connection`] running on its own thread.
+///
+/// The worker thread exits, closing the connection, once the
+/// `AsyncConnection` is dropped and all pending calls have run.
+pub struct AsyncConnection {
+    sender: Mutex<Sender<Job>>,
+    interrupt: Arc<InterruptHandle>,
+}
+
+impl AsyncConnection {
+    /// `feature = "async"` Open a new connection to a SQLite database on a
+    /// worker thread.
+    ///
+    /// See [`Connection::open`].
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `path` cannot be converted to a C-compatible
+    /// string or if the underlying SQLite open call fails.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<AsyncConnection> {
+        Connection::open(path).map(AsyncConnection::new)
+    }
+
+    /// `feature = "async"` Open a new connection to an in-memory SQLite
+    /// database on a worker thread.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite open call fails.
+    pub fn open_in_memory() -> Result<AsyncConnection> {
+        Connection::open_in_memory().map(AsyncConnection::new)
+    }
+
+    /// `feature = "async"` Open a new connection to a SQLite database on a
+    /// worker thread, with the given flags.
+    ///
+    /// See [`Connection::open_with_flags`].
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `path` cannot be converted to a C-compatible
+    /// string or if the underlying SQLite open call fails.
+    pub fn open_with_flags<P: AsRef<Path>>(path: P, flags: OpenFlags) -> Result<AsyncConnection> {
+        Connection::open_with_flags(path, flags).map(AsyncConnection::new)
+    }
+
+    /// `feature = "async"` Move an already opened connection to a new worker
+    /// thread.
+    pub fn new(conn: Connection) -> AsyncConnection {
+        let interrupt = Arc::new(conn.get_interrupt_handle());
+        let (sender, receiver) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            let mut conn = conn;
+            for job in receiver {
+                job(&mut conn);
+            }
+        });
+        AsyncConnection {
+            sender: Mutex::new(sender),
+            interrupt,
+        }
+    }
+
+    /// `feature = "async"` Run `f` with the connection on the worker thread.
+    ///
+    /// Calls are executed in the order they are made. If `f` panics, the
+    /// panic is propagated to the task awaiting the returned future.
+    pub fn call<F, R>(&self, f: F) -> CallFuture<R>
+    where
+        F: FnOnce(&mut Connection) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(State {
+            started: false,
+            cancelled: false,
+            result: None,
+            waker: None,
+        }));
+        let job_state = state.clone();
+        let job: Job = Box::new(move |conn| {
+            {
+                let mut state = job_state.lock().unwrap();
+                if state.cancelled {
+                    return;
+                }
+                state.started = true;
+            }
+            let result = panic::catch_unwind(AssertUnwindSafe(|| f(conn)));
+            let mut state = job_state.lock().unwrap();
+            state.result = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+        if self.sender.lock().unwrap().send(job).is_err() {
+            state.lock().unwrap().result = Some(Ok(Err(error_from_sqlite_code(
+                ffi::SQLITE_MISUSE,
+                Some("worker thread is gone".to_owned()),
+            ))));
+        }
+        CallFuture {
+            state,
+            interrupt: self.interrupt.clone(),
+            done: false,
+        }
+    }
+
+    /// `feature = "async"` Execute a single SQL statement on the worker
+    /// thread.
+    ///
+    /// See [`Connection::execute`]. `params` must be owned (e.g. `[1, 2]` or
+    /// [`params_from_iter`](crate::params_from_iter) over owned values); for
+    /// statements without parameters, use [`call`](AsyncConnection::call).
+    pub fn execute<S, P>(&self, sql: S, params: P) -> CallFuture<usize>
+    where
+        S: Into<String>,
+        P: Params + Send + 'static,
+    {
+        let sql = sql.into();
+        self.call(move |conn| conn.execute(&sql, params))
+    }
+
+    /// `feature = "async"` Execute a query expected to return a single row on
+    /// the worker thread, and map it with `f`.
+    ///
+    /// See [`Connection::query_row`]. `params` must be owned, like for
+    /// [`execute`](AsyncConnection::execute).
+    pub fn query_row<S, P, F, T>(&self, sql: S, params: P, f: F) -> CallFuture<T>
+    where
+        S: Into<String>,
+        P: Params + Send + 'static,
+        F: FnOnce(&Row<'_>) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let sql = sql.into();
+        self.call(move |conn| conn.query_row(&sql, params, f))
+    }
+
+    /// `feature = "async"` Get access to the interrupt handle of the
+    /// underlying connection.
+    pub fn get_interrupt_handle(&self) -> Arc<InterruptHandle> {
+        self.interrupt.clone()
+    }
+}
+
+impl fmt::Debug for AsyncConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncConnection").finish()
+    }
+}
+
+struct State<R> {
+    started: bool,
+    cancelled: bool,
+    result: Option<thread::Result<Result<R>>>,
+    waker: Option<Waker>,
+}
+
+/// `feature = "async"` Future returned by [`AsyncConnection::call`] and its
+/// convenience wrappers.
+///
+/// Dropping it before completion cancels the call.
+#[must_use = "dropping the future cancels the call"]
+pub struct CallFuture<R> {
+    state: Arc<Mutex<State<R>>>,
+    interrupt: Arc<InterruptHandle>,
+    done: bool,
+}
+
+impl<R> Future for CallFuture<R> {
+    type Output = Result<R>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<R>> {
+        let result = {
+            let mut state = self.state.lock().unwrap();
+            match state.result.take() {
+                Some(result) => result,
+                None => {
+                    state.waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            }
+        };
+        self.done = true;
+        match result {
+            Ok(result) => Poll::Ready(result),
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+impl<R> Drop for CallFuture<R> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        // Keep the lock while interrupting so that the call cannot complete
+        // and let the next one start in the meantime.
+        let mut state = self.state.lock().unwrap();
+        state.cancelled = true;
+        if state.started && state.result.is_none() {
+            self.interrupt.interrupt();
+        }
+    }
+}
+
+impl<R> fmt::Debug for CallFuture<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CallFuture")
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AsyncConnection;
+    use crate::{Connection, Error, ErrorCode, Result};
+    use std::future::Future;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread;
+    use std::time::Duration;
+
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    // Minimal executor: park the current thread until woken.
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = Box::pin(fut);
+        loop {
+            match fut.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_call() -> Result<()> {
+        let db = AsyncConnection::open_in_memory()?;
+        block_on(db.call(|conn| conn.execute_batch("CREATE TABLE foo(x INTEGER)")))?;
+        assert_eq!(1, block_on(db.execute("INSERT INTO foo VALUES (?)", [42]))?);
+        let x: i64 = block_on(db.query_row("SELECT x FROM foo WHERE x > ?", [0], |r| r.get(0)))?;
+        assert_eq!(42, x);
+        let err = block_on(db.execute("INSERT INTO bar VALUES (?)", [1])).unwrap_err();
+        assert!(matches!(err, Error::SqliteFailure(..)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_call_in_order() -> Result<()> {
+        let db = AsyncConnection::new(Connection::open_in_memory()?);
+        let create = db.call(|conn| conn.execute_batch("CREATE TABLE foo(x INTEGER)"));
+        let insert = db.execute("INSERT INTO foo VALUES (?)", [1]);
+        assert_eq!(1, block_on(insert)?);
+        block_on(create)?;
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "boom")]
+    fn test_call_panic() {
+        let db = AsyncConnection::open_in_memory().unwrap();
+        let _: Result<()> = block_on(db.call(|_| panic!("boom")));
+    }
+
+    #[test]
+    #[cfg(feature = "functions")]
+    fn test_drop_cancels() -> Result<()> {
+        let db = AsyncConnection::open_in_memory()?;
+        let (tx, rx) = std::sync::mpsc::channel();
+        let running = db.call(move |conn| {
+            conn.create_scalar_function(
+                "started",
+                0,
+                crate::functions::FunctionFlags::SQLITE_UTF8,
+                move |_| {
+                    let _ = tx.send(());
+                    Ok(0)
+                },
+            )?;
+            conn.query_row(
+                "WITH RECURSIVE c(x) AS (SELECT started() UNION ALL SELECT x + 1 FROM c) \
+                 SELECT max(x) FROM c",
+                [],
+                |r| r.get::<_, i64>(0),
+            )
+        });
+        // Queued behind the first call, and dropped before it starts.
+        drop(db.call(|conn| conn.execute_batch("CREATE TABLE skipped(x)")));
+
+        rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        drop(running);
+
+        let count: i64 = block_on(db.query_row(
+            "SELECT count(*) FROM sqlite_master WHERE name = ?",
+            ["skipped"],
+            |r| r.get(0),
+        ))?;
+        assert_eq!(0, count);
+        Ok(())
+    }
+
+    #[test]
+    fn test_interrupted_error() -> Result<()> {
+        let db = AsyncConnection::open_in_memory()?;
+        let handle = db.get_interrupt_handle();
+        let fut = db.call(|conn| {
+            conn.query_row(
+                "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c) \
+                 SELECT max(x) FROM c",
+                [],
+                |r| r.get::<_, i64>(0),
+            )
+        });
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            handle.interrupt();
+        });
+        match block_on(fut).unwrap_err() {
+            Error::SqliteFailure(err, _) => assert_eq!(ErrorCode::OperationInterrupted, err.code),
+            err => panic!("unexpected error {}", err),
+        }
+        Ok(())
+    }
+}
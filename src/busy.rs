@@ -1,14 +1,223 @@
-///! Busy handler (when the database is locked)
+//! Busy handler (when the database is locked)
+//!
+//! Besides [`Connection::busy_timeout`] and [`Connection::busy_handler`], a
+//! [`BusyPolicy`] (like a closure, or a [`Backoff`]) can be installed with
+//! [`Connection::busy_policy`]:
+//!
+//! ```rust,no_run
+//! use rusqlite::busy::{Backoff, BusyPolicy};
+//! use rusqlite::{Connection, Result};
+//! use std::time::Duration;
+//!
+//! fn open(path: &str) -> Result<Connection> {
+//!     let db = Connection::open(path)?;
+//!     let policy = Backoff::new(Duration::from_millis(1), Duration::from_millis(100))
+//!         .capped(Duration::from_secs(5));
+//!     db.busy_policy(Some(policy))?;
+//!     Ok(db)
+//! }
+//! ```
 use std::convert::TryInto;
 use std::mem;
 use std::os::raw::{c_int, c_void};
-use std::panic::catch_unwind;
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::ptr;
-use std::time::Duration;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use crate::ffi;
 use crate::{Connection, InnerConnection, Result};
 
+/// Decides whether to retry an operation on a locked database, and how long
+/// to wait before doing so.
+///
+/// Implemented for closures taking the number of times the policy has been
+/// invoked previously for the same locking event.
+pub trait BusyPolicy: Send + 'static {
+    /// Called when the database is locked, with the number of times the
+    /// policy has been called previously for the same locking event. Returns
+    /// `true` to make another attempt (after sleeping as needed), `false` to
+    /// give up with `SQLITE_BUSY`.
+    fn retry(&mut self, count: i32) -> bool;
+
+    /// Give up once `max_total` has elapsed since the first retry of a
+    /// locking event.
+    #[inline]
+    fn capped(self, max_total: Duration) -> Capped<Self>
+    where
+        Self: Sized,
+    {
+        Capped {
+            policy: self,
+            max_total,
+            start: None,
+        }
+    }
+
+    /// `feature = "log"` or `feature = "tracing"` Log each retry (at debug
+    /// level) and when the policy gives up (at warn level), under the
+    /// `rusqlite` target.
+    #[cfg(any(feature = "log", feature = "tracing"))]
+    #[inline]
+    fn logged(self) -> Logged<Self>
+    where
+        Self: Sized,
+    {
+        Logged { policy: self }
+    }
+}
+
+impl<F> BusyPolicy for F
+where
+    F: FnMut(i32) -> bool + Send + 'static,
+{
+    #[inline]
+    fn retry(&mut self, count: i32) -> bool {
+        self(count)
+    }
+}
+
+/// Exponential backoff: sleeps `initial`, then twice as long at each retry,
+/// up to `max_delay` per retry. With jitter (the default), each delay is
+/// picked at random between half and all of it, so that concurrent writers
+/// don't retry in lockstep.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    initial: Duration,
+    max_delay: Duration,
+    max_retries: Option<i32>,
+    jitter: bool,
+    seed: u64,
+}
+
+impl Backoff {
+    /// Backoff starting at `initial`, capped at `max_delay` per retry, with
+    /// jitter and no limit on the number of retries.
+    pub fn new(initial: Duration, max_delay: Duration) -> Backoff {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+        Backoff {
+            initial,
+            max_delay,
+            max_retries: None,
+            jitter: true,
+            // must not be 0
+            seed: u64::from(nanos) | 1,
+        }
+    }
+
+    /// Give up after `n` retries.
+    #[inline]
+    pub fn max_retries(mut self, n: i32) -> Backoff {
+        self.max_retries = Some(n);
+        self
+    }
+
+    /// Enable or disable jitter.
+    #[inline]
+    pub fn jitter(mut self, jitter: bool) -> Backoff {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Returns the delay before retry number `count` (starting at 0), or
+    /// `None` if the policy gives up.
+    pub fn delay(&mut self, count: i32) -> Option<Duration> {
+        if matches!(self.max_retries, Some(max) if count >= max) {
+            return None;
+        }
+        let factor = 1u32.checked_shl(count.max(0) as u32).unwrap_or(u32::MAX);
+        let delay = self
+            .initial
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay));
+        if self.jitter {
+            Some(delay.mul_f64(0.5 + 0.5 * self.random()))
+        } else {
+            Some(delay)
+        }
+    }
+
+    // xorshift64*, in [0, 1)
+    fn random(&mut self) -> f64 {
+        self.seed ^= self.seed >> 12;
+        self.seed ^= self.seed << 25;
+        self.seed ^= self.seed >> 27;
+        let r = self.seed.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        (r >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl BusyPolicy for Backoff {
+    fn retry(&mut self, count: i32) -> bool {
+        match self.delay(count) {
+            Some(delay) => {
+                thread::sleep(delay);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A policy which gives up once a total wait has elapsed. See
+/// [`BusyPolicy::capped`].
+#[derive(Clone, Debug)]
+pub struct Capped<P> {
+    policy: P,
+    max_total: Duration,
+    start: Option<Instant>,
+}
+
+impl<P: BusyPolicy> BusyPolicy for Capped<P> {
+    fn retry(&mut self, count: i32) -> bool {
+        let now = Instant::now();
+        let start = match self.start {
+            Some(start) if count > 0 => start,
+            _ => {
+                self.start = Some(now);
+                now
+            }
+        };
+        now.duration_since(start) < self.max_total && self.policy.retry(count)
+    }
+}
+
+/// `feature = "log"` or `feature = "tracing"` A policy which logs its
+/// decisions. See [`BusyPolicy::logged`].
+#[cfg(any(feature = "log", feature = "tracing"))]
+#[derive(Clone, Debug)]
+pub struct Logged<P> {
+    policy: P,
+}
+
+#[cfg(any(feature = "log", feature = "tracing"))]
+impl<P: BusyPolicy> BusyPolicy for Logged<P> {
+    fn retry(&mut self, count: i32) -> bool {
+        let start = Instant::now();
+        let retry = self.policy.retry(count);
+        if retry {
+            let waited = start.elapsed();
+            #[cfg(feature = "log")]
+            log::debug!(target: "rusqlite", "database is locked, retry {} after {:?}", count + 1, waited);
+            #[cfg(feature = "tracing")]
+            tracing::debug!(target: "rusqlite", retry = count + 1, ?waited, "database is locked");
+        } else {
+            #[cfg(feature = "log")]
+            log::warn!(target: "rusqlite", "database is locked, giving up after {} retries", count);
+            #[cfg(feature = "tracing")]
+            tracing::warn!(target: "rusqlite", retries = count, "database is locked, giving up");
+        }
+        retry
+    }
+}
+
+pub(crate) struct BusyState {
+    policy: Box<dyn BusyPolicy>,
+    retries: u64,
+}
+
 impl Connection {
     /// Set a busy handler that sleeps for a specified amount of time when a
     /// table is locked. The handler will sleep multiple times until at
@@ -70,16 +279,79 @@ impl Connection {
             },
             None => unsafe { ffi::sqlite3_busy_handler(c.db(), None, ptr::null_mut()) },
         };
+        c.busy = None;
+        c.decode_result(r)
+    }
+
+    /// Register a [`BusyPolicy`] (like a closure or a [`Backoff`]) to handle
+    /// `SQLITE_BUSY` errors. See [`busy_handler`](Connection::busy_handler).
+    ///
+    /// If the policy panics, no additional attempt is made.
+    pub fn busy_policy<P: BusyPolicy>(&self, policy: Option<P>) -> Result<()> {
+        unsafe extern "C" fn call_busy_policy(p_arg: *mut c_void, count: c_int) -> c_int {
+            let state = &mut *(p_arg as *mut BusyState);
+            let policy = &mut state.policy;
+            if let Ok(true) = catch_unwind(AssertUnwindSafe(|| policy.retry(count))) {
+                state.retries += 1;
+                1
+            } else {
+                0
+            }
+        }
+        let mut c = self.db.borrow_mut();
+        let r = match policy {
+            Some(policy) => {
+                let mut state = Box::new(BusyState {
+                    policy: Box::new(policy),
+                    retries: c.busy.as_ref().map_or(0, |state| state.retries),
+                });
+                let r = unsafe {
+                    ffi::sqlite3_busy_handler(
+                        c.db(),
+                        Some(call_busy_policy),
+                        &mut *state as *mut BusyState as *mut c_void,
+                    )
+                };
+                c.busy = Some(state);
+                r
+            }
+            None => {
+                let r = unsafe { ffi::sqlite3_busy_handler(c.db(), None, ptr::null_mut()) };
+                c.busy = None;
+                r
+            }
+        };
         c.decode_result(r)
     }
+
+    /// Returns the number of retries granted by the [`BusyPolicy`] installed
+    /// with [`busy_policy`](Connection::busy_policy) (across policies, until
+    /// another kind of busy handler is set).
+    #[inline]
+    pub fn busy_retries(&self) -> u64 {
+        self.db
+            .borrow()
+            .busy
+            .as_ref()
+            .map_or(0, |state| state.retries)
+    }
 }
 
 impl InnerConnection {
     #[inline]
     fn busy_timeout(&mut self, timeout: c_int) -> Result<()> {
         let r = unsafe { ffi::sqlite3_busy_timeout(self.db, timeout) };
+        self.busy = None;
         self.decode_result(r)
     }
+
+    /// Unregister the busy policy, if any, before it is dropped while the
+    /// connection stays open.
+    pub(crate) fn remove_busy_policy(&mut self) {
+        if self.busy.take().is_some() {
+            unsafe { ffi::sqlite3_busy_handler(self.db, None, ptr::null_mut()) };
+        }
+    }
 }
 
 #[cfg(test)]
@@ -87,10 +359,20 @@ mod test {
     use std::sync::atomic::{AtomicBool, Ordering};
     use std::sync::mpsc::sync_channel;
     use std::thread;
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
 
+    use super::{Backoff, BusyPolicy};
     use crate::{Connection, Error, ErrorCode, Result, TransactionBehavior};
 
+    fn assert_busy<T: std::fmt::Debug>(r: Result<T>) {
+        match r.unwrap_err() {
+            Error::SqliteFailure(err, _) => {
+                assert_eq!(err.code, ErrorCode::DatabaseBusy);
+            }
+            err => panic!("Unexpected error {}", err),
+        }
+    }
+
     #[test]
     fn test_default_busy() -> Result<()> {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -172,4 +454,109 @@ mod test {
 
         child.join().unwrap();
     }
+
+    #[test]
+    fn test_busy_policy_closure() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+
+        let mut db1 = Connection::open(&path)?;
+        let tx1 = db1.transaction_with_behavior(TransactionBehavior::Exclusive)?;
+        let db2 = Connection::open(&path)?;
+        let mut counts = Vec::new();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        db2.busy_policy(Some(move |count| {
+            counts.push(count);
+            sender.send(counts.clone()).unwrap();
+            count < 2
+        }))?;
+        assert_busy(db2.query_row("PRAGMA schema_version", [], |r| r.get::<_, i32>(0)));
+        assert_eq!(vec![0, 1, 2], receiver.try_iter().last().unwrap());
+        assert_eq!(2, db2.busy_retries());
+
+        assert_busy(db2.query_row("PRAGMA schema_version", [], |r| r.get::<_, i32>(0)));
+        assert_eq!(4, db2.busy_retries());
+
+        db2.busy_timeout(Duration::from_millis(0))?;
+        assert_eq!(0, db2.busy_retries());
+        tx1.rollback()
+    }
+
+    #[test]
+    fn test_backoff_delays() {
+        let mut backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(50))
+            .jitter(false)
+            .max_retries(5);
+        let delays: Vec<_> = (0..6).map(|count| backoff.delay(count)).collect();
+        assert_eq!(
+            vec![
+                Some(Duration::from_millis(10)),
+                Some(Duration::from_millis(20)),
+                Some(Duration::from_millis(40)),
+                Some(Duration::from_millis(50)),
+                Some(Duration::from_millis(50)),
+                None,
+            ],
+            delays
+        );
+        assert_eq!(
+            Some(Duration::from_millis(50)),
+            backoff.jitter(false).max_retries(100).delay(64)
+        );
+
+        let mut backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(50));
+        for count in 0..100 {
+            let delay = backoff.delay(count).unwrap();
+            let max = Duration::from_millis(10 << count.min(3)).min(Duration::from_millis(50));
+            assert!(delay >= max / 2 && delay <= max, "{:?} {:?}", delay, max);
+        }
+    }
+
+    #[test]
+    fn test_backoff_capped() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+
+        let mut db1 = Connection::open(&path)?;
+        let tx1 = db1.transaction_with_behavior(TransactionBehavior::Exclusive)?;
+        let db2 = Connection::open(&path)?;
+        let policy = Backoff::new(Duration::from_millis(1), Duration::from_millis(5))
+            .capped(Duration::from_millis(50));
+        db2.busy_policy(Some(policy))?;
+        let start = Instant::now();
+        assert_busy(db2.query_row("PRAGMA schema_version", [], |r| r.get::<_, i32>(0)));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(db2.busy_retries() >= 5);
+        tx1.rollback()
+    }
+
+    #[test]
+    fn test_backoff_waits_for_lock() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+
+        let db2 = Connection::open(&path).unwrap();
+        let policy = Backoff::new(Duration::from_millis(1), Duration::from_millis(10))
+            .capped(Duration::from_secs(10));
+        db2.busy_policy(Some(policy)).unwrap();
+
+        let (rx, tx) = sync_channel(0);
+        let child = thread::spawn(move || {
+            let mut db1 = Connection::open(&path).unwrap();
+            let tx1 = db1
+                .transaction_with_behavior(TransactionBehavior::Exclusive)
+                .unwrap();
+            rx.send(1).unwrap();
+            thread::sleep(Duration::from_millis(100));
+            tx1.rollback().unwrap();
+        });
+
+        assert_eq!(tx.recv().unwrap(), 1);
+        db2.query_row("PRAGMA schema_version", [], |row| row.get::<_, i32>(0))
+            .unwrap();
+        assert!(db2.busy_retries() > 0);
+
+        child.join().unwrap();
+    }
 }
@@ -0,0 +1,270 @@
+//! `feature = "statement_checker"` Report statements doing full scans, sorts
+//! or automatic indexes.
+//!
+//! Once a checker is installed, the `FullscanStep`, `Sort` and `AutoIndex`
+//! [status counters](crate::StatementStatus) of each execution of a
+//! statement are compared against [`Thresholds`] when the execution completes
+//! (`execute` returns or the rows are exhausted or dropped), and the
+//! statements reaching any threshold are reported to a callback. This is meant
+//! for test and debug builds, to catch missing indexes early.
+//!
+//! ```rust
+//! use rusqlite::checker::Thresholds;
+//! use rusqlite::{Connection, Result};
+//! use std::sync::{Arc, Mutex};
+//!
+//! fn check() -> Result<()> {
+//!     let db = Connection::open_in_memory()?;
+//!     let reported = Arc::new(Mutex::new(Vec::new()));
+//!     let r = reported.clone();
+//!     db.statement_checker(
+//!         Thresholds::default().fullscan_step(1),
+//!         Some(move |report: &rusqlite::checker::Report<'_>| {
+//!             r.lock().unwrap().push(report.sql().to_owned())
+//!         }),
+//!     );
+//!     db.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES (1), (2);")?;
+//!     db.query_row("SELECT COUNT(*) FROM foo WHERE x = 1", [], |r| r.get::<_, i64>(0))?;
+//!     assert_eq!(1, reported.lock().unwrap().len());
+//!     Ok(())
+//! }
+//! ```
+use crate::{Connection, Statement, StatementStatus};
+
+/// `feature = "statement_checker"` Counter values from which a statement
+/// execution is reported. A threshold which is not set is not checked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Thresholds {
+    fullscan_step: Option<i32>,
+    sort: Option<i32>,
+    auto_index: Option<i32>,
+}
+
+impl Thresholds {
+    /// Report executions with at least `n` steps of full table scans.
+    #[inline]
+    pub fn fullscan_step(mut self, n: i32) -> Thresholds {
+        self.fullscan_step = Some(n);
+        self
+    }
+
+    /// Report executions with at least `n` sort operations.
+    #[inline]
+    pub fn sort(mut self, n: i32) -> Thresholds {
+        self.sort = Some(n);
+        self
+    }
+
+    /// Report executions inserting at least `n` rows into automatic indexes.
+    #[inline]
+    pub fn auto_index(mut self, n: i32) -> Thresholds {
+        self.auto_index = Some(n);
+        self
+    }
+
+    fn reached(&self, counts: &Counts) -> bool {
+        let reached = |threshold: Option<i32>, count| matches!(threshold, Some(t) if count >= t);
+        reached(self.fullscan_step, counts.fullscan_step)
+            || reached(self.sort, counts.sort)
+            || reached(self.auto_index, counts.auto_index)
+    }
+}
+
+/// `feature = "statement_checker"` A statement execution which reached one of
+/// the [`Thresholds`].
+#[derive(Debug)]
+pub struct Report<'s> {
+    sql: &'s str,
+    counts: Counts,
+}
+
+impl Report<'_> {
+    /// Returns the SQL text of the statement.
+    #[inline]
+    pub fn sql(&self) -> &str {
+        self.sql
+    }
+
+    /// Returns the number of full table scan steps of the execution.
+    #[inline]
+    pub fn fullscan_step(&self) -> i32 {
+        self.counts.fullscan_step
+    }
+
+    /// Returns the number of sort operations of the execution.
+    #[inline]
+    pub fn sort(&self) -> i32 {
+        self.counts.sort
+    }
+
+    /// Returns the number of rows inserted into automatic indexes by the
+    /// execution.
+    #[inline]
+    pub fn auto_index(&self) -> i32 {
+        self.counts.auto_index
+    }
+}
+
+pub(crate) struct StatementChecker {
+    thresholds: Thresholds,
+    callback: Box<dyn FnMut(&Report<'_>) + Send>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Counts {
+    fullscan_step: i32,
+    sort: i32,
+    auto_index: i32,
+}
+
+impl Counts {
+    /// Counters at the start of an execution, if a checker is installed.
+    pub(crate) fn start(stmt: &Statement<'_>) -> Option<Counts> {
+        if stmt.conn.db.borrow().statement_checker.is_some() {
+            Some(Counts::get(stmt))
+        } else {
+            None
+        }
+    }
+
+    fn get(stmt: &Statement<'_>) -> Counts {
+        Counts {
+            fullscan_step: stmt.get_status(StatementStatus::FullscanStep),
+            sort: stmt.get_status(StatementStatus::Sort),
+            auto_index: stmt.get_status(StatementStatus::AutoIndex),
+        }
+    }
+
+    /// Check the counters of the execution which started at `self`.
+    pub(crate) fn finish(self, stmt: &Statement<'_>) {
+        let end = Counts::get(stmt);
+        let counts = Counts {
+            fullscan_step: end.fullscan_step - self.fullscan_step,
+            sort: end.sort - self.sort,
+            auto_index: end.auto_index - self.auto_index,
+        };
+        // The checker is moved out so that the connection is not borrowed
+        // while the callback runs.
+        let mut checker = match stmt.conn.db.borrow_mut().statement_checker.take() {
+            Some(checker) => checker,
+            None => return,
+        };
+        if checker.thresholds.reached(&counts) {
+            let sql = stmt
+                .stmt
+                .sql()
+                .map_or(String::new(), |sql| sql.to_string_lossy().into_owned());
+            (checker.callback)(&Report { sql: &sql, counts });
+        }
+        let mut c = stmt.conn.db.borrow_mut();
+        if c.statement_checker.is_none() {
+            c.statement_checker = Some(checker);
+        }
+    }
+}
+
+impl Connection {
+    /// `feature = "statement_checker"` Install (or remove, with `None`) a
+    /// callback reporting the statement executions which reach any of the
+    /// `thresholds`.
+    ///
+    /// The callback must not use this connection.
+    pub fn statement_checker<F>(&self, thresholds: Thresholds, callback: Option<F>)
+    where
+        F: FnMut(&Report<'_>) + Send + 'static,
+    {
+        self.db.borrow_mut().statement_checker = callback.map(|callback| StatementChecker {
+            thresholds,
+            callback: Box::new(callback),
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::{Report, Thresholds};
+    use crate::{Connection, Result};
+
+    type Reports = Arc<Mutex<Vec<(String, i32, i32, i32)>>>;
+
+    fn checked(thresholds: Thresholds) -> Result<(Connection, Reports)> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE foo(x INTEGER, y TEXT);
+             CREATE INDEX foo_x ON foo(x);
+             CREATE TABLE bar(x INTEGER, z TEXT);
+             INSERT INTO foo VALUES (1, 'a'), (2, 'b'), (3, 'c');
+             INSERT INTO bar VALUES (1, 'a'), (2, 'b'), (3, 'c');",
+        )?;
+        let reports = Reports::default();
+        let r = reports.clone();
+        db.statement_checker(
+            thresholds,
+            Some(move |report: &Report<'_>| {
+                r.lock().unwrap().push((
+                    report.sql().to_owned(),
+                    report.fullscan_step(),
+                    report.sort(),
+                    report.auto_index(),
+                ))
+            }),
+        );
+        Ok((db, reports))
+    }
+
+    #[test]
+    fn test_fullscan() -> Result<()> {
+        let (db, reports) = checked(Thresholds::default().fullscan_step(2))?;
+        let mut stmt = db.prepare("SELECT * FROM foo WHERE y = ?")?;
+        assert_eq!(1, stmt.query_map(["a"], |_| Ok(()))?.count());
+        let mut stmt = db.prepare("SELECT * FROM foo WHERE x = ?")?;
+        assert_eq!(1, stmt.query_map([1], |_| Ok(()))?.count());
+        db.execute("UPDATE foo SET y = 'd' WHERE y = 'c'", [])?;
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(2, reports.len());
+        assert_eq!("SELECT * FROM foo WHERE y = ?", reports[0].0);
+        assert_eq!(2, reports[0].1);
+        assert_eq!("UPDATE foo SET y = 'd' WHERE y = 'c'", reports[1].0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_per_execution() -> Result<()> {
+        let (db, reports) = checked(Thresholds::default().fullscan_step(2))?;
+        let mut stmt = db.prepare("SELECT * FROM foo")?;
+        for _ in 0..3 {
+            // dropped before exhausting the rows
+            let mut rows = stmt.query([])?;
+            rows.next()?;
+        }
+        assert!(reports.lock().unwrap().is_empty());
+        assert_eq!(3, stmt.query_map([], |_| Ok(()))?.count());
+        assert_eq!(1, reports.lock().unwrap().len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_and_auto_index() -> Result<()> {
+        let (db, reports) = checked(Thresholds::default().sort(1).auto_index(1))?;
+        let mut stmt = db.prepare("SELECT * FROM foo ORDER BY y")?;
+        assert_eq!(3, stmt.query_map([], |_| Ok(()))?.count());
+        let mut stmt = db.prepare("SELECT * FROM foo, bar WHERE foo.y = bar.z")?;
+        assert_eq!(3, stmt.query_map([], |_| Ok(()))?.count());
+
+        {
+            let reports = reports.lock().unwrap();
+            assert_eq!(2, reports.len());
+            assert_eq!(1, reports[0].2);
+            assert_eq!(0, reports[0].3);
+            assert!(reports[1].3 > 0);
+        }
+
+        db.statement_checker(Thresholds::default(), None::<fn(&Report<'_>)>);
+        assert_eq!(3, stmt.query_map([], |_| Ok(()))?.count());
+        assert_eq!(2, reports.lock().unwrap().len());
+        Ok(())
+    }
+}
@@ -1,5 +1,14 @@
 use std::str;
 
+#[cfg(feature = "column_metadata")]
+use std::ffi::CStr;
+#[cfg(feature = "column_metadata")]
+use std::os::raw::c_char;
+#[cfg(feature = "column_metadata")]
+use std::ptr;
+
+#[cfg(feature = "column_metadata")]
+use crate::{ffi, str_to_cstring, Connection, DatabaseName};
 use crate::{Error, Result, Row, Rows, Statement};
 
 /// Information about a column of a SQLite query.
@@ -7,6 +16,12 @@ use crate::{Error, Result, Row, Rows, Statement};
 pub struct Column<'stmt> {
     name: &'stmt str,
     decl_type: Option<&'stmt str>,
+    #[cfg(feature = "column_metadata")]
+    database_name: Option<&'stmt str>,
+    #[cfg(feature = "column_metadata")]
+    table_name: Option<&'stmt str>,
+    #[cfg(feature = "column_metadata")]
+    origin_name: Option<&'stmt str>,
 }
 
 impl Column<'_> {
@@ -21,6 +36,134 @@ impl Column<'_> {
     pub fn decl_type(&self) -> Option<&str> {
         self.decl_type
     }
+
+    /// `feature = "column_metadata"` Returns the name of the database (`main`,
+    /// `temp` or the attached name) the column is taken from (`None` for
+    /// expression).
+    #[cfg(feature = "column_metadata")]
+    #[inline]
+    pub fn database_name(&self) -> Option<&str> {
+        self.database_name
+    }
+
+    /// `feature = "column_metadata"` Returns the name of the table the column
+    /// is taken from (`None` for expression).
+    #[cfg(feature = "column_metadata")]
+    #[inline]
+    pub fn table_name(&self) -> Option<&str> {
+        self.table_name
+    }
+
+    /// `feature = "column_metadata"` Returns the name of the table column the
+    /// column is taken from, regardless of any `AS` clause (`None` for
+    /// expression).
+    #[cfg(feature = "column_metadata")]
+    #[inline]
+    pub fn origin_name(&self) -> Option<&str> {
+        self.origin_name
+    }
+}
+
+/// `feature = "column_metadata"` Metadata about a column of a table, as
+/// returned by [`Connection::column_metadata`].
+#[cfg(feature = "column_metadata")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColumnMetadata {
+    decl_type: Option<String>,
+    collation: Option<String>,
+    not_null: bool,
+    primary_key: bool,
+    auto_increment: bool,
+}
+
+#[cfg(feature = "column_metadata")]
+impl ColumnMetadata {
+    /// Returns the declared type of the column.
+    #[inline]
+    pub fn decl_type(&self) -> Option<&str> {
+        self.decl_type.as_deref()
+    }
+
+    /// Returns the name of the default collation sequence of the column.
+    #[inline]
+    pub fn collation(&self) -> Option<&str> {
+        self.collation.as_deref()
+    }
+
+    /// Returns `true` if the column has a NOT NULL constraint.
+    #[inline]
+    pub fn not_null(&self) -> bool {
+        self.not_null
+    }
+
+    /// Returns `true` if the column is part of the primary key.
+    #[inline]
+    pub fn primary_key(&self) -> bool {
+        self.primary_key
+    }
+
+    /// Returns `true` if the column is AUTOINCREMENT.
+    #[inline]
+    pub fn auto_increment(&self) -> bool {
+        self.auto_increment
+    }
+}
+
+#[cfg(feature = "column_metadata")]
+impl Connection {
+    /// `feature = "column_metadata"` Returns metadata about the `column` of
+    /// `table`, in `db_name` or, if `None`, in the first database (in search
+    /// order) which has such a table.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the table or the column does not exist.
+    pub fn column_metadata(
+        &self,
+        db_name: Option<DatabaseName<'_>>,
+        table: &str,
+        column: &str,
+    ) -> Result<ColumnMetadata> {
+        let db_name = db_name.map(|db_name| db_name.to_cstring()).transpose()?;
+        let table = str_to_cstring(table)?;
+        let column = str_to_cstring(column)?;
+        let mut decl_type: *const c_char = ptr::null();
+        let mut collation: *const c_char = ptr::null();
+        let mut not_null = 0;
+        let mut primary_key = 0;
+        let mut auto_increment = 0;
+        let mut c = self.db.borrow_mut();
+        let r = unsafe {
+            ffi::sqlite3_table_column_metadata(
+                c.db(),
+                db_name
+                    .as_ref()
+                    .map_or(ptr::null(), |db_name| db_name.as_ptr()),
+                table.as_ptr(),
+                column.as_ptr(),
+                &mut decl_type,
+                &mut collation,
+                &mut not_null,
+                &mut primary_key,
+                &mut auto_increment,
+            )
+        };
+        c.decode_result(r)?;
+        let to_string = |s: *const c_char| {
+            if s.is_null() {
+                None
+            } else {
+                Some(unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned())
+            }
+        };
+        Ok(ColumnMetadata {
+            decl_type: to_string(decl_type),
+            collation: to_string(collation),
+            not_null: not_null != 0,
+            primary_key: primary_key != 0,
+            auto_increment: auto_increment != 0,
+        })
+    }
 }
 
 impl Statement<'_> {
@@ -102,12 +245,27 @@ impl Statement<'_> {
             let decl_type = slice.map(|s| {
                 str::from_utf8(s.to_bytes()).expect("Invalid UTF-8 sequence in column declaration")
             });
-            cols.push(Column { name, decl_type });
+            cols.push(Column {
+                name,
+                decl_type,
+                #[cfg(feature = "column_metadata")]
+                database_name: metadata_name(self.stmt.column_database_name(i)),
+                #[cfg(feature = "column_metadata")]
+                table_name: metadata_name(self.stmt.column_table_name(i)),
+                #[cfg(feature = "column_metadata")]
+                origin_name: metadata_name(self.stmt.column_origin_name(i)),
+            });
         }
         cols
     }
 }
 
+#[cfg(feature = "column_metadata")]
+#[inline]
+fn metadata_name(s: Option<&CStr>) -> Option<&str> {
+    s.map(|s| str::from_utf8(s.to_bytes()).expect("Invalid UTF-8 sequence in column metadata"))
+}
+
 impl<'stmt> Rows<'stmt> {
     /// Get all the column names.
     #[inline]
@@ -199,6 +357,49 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    #[cfg(feature = "column_metadata")]
+    fn test_columns_origin() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(x INTEGER, y TEXT)")?;
+        let query = db.prepare("SELECT x AS renamed, y, 1 + 1 FROM foo")?;
+        let columns = query.columns();
+        assert_eq!(Some("main"), columns[0].database_name());
+        assert_eq!(Some("foo"), columns[0].table_name());
+        assert_eq!(Some("x"), columns[0].origin_name());
+        assert_eq!("renamed", columns[0].name());
+        assert_eq!(Some("y"), columns[1].origin_name());
+        assert_eq!(None, columns[2].table_name());
+        assert_eq!(None, columns[2].origin_name());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "column_metadata")]
+    fn test_column_metadata() -> Result<()> {
+        use crate::MAIN_DB;
+
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE foo(id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL COLLATE NOCASE)",
+        )?;
+        let id = db.column_metadata(Some(MAIN_DB), "foo", "id")?;
+        assert_eq!(Some("INTEGER"), id.decl_type());
+        assert!(id.primary_key());
+        assert!(id.auto_increment());
+        assert!(!id.not_null());
+
+        let name = db.column_metadata(None, "foo", "name")?;
+        assert_eq!(Some("TEXT"), name.decl_type());
+        assert_eq!(Some("NOCASE"), name.collation());
+        assert!(name.not_null());
+        assert!(!name.primary_key());
+
+        assert!(db.column_metadata(None, "foo", "bar").is_err());
+        assert!(db.column_metadata(None, "bar", "id").is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_column_name_in_error() -> Result<()> {
         use crate::{types::Type, Error};
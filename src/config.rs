@@ -7,6 +7,7 @@ use crate::{Connection, Result};
 
 /// Database Connection Configuration Options
 /// See [Database Connection Configuration Options](https://sqlite.org/c3ref/c_dbconfig_enable_fkey.html) for details.
+#[derive(Clone, Copy, Debug)]
 #[repr(i32)]
 #[allow(non_snake_case, non_camel_case_types)]
 #[non_exhaustive]
@@ -0,0 +1,638 @@
+//! `feature = "serde"` Deserialize rows into Rust types.
+//!
+//! Struct fields (and map keys) are matched against
+//! [`column_names`](Statement::column_names), whereas tuples and sequences
+//! take the columns in order. A single-column row can also be deserialized
+//! directly into its column's type.
+use std::cell::Cell;
+use std::error;
+use std::fmt;
+use std::str;
+
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, SeqAccess,
+    Visitor,
+};
+use serde::{forward_to_deserialize_any, Deserialize};
+
+use crate::types::{Type, ValueRef};
+use crate::{Connection, Error, MappedRows, Params, Result, Row, Statement};
+
+impl Row<'_> {
+    /// `feature = "serde"` Deserialize the current row into `T`.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// #[derive(serde::Deserialize)]
+    /// struct Person {
+    ///     name: String,
+    ///     age: Option<u8>,
+    /// }
+    ///
+    /// fn people(conn: &Connection) -> Result<Vec<Person>> {
+    ///     let mut stmt = conn.prepare("SELECT name, age FROM person")?;
+    ///     let mut rows = stmt.query([])?;
+    ///     let mut people = Vec::new();
+    ///     while let Some(row) = rows.next()? {
+    ///         people.push(row.deserialize()?);
+    ///     }
+    ///     Ok(people)
+    /// }
+    /// ```
+    ///
+    /// ## Failure
+    ///
+    /// Returns an `Error::FromSqlConversionFailure` with the index and the
+    /// type of the column that cannot be converted, an
+    /// `Error::InvalidColumnName` if a struct field has no matching column,
+    /// or an `Error::InvalidColumnIndex` if there are not enough columns for
+    /// a tuple.
+    pub fn deserialize<'de, T: Deserialize<'de>>(&'de self) -> Result<T> {
+        let pos = Cell::new(0);
+        T::deserialize(RowDeserializer {
+            row: self,
+            pos: &pos,
+        })
+        .map_err(|err| err.into_error(self, pos.get()))
+    }
+}
+
+/// `feature = "serde"` An iterator over the rows of a query, deserialized
+/// by [`Statement::query_as`].
+pub type DeserializedRows<'stmt, T> = MappedRows<'stmt, fn(&Row<'_>) -> Result<T>>;
+
+impl Statement<'_> {
+    /// `feature = "serde"` Execute the prepared statement, returning an
+    /// iterator over the rows deserialized into `T`.
+    ///
+    /// See [`Row::deserialize`] for how columns are mapped onto `T`.
+    ///
+    /// ## Failure
+    ///
+    /// Will return `Err` if binding parameters fails.
+    pub fn query_as<T, P>(&mut self, params: P) -> Result<DeserializedRows<'_, T>>
+    where
+        T: DeserializeOwned,
+        P: Params,
+    {
+        let f: fn(&Row<'_>) -> Result<T> = |row| row.deserialize();
+        self.query_map(params, f)
+    }
+}
+
+impl Connection {
+    /// `feature = "serde"` Convenience method to execute a query that is
+    /// expected to return a single row, deserialized into `T`.
+    ///
+    /// See [`Row::deserialize`] for how columns are mapped onto `T`.
+    ///
+    /// ## Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string
+    /// or if the underlying SQLite call fails.
+    pub fn query_row_as<T, P>(&self, sql: &str, params: P) -> Result<T>
+    where
+        T: DeserializeOwned,
+        P: Params,
+    {
+        self.query_row(sql, params, |row| row.deserialize())
+    }
+}
+
+#[derive(Debug)]
+enum DeError {
+    Sqlite(Error),
+    Custom(String),
+}
+
+impl DeError {
+    fn into_error(self, row: &Row<'_>, idx: usize) -> Error {
+        match self {
+            DeError::Sqlite(err) => err,
+            DeError::Custom(msg) => {
+                let ty = row
+                    .get_raw_checked(idx)
+                    .map_or(Type::Null, |value| value.data_type());
+                Error::FromSqlConversionFailure(idx, ty, msg.into())
+            }
+        }
+    }
+}
+
+impl fmt::Display for DeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            DeError::Sqlite(ref err) => err.fmt(f),
+            DeError::Custom(ref msg) => msg.fmt(f),
+        }
+    }
+}
+
+impl error::Error for DeError {}
+
+impl de::Error for DeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        DeError::Custom(msg.to_string())
+    }
+
+    fn invalid_length(len: usize, _: &dyn de::Expected) -> Self {
+        DeError::Sqlite(Error::InvalidColumnIndex(len))
+    }
+
+    fn unknown_field(field: &str, _: &'static [&'static str]) -> Self {
+        DeError::Sqlite(Error::InvalidColumnName(field.to_owned()))
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        DeError::Sqlite(Error::InvalidColumnName(field.to_owned()))
+    }
+}
+
+struct RowDeserializer<'de, 'stmt, 'p> {
+    row: &'de Row<'stmt>,
+    // index of the column being deserialized, to locate row level errors
+    pos: &'p Cell<usize>,
+}
+
+impl<'de> RowDeserializer<'de, '_, '_> {
+    fn column(&self, idx: usize) -> std::result::Result<ValueDeserializer<'de>, DeError> {
+        self.pos.set(idx);
+        let value = self.row.get_raw_checked(idx).map_err(DeError::Sqlite)?;
+        Ok(ValueDeserializer { value, idx })
+    }
+
+    fn single_column(&self) -> std::result::Result<ValueDeserializer<'de>, DeError> {
+        let count = self.row.stmt.column_count();
+        if count == 1 {
+            self.column(0)
+        } else {
+            // either no column at all, or unexpected ones starting at index 1
+            Err(DeError::Sqlite(Error::InvalidColumnIndex(count.min(1))))
+        }
+    }
+}
+
+impl<'de> Deserializer<'de> for RowDeserializer<'de, '_, '_> {
+    type Error = DeError;
+
+    fn deserialize_any<V>(self, visitor: V) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        if self.row.stmt.column_count() == 1 {
+            self.single_column()?.deserialize_any(visitor)
+        } else {
+            self.deserialize_map(visitor)
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        if self.row.stmt.column_count() == 1 {
+            self.single_column()?.deserialize_option(visitor)
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        self.single_column()?
+            .deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(RowAccess { de: self, idx: 0 })
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(RowAccess { de: self, idx: 0 })
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    // scalars are only supported for single-column rows
+    fn deserialize_bool<V>(self, visitor: V) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        self.single_column()?.deserialize_bool(visitor)
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        self.single_column()?.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        self.single_column()?.deserialize_bytes(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        identifier
+    }
+}
+
+struct RowAccess<'de, 'stmt, 'p> {
+    de: RowDeserializer<'de, 'stmt, 'p>,
+    idx: usize,
+}
+
+impl<'de> SeqAccess<'de> for RowAccess<'de, '_, '_> {
+    type Error = DeError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> std::result::Result<Option<T::Value>, DeError>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.idx >= self.de.row.stmt.column_count() {
+            return Ok(None);
+        }
+        let value = self.de.column(self.idx)?;
+        self.idx += 1;
+        seed.deserialize(value).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.de.row.stmt.column_count() - self.idx)
+    }
+}
+
+impl<'de> MapAccess<'de> for RowAccess<'de, '_, '_> {
+    type Error = DeError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> std::result::Result<Option<K::Value>, DeError>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if self.idx >= self.de.row.stmt.column_count() {
+            return Ok(None);
+        }
+        let name = self
+            .de
+            .row
+            .stmt
+            .column_name(self.idx)
+            .map_err(DeError::Sqlite)?;
+        seed.deserialize(name.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> std::result::Result<V::Value, DeError>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let value = self.de.column(self.idx)?;
+        self.idx += 1;
+        seed.deserialize(value)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.de.row.stmt.column_count() - self.idx)
+    }
+}
+
+struct ValueDeserializer<'de> {
+    value: ValueRef<'de>,
+    idx: usize,
+}
+
+impl ValueDeserializer<'_> {
+    // Attach the column index and type to a conversion error.
+    fn locate(&self, err: DeError) -> DeError {
+        match err {
+            DeError::Custom(msg) => DeError::Sqlite(Error::FromSqlConversionFailure(
+                self.idx,
+                self.value.data_type(),
+                msg.into(),
+            )),
+            err => err,
+        }
+    }
+
+    fn text<'de>(&self, text: &'de [u8]) -> std::result::Result<&'de str, DeError> {
+        str::from_utf8(text).map_err(|err| {
+            DeError::Sqlite(Error::FromSqlConversionFailure(
+                self.idx,
+                Type::Text,
+                Box::new(err),
+            ))
+        })
+    }
+}
+
+impl<'de> Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = DeError;
+
+    fn deserialize_any<V>(self, visitor: V) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        let r = match self.value {
+            ValueRef::Null => visitor.visit_unit(),
+            ValueRef::Integer(i) => visitor.visit_i64(i),
+            ValueRef::Real(f) => visitor.visit_f64(f),
+            ValueRef::Text(s) => visitor.visit_borrowed_str(self.text(s)?),
+            ValueRef::Blob(b) => visitor.visit_borrowed_bytes(b),
+        };
+        r.map_err(|err| self.locate(err))
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            ValueRef::Integer(i) => visitor.visit_bool(i != 0).map_err(|err| self.locate(err)),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            ValueRef::Null => visitor.visit_none().map_err(|err| self.locate(err)),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            ValueRef::Text(b) | ValueRef::Blob(b) => visitor
+                .visit_borrowed_bytes(b)
+                .map_err(|err| self.locate(err)),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // Only unit variants are supported, stored as text.
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> std::result::Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            ValueRef::Text(s) => {
+                let variant: de::value::BorrowedStrDeserializer<'de, DeError> =
+                    de::value::BorrowedStrDeserializer::new(self.text(s)?);
+                visitor.visit_enum(variant).map_err(|err| self.locate(err))
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        unit unit_struct seq tuple tuple_struct map struct identifier
+        ignored_any
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::Deserialize;
+
+    use crate::types::Type;
+    use crate::{Connection, Error, Result};
+
+    fn checked_memory_handle() -> Result<Connection> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT NOT NULL, age INTEGER, \
+                 kind TEXT NOT NULL, data BLOB);
+             INSERT INTO person VALUES (1, 'Alice', 42, 'Admin', x'0102');
+             INSERT INTO person VALUES (2, 'Bob', NULL, 'Guest', NULL);",
+        )?;
+        Ok(db)
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct PersonId(i64);
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Kind {
+        Admin,
+        Guest,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Person {
+        id: PersonId,
+        name: String,
+        age: Option<u8>,
+        kind: Kind,
+        #[serde(with = "serde_bytes_opt")]
+        data: Option<Vec<u8>>,
+    }
+
+    mod serde_bytes_opt {
+        use serde::{Deserialize, Deserializer};
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<u8>>, D::Error> {
+            Option::<&[u8]>::deserialize(d).map(|b| b.map(<[u8]>::to_vec))
+        }
+    }
+
+    #[test]
+    fn test_query_as_struct() -> Result<()> {
+        let db = checked_memory_handle()?;
+        let mut stmt = db.prepare("SELECT * FROM person ORDER BY id")?;
+        let people = stmt
+            .query_as::<Person, _>([])?
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            vec![
+                Person {
+                    id: PersonId(1),
+                    name: "Alice".to_owned(),
+                    age: Some(42),
+                    kind: Kind::Admin,
+                    data: Some(vec![1, 2]),
+                },
+                Person {
+                    id: PersonId(2),
+                    name: "Bob".to_owned(),
+                    age: None,
+                    kind: Kind::Guest,
+                    data: None,
+                },
+            ],
+            people
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_row_as() -> Result<()> {
+        let db = checked_memory_handle()?;
+        let (name, age): (String, Option<i64>) =
+            db.query_row_as("SELECT name, age FROM person WHERE id = 2", [])?;
+        assert_eq!(("Bob".to_owned(), None), (name, age));
+
+        let id: PersonId = db.query_row_as("SELECT id FROM person WHERE name = ?", ["Alice"])?;
+        assert_eq!(PersonId(1), id);
+
+        let kind: Option<Kind> = db.query_row_as("SELECT kind FROM person WHERE id = 1", [])?;
+        assert_eq!(Some(Kind::Admin), kind);
+        Ok(())
+    }
+
+    #[test]
+    fn test_borrowed_row() -> Result<()> {
+        #[derive(Deserialize)]
+        struct Name<'a> {
+            name: &'a str,
+        }
+
+        let db = checked_memory_handle()?;
+        let mut stmt = db.prepare("SELECT name FROM person WHERE id = 1")?;
+        let mut rows = stmt.query([])?;
+        let row = rows.next()?.unwrap();
+        let name: Name<'_> = row.deserialize()?;
+        assert_eq!("Alice", name.name);
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() -> Result<()> {
+        let db = checked_memory_handle()?;
+
+        match db.query_row_as::<Person, _>("SELECT id, name FROM person", []) {
+            Err(Error::InvalidColumnName(name)) => assert_eq!("kind", name),
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        match db.query_row_as::<(i64, u8), _>("SELECT id, name FROM person", []) {
+            Err(Error::FromSqlConversionFailure(1, Type::Text, _)) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        match db.query_row_as::<(i64, String, u8), _>("SELECT id, name FROM person", []) {
+            Err(Error::InvalidColumnIndex(2)) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        match db.query_row_as::<Kind, _>("SELECT 'Unknown'", []) {
+            Err(Error::FromSqlConversionFailure(0, Type::Text, _)) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        match db.query_row_as::<u8, _>("SELECT 1000", []) {
+            Err(Error::FromSqlConversionFailure(0, Type::Integer, _)) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+        Ok(())
+    }
+}
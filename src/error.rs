@@ -116,6 +116,28 @@ pub enum Error {
     /// return it if the blob has insufficient data.
     #[cfg(feature = "blob")]
     BlobSizeError,
+
+    /// Error returned by [`OpenOptions`](crate::OpenOptions) when a step of
+    /// opening or configuring a connection fails. The `String` names the step
+    /// (`"open"`, `"journal_mode"`, `"init[0]"`...).
+    OpenStepFailed(String, Box<Error>),
+
+    /// Error returned by [`Migrations`](crate::migrations::Migrations) when
+    /// a migration fails. The `usize` is the index of the migration.
+    #[cfg(feature = "migrations")]
+    MigrationFailed(usize, Box<Error>),
+
+    /// Error when a statement is interrupted because the deadline set with
+    /// [`Connection::with_deadline`](crate::Connection::with_deadline) or
+    /// [`Statement::set_timeout`](crate::Statement::set_timeout) has passed.
+    #[cfg(feature = "hooks")]
+    DeadlineExceeded,
+
+    /// Error returned by
+    /// [`Connection::transact`](crate::Connection::transact) when the
+    /// database stayed busy or locked. The `u32` is the number of attempts,
+    /// the `Error` the last failure.
+    RetriesExhausted(u32, Box<Error>),
 }
 
 impl PartialEq for Error {
@@ -160,6 +182,16 @@ impl PartialEq for Error {
             }
             #[cfg(feature = "blob")]
             (Error::BlobSizeError, Error::BlobSizeError) => true,
+            (Error::OpenStepFailed(s1, e1), Error::OpenStepFailed(s2, e2)) => s1 == s2 && e1 == e2,
+            #[cfg(feature = "migrations")]
+            (Error::MigrationFailed(i1, e1), Error::MigrationFailed(i2, e2)) => {
+                i1 == i2 && e1 == e2
+            }
+            #[cfg(feature = "hooks")]
+            (Error::DeadlineExceeded, Error::DeadlineExceeded) => true,
+            (Error::RetriesExhausted(n1, e1), Error::RetriesExhausted(n2, e2)) => {
+                n1 == n2 && e1 == e2
+            }
             (..) => false,
         }
     }
@@ -277,6 +309,14 @@ impl fmt::Display for Error {
 
             #[cfg(feature = "blob")]
             Error::BlobSizeError => "Blob size is insufficient".fmt(f),
+            Error::OpenStepFailed(ref step, ref err) => write!(f, "{} failed: {}", step, err),
+            #[cfg(feature = "migrations")]
+            Error::MigrationFailed(i, ref err) => write!(f, "Migration {} failed: {}", i, err),
+            #[cfg(feature = "hooks")]
+            Error::DeadlineExceeded => write!(f, "Deadline exceeded"),
+            Error::RetriesExhausted(n, ref err) => {
+                write!(f, "Giving up after {} attempts: {}", n, err)
+            }
         }
     }
 }
@@ -324,6 +364,15 @@ impl error::Error for Error {
 
             #[cfg(feature = "blob")]
             Error::BlobSizeError => None,
+
+            Error::OpenStepFailed(_, ref err) => Some(&**err),
+            #[cfg(feature = "migrations")]
+            Error::MigrationFailed(_, ref err) => Some(&**err),
+
+            #[cfg(feature = "hooks")]
+            Error::DeadlineExceeded => None,
+
+            Error::RetriesExhausted(_, ref err) => Some(&**err),
         }
     }
 }
@@ -1,16 +1,28 @@
 //! `feature = "hooks"` Commit, Data Change and Rollback Notification Callbacks
 #![allow(non_camel_case_types)]
 
+#[cfg(feature = "preupdate_hook")]
+use std::marker::PhantomData;
 use std::os::raw::{c_char, c_int, c_void};
-use std::panic::{catch_unwind, RefUnwindSafe};
+use std::panic::{catch_unwind, AssertUnwindSafe, RefUnwindSafe};
 use std::ptr;
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "preupdate_hook")]
+use crate::error::{error_from_handle, error_from_sqlite_code};
 use crate::ffi;
+#[cfg(feature = "preupdate_hook")]
+use crate::types::ValueRef;
 
-use crate::{Connection, InnerConnection};
+#[cfg(feature = "modern_sqlite")]
+use crate::DatabaseName;
+#[cfg(feature = "preupdate_hook")]
+use crate::Error;
+use crate::{Connection, InnerConnection, Result, Statement};
 
 /// `feature = "hooks"` Action Codes
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(i32)]
 #[non_exhaustive]
 pub enum Action {
@@ -36,6 +48,370 @@ impl From<i32> for Action {
     }
 }
 
+/// `feature = "hooks"` The context received by an authorizer hook.
+///
+/// See <https://sqlite.org/c3ref/set_authorizer.html> for more info.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AuthContext<'c> {
+    /// The action to be authorized.
+    pub action: AuthAction<'c>,
+
+    /// The database name, if applicable.
+    pub database_name: Option<&'c str>,
+
+    /// The inner-most trigger or view responsible for the access attempt.
+    /// `None` if the access attempt was made by top-level SQL code.
+    pub accessor: Option<&'c str>,
+}
+
+/// `feature = "hooks"` Actions and arguments found within a statement during
+/// preparation.
+///
+/// See <https://sqlite.org/c3ref/c_alter_table.html> for more info.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+#[allow(missing_docs)]
+pub enum AuthAction<'c> {
+    /// This variant is not normally produced by SQLite. You may encounter it
+    /// if you're using a different version than what's supported by this
+    /// library.
+    Unknown {
+        /// The unknown authorization action code.
+        code: i32,
+        /// The third arg to the authorizer callback.
+        arg1: Option<&'c str>,
+        /// The fourth arg to the authorizer callback.
+        arg2: Option<&'c str>,
+    },
+    CreateIndex {
+        index: &'c str,
+        table: &'c str,
+    },
+    CreateTable {
+        table: &'c str,
+    },
+    CreateTempIndex {
+        index: &'c str,
+        table: &'c str,
+    },
+    CreateTempTable {
+        table: &'c str,
+    },
+    CreateTempTrigger {
+        trigger: &'c str,
+        table: &'c str,
+    },
+    CreateTempView {
+        view: &'c str,
+    },
+    CreateTrigger {
+        trigger: &'c str,
+        table: &'c str,
+    },
+    CreateView {
+        view: &'c str,
+    },
+    Delete {
+        table: &'c str,
+    },
+    DropIndex {
+        index: &'c str,
+        table: &'c str,
+    },
+    DropTable {
+        table: &'c str,
+    },
+    DropTempIndex {
+        index: &'c str,
+        table: &'c str,
+    },
+    DropTempTable {
+        table: &'c str,
+    },
+    DropTempTrigger {
+        trigger: &'c str,
+        table: &'c str,
+    },
+    DropTempView {
+        view: &'c str,
+    },
+    DropTrigger {
+        trigger: &'c str,
+        table: &'c str,
+    },
+    DropView {
+        view: &'c str,
+    },
+    Insert {
+        table: &'c str,
+    },
+    Pragma {
+        name: &'c str,
+        arg: Option<&'c str>,
+    },
+    Read {
+        table: &'c str,
+        column: &'c str,
+    },
+    Select,
+    Transaction {
+        operation: TransactionOperation,
+    },
+    Update {
+        table: &'c str,
+        column: &'c str,
+    },
+    Attach {
+        filename: &'c str,
+    },
+    Detach {
+        database: &'c str,
+    },
+    AlterTable {
+        database: &'c str,
+        table: &'c str,
+    },
+    Reindex {
+        index: &'c str,
+    },
+    Analyze {
+        table: &'c str,
+    },
+    CreateVtable {
+        table: &'c str,
+        module: &'c str,
+    },
+    DropVtable {
+        table: &'c str,
+        module: &'c str,
+    },
+    Function {
+        name: &'c str,
+    },
+    Savepoint {
+        operation: TransactionOperation,
+        name: &'c str,
+    },
+    Recursive,
+}
+
+impl<'c> AuthAction<'c> {
+    fn from_raw(code: i32, arg1: Option<&'c str>, arg2: Option<&'c str>) -> Self {
+        match (code, arg1, arg2) {
+            (ffi::SQLITE_CREATE_INDEX, Some(index), Some(table)) => {
+                Self::CreateIndex { index, table }
+            }
+            (ffi::SQLITE_CREATE_TABLE, Some(table), _) => Self::CreateTable { table },
+            (ffi::SQLITE_CREATE_TEMP_INDEX, Some(index), Some(table)) => {
+                Self::CreateTempIndex { index, table }
+            }
+            (ffi::SQLITE_CREATE_TEMP_TABLE, Some(table), _) => Self::CreateTempTable { table },
+            (ffi::SQLITE_CREATE_TEMP_TRIGGER, Some(trigger), Some(table)) => {
+                Self::CreateTempTrigger { trigger, table }
+            }
+            (ffi::SQLITE_CREATE_TEMP_VIEW, Some(view), _) => Self::CreateTempView { view },
+            (ffi::SQLITE_CREATE_TRIGGER, Some(trigger), Some(table)) => {
+                Self::CreateTrigger { trigger, table }
+            }
+            (ffi::SQLITE_CREATE_VIEW, Some(view), _) => Self::CreateView { view },
+            (ffi::SQLITE_DELETE, Some(table), None) => Self::Delete { table },
+            (ffi::SQLITE_DROP_INDEX, Some(index), Some(table)) => Self::DropIndex { index, table },
+            (ffi::SQLITE_DROP_TABLE, Some(table), _) => Self::DropTable { table },
+            (ffi::SQLITE_DROP_TEMP_INDEX, Some(index), Some(table)) => {
+                Self::DropTempIndex { index, table }
+            }
+            (ffi::SQLITE_DROP_TEMP_TABLE, Some(table), _) => Self::DropTempTable { table },
+            (ffi::SQLITE_DROP_TEMP_TRIGGER, Some(trigger), Some(table)) => {
+                Self::DropTempTrigger { trigger, table }
+            }
+            (ffi::SQLITE_DROP_TEMP_VIEW, Some(view), _) => Self::DropTempView { view },
+            (ffi::SQLITE_DROP_TRIGGER, Some(trigger), Some(table)) => {
+                Self::DropTrigger { trigger, table }
+            }
+            (ffi::SQLITE_DROP_VIEW, Some(view), _) => Self::DropView { view },
+            (ffi::SQLITE_INSERT, Some(table), _) => Self::Insert { table },
+            (ffi::SQLITE_PRAGMA, Some(name), arg) => Self::Pragma { name, arg },
+            (ffi::SQLITE_READ, Some(table), Some(column)) => Self::Read { table, column },
+            (ffi::SQLITE_SELECT, ..) => Self::Select,
+            (ffi::SQLITE_TRANSACTION, Some(operation), _) => Self::Transaction {
+                operation: TransactionOperation::from_str(operation),
+            },
+            (ffi::SQLITE_UPDATE, Some(table), Some(column)) => Self::Update { table, column },
+            (ffi::SQLITE_ATTACH, Some(filename), _) => Self::Attach { filename },
+            (ffi::SQLITE_DETACH, Some(database), _) => Self::Detach { database },
+            (ffi::SQLITE_ALTER_TABLE, Some(database), Some(table)) => {
+                Self::AlterTable { database, table }
+            }
+            (ffi::SQLITE_REINDEX, Some(index), _) => Self::Reindex { index },
+            (ffi::SQLITE_ANALYZE, Some(table), _) => Self::Analyze { table },
+            (ffi::SQLITE_CREATE_VTABLE, Some(table), Some(module)) => {
+                Self::CreateVtable { table, module }
+            }
+            (ffi::SQLITE_DROP_VTABLE, Some(table), Some(module)) => {
+                Self::DropVtable { table, module }
+            }
+            (ffi::SQLITE_FUNCTION, _, Some(name)) => Self::Function { name },
+            (ffi::SQLITE_SAVEPOINT, Some(operation), Some(name)) => Self::Savepoint {
+                operation: TransactionOperation::from_str(operation),
+                name,
+            },
+            // SQLITE_RECURSIVE (3.8.3)
+            (33, ..) => Self::Recursive,
+            (code, arg1, arg2) => Self::Unknown { code, arg1, arg2 },
+        }
+    }
+}
+
+/// `feature = "hooks"` Transaction operations reported to an authorizer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+#[allow(missing_docs)]
+pub enum TransactionOperation {
+    Unknown,
+    Begin,
+    Commit,
+    Release,
+    Rollback,
+}
+
+impl TransactionOperation {
+    fn from_str(op_str: &str) -> Self {
+        match op_str {
+            "BEGIN" => Self::Begin,
+            "COMMIT" => Self::Commit,
+            "RELEASE" => Self::Release,
+            "ROLLBACK" => Self::Rollback,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// `feature = "hooks"` Return value of an authorizer hook.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Authorization {
+    /// Authorize the action.
+    Allow,
+    /// Don't allow access, but don't trigger an error either: a column read
+    /// is replaced by NULL, a deleted table is left untouched, ...
+    Ignore,
+    /// Trigger an error.
+    Deny,
+}
+
+impl Authorization {
+    fn into_raw(self) -> c_int {
+        match self {
+            Self::Allow => ffi::SQLITE_OK,
+            Self::Ignore => ffi::SQLITE_IGNORE,
+            Self::Deny => ffi::SQLITE_DENY,
+        }
+    }
+}
+
+pub(crate) type BoxedAuthorizer = Box<dyn for<'c> FnMut(AuthContext<'c>) -> Authorization + Send>;
+
+#[cfg(feature = "modern_sqlite")] // 3.7.6
+pub(crate) type BoxedWalHook = Box<dyn FnMut(&str, c_int) + Send>;
+
+/// `feature = "preupdate_hook"` Access to the values of the row being
+/// changed, available from within a
+/// [`preupdate_hook`](Connection::preupdate_hook) callback.
+///
+/// See <https://sqlite.org/c3ref/preupdate_count.html> for more info.
+#[cfg(feature = "preupdate_hook")]
+pub struct PreUpdateCase<'a> {
+    db: *mut ffi::sqlite3,
+    action: Action,
+    phantom: PhantomData<&'a ()>,
+}
+
+#[cfg(feature = "preupdate_hook")]
+impl PreUpdateCase<'_> {
+    /// Number of columns in the row that is being inserted, updated, or
+    /// deleted.
+    #[inline]
+    pub fn count(&self) -> i32 {
+        unsafe { ffi::sqlite3_preupdate_count(self.db) }
+    }
+
+    /// Depth of the trigger that caused the change: 0 for a direct
+    /// insert, update, or delete, 1 for a change made by a top-level
+    /// trigger, and so on.
+    #[inline]
+    pub fn depth(&self) -> i32 {
+        unsafe { ffi::sqlite3_preupdate_depth(self.db) }
+    }
+
+    /// Value of the `i`th column of the row before it is updated or deleted.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` for an insertion, or if `i` is out of range.
+    pub fn get_old(&self, i: i32) -> Result<ValueRef<'_>> {
+        if self.action == Action::SQLITE_INSERT {
+            return Err(error_from_sqlite_code(ffi::SQLITE_MISUSE, None));
+        }
+        self.check_column_index(i)?;
+        let mut p_value: *mut ffi::sqlite3_value = ptr::null_mut();
+        let rc = unsafe { ffi::sqlite3_preupdate_old(self.db, i, &mut p_value) };
+        self.value_ref(rc, p_value)
+    }
+
+    /// Value of the `i`th column of the row after it is inserted or updated.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` for a deletion, or if `i` is out of range.
+    pub fn get_new(&self, i: i32) -> Result<ValueRef<'_>> {
+        if self.action == Action::SQLITE_DELETE {
+            return Err(error_from_sqlite_code(ffi::SQLITE_MISUSE, None));
+        }
+        self.check_column_index(i)?;
+        let mut p_value: *mut ffi::sqlite3_value = ptr::null_mut();
+        let rc = unsafe { ffi::sqlite3_preupdate_new(self.db, i, &mut p_value) };
+        self.value_ref(rc, p_value)
+    }
+
+    #[inline]
+    fn check_column_index(&self, i: i32) -> Result<()> {
+        if i < 0 || i >= self.count() {
+            Err(Error::InvalidColumnIndex(i as usize))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[inline]
+    fn value_ref(&self, rc: c_int, p_value: *mut ffi::sqlite3_value) -> Result<ValueRef<'_>> {
+        if rc != ffi::SQLITE_OK {
+            return Err(unsafe { error_from_handle(self.db, rc) });
+        }
+        Ok(unsafe { ValueRef::from_value(p_value) })
+    }
+}
+
+/// `feature = "hooks"` and `feature = "modern_sqlite"` Checkpoint mode, see
+/// [`wal_checkpoint_v2`](Connection::wal_checkpoint_v2).
+#[cfg(feature = "modern_sqlite")] // 3.7.6
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(i32)]
+#[non_exhaustive]
+pub enum CheckpointMode {
+    /// Checkpoint as many frames as possible without waiting for any database
+    /// readers or writers to finish.
+    Passive = ffi::SQLITE_CHECKPOINT_PASSIVE,
+    /// Wait until there is no database writer and all readers are reading
+    /// from the most recent database snapshot, then checkpoint all frames.
+    Full = ffi::SQLITE_CHECKPOINT_FULL,
+    /// Like [`Full`](CheckpointMode::Full), and then wait until all readers
+    /// are reading from the database file only.
+    Restart = ffi::SQLITE_CHECKPOINT_RESTART,
+    /// Like [`Restart`](CheckpointMode::Restart), and also truncate the log
+    /// file to zero bytes (3.8.8).
+    Truncate = 3, // SQLITE_CHECKPOINT_TRUNCATE
+}
+
 impl Connection {
     /// `feature = "hooks"` Register a callback function to be invoked whenever
     /// a transaction is committed.
@@ -79,6 +455,38 @@ impl Connection {
         self.db.borrow_mut().update_hook(hook);
     }
 
+    /// `feature = "preupdate_hook"` Register a callback function to be
+    /// invoked before each row is updated, inserted or deleted.
+    ///
+    /// The callback parameters are:
+    ///
+    /// - the type of database update (SQLITE_INSERT, SQLITE_UPDATE or
+    ///   SQLITE_DELETE),
+    /// - the name of the database ("main", "temp", ...),
+    /// - the name of the table that is updated,
+    /// - the ROWID of the row before the change (undefined for an insertion),
+    /// - the ROWID of the row after the change (undefined for a deletion),
+    /// - a [`PreUpdateCase`] giving access to the old and new values of the
+    ///   row.
+    ///
+    /// Unlike [`update_hook`](Connection::update_hook), this callback is also
+    /// invoked for changes to `WITHOUT ROWID` tables, in which case the
+    /// ROWIDs are undefined.
+    ///
+    /// # Failure
+    ///
+    /// SQLite has a single preupdate hook per connection, which is also used
+    /// by the session extension: will return `Err` with `SQLITE_MISUSE` while
+    /// a [`Session`](crate::session::Session) is attached to this connection.
+    #[cfg(feature = "preupdate_hook")]
+    #[inline]
+    pub fn preupdate_hook<'c, F>(&'c self, hook: Option<F>) -> Result<()>
+    where
+        F: FnMut(Action, &str, &str, i64, i64, &PreUpdateCase<'_>) + Send + 'c,
+    {
+        self.db.borrow_mut().preupdate_hook(hook)
+    }
+
     /// `feature = "hooks"` Register a query progress callback.
     ///
     /// The parameter `num_ops` is the approximate number of virtual machine
@@ -87,21 +495,223 @@ impl Connection {
     /// is disabled.
     ///
     /// If the progress callback returns `true`, the operation is interrupted.
+    ///
+    /// The handler keeps running while a deadline is set with
+    /// [`with_deadline`](Connection::with_deadline) or
+    /// [`Statement::set_timeout`](crate::Statement::set_timeout); if
+    /// `num_ops` is larger than the granularity of deadline checks (1000),
+    /// the handler is invoked approximately every `num_ops` instructions.
     pub fn progress_handler<F>(&self, num_ops: c_int, handler: Option<F>)
     where
         F: FnMut() -> bool + Send + RefUnwindSafe + 'static,
     {
         self.db.borrow_mut().progress_handler(num_ops, handler);
     }
+
+    /// `feature = "hooks"` Run `f` with a deadline: statements executed by
+    /// `f` on this connection are interrupted once `deadline` has passed, and
+    /// fail with [`Error::DeadlineExceeded`].
+    ///
+    /// Deadlines nest: an inner deadline cannot extend an outer one. Time
+    /// spent waiting for a lock (see
+    /// [`busy_timeout`](Connection::busy_timeout)) is not interrupted.
+    ///
+    /// This works alongside [`progress_handler`](Connection::progress_handler).
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Error, Result};
+    /// # use std::time::{Duration, Instant};
+    /// fn count(conn: &Connection) -> Result<Option<i64>> {
+    ///     let deadline = Instant::now() + Duration::from_millis(100);
+    ///     let r = conn.with_deadline(deadline, |conn| {
+    ///         conn.query_row("SELECT COUNT(*) FROM big_table", [], |r| r.get(0))
+    ///     });
+    ///     match r {
+    ///         Ok(n) => Ok(Some(n)),
+    ///         Err(Error::DeadlineExceeded) => Ok(None),
+    ///         Err(e) => Err(e),
+    ///     }
+    /// }
+    /// ```
+    pub fn with_deadline<T, F>(&self, deadline: Instant, f: F) -> Result<T>
+    where
+        F: FnOnce(&Connection) -> Result<T>,
+    {
+        let _guard = DeadlineGuard::new(self, deadline);
+        f(self)
+    }
+
+    /// `feature = "hooks"` Run `f` with a deadline `timeout` from now.
+    /// See [`with_deadline`](Connection::with_deadline).
+    #[inline]
+    pub fn with_timeout<T, F>(&self, timeout: Duration, f: F) -> Result<T>
+    where
+        F: FnOnce(&Connection) -> Result<T>,
+    {
+        self.with_deadline(Instant::now() + timeout, f)
+    }
+
+    /// `feature = "hooks"` Register an authorizer callback that's invoked
+    /// as a statement is being prepared.
+    ///
+    /// The callback is given the [`AuthContext`] of each action found in the
+    /// statement, and decides whether it is allowed. If the callback panics,
+    /// the action is denied.
+    #[inline]
+    pub fn authorizer<F>(&self, hook: Option<F>)
+    where
+        F: for<'r> FnMut(AuthContext<'r>) -> Authorization + Send + RefUnwindSafe + 'static,
+    {
+        self.db.borrow_mut().authorizer(hook);
+    }
+
+    /// `feature = "hooks"` and `feature = "modern_sqlite"` Register a
+    /// callback function to be invoked each time data is committed to a
+    /// database in WAL mode.
+    ///
+    /// The callback parameters are the name of the database that was written
+    /// to and the number of frames currently in its write-ahead log.
+    ///
+    /// The connection's auto-checkpoint mechanism is implemented with the
+    /// same hook, so registering (or removing) a callback disables it. Use
+    /// [`wal_checkpoint_v2`](Connection::wal_checkpoint_v2) to checkpoint
+    /// instead, or [`wal_autocheckpoint`](Connection::wal_autocheckpoint) to
+    /// reinstate it.
+    #[cfg(feature = "modern_sqlite")] // 3.7.0
+    #[inline]
+    pub fn wal_hook<F>(&self, hook: Option<F>)
+    where
+        F: FnMut(&str, c_int) + Send + RefUnwindSafe + 'static,
+    {
+        self.db.borrow_mut().wal_hook(hook);
+    }
+
+    /// `feature = "hooks"` and `feature = "modern_sqlite"` Run a checkpoint
+    /// of the `db_name` database in the given `mode`.
+    ///
+    /// Returns the total number of frames in the write-ahead log and the
+    /// number of them that were checkpointed. Both are -1 if the database is
+    /// not in WAL mode.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `db_name` is not an attached database, or with
+    /// `SQLITE_BUSY` if a lock required by `mode` couldn't be obtained.
+    #[cfg(feature = "modern_sqlite")] // 3.7.6
+    pub fn wal_checkpoint_v2(
+        &self,
+        db_name: DatabaseName<'_>,
+        mode: CheckpointMode,
+    ) -> Result<(c_int, c_int)> {
+        let db_name = db_name.to_cstring()?;
+        let mut c = self.db.borrow_mut();
+        let mut log_frames: c_int = -1;
+        let mut checkpointed_frames: c_int = -1;
+        let r = unsafe {
+            ffi::sqlite3_wal_checkpoint_v2(
+                c.db(),
+                db_name.as_ptr(),
+                mode as c_int,
+                &mut log_frames,
+                &mut checkpointed_frames,
+            )
+        };
+        c.decode_result(r)?;
+        Ok((log_frames, checkpointed_frames))
+    }
+
+    /// `feature = "hooks"` and `feature = "modern_sqlite"` Automatically
+    /// checkpoint a database whenever its write-ahead log reaches `n` frames
+    /// after a commit. A value of zero or less disables automatic checkpoints.
+    ///
+    /// This replaces any callback registered with
+    /// [`wal_hook`](Connection::wal_hook).
+    #[cfg(feature = "modern_sqlite")] // 3.7.0
+    #[inline]
+    pub fn wal_autocheckpoint(&self, n: c_int) -> Result<()> {
+        self.db.borrow_mut().wal_autocheckpoint(n)
+    }
+}
+
+/// Number of virtual machine instructions between deadline checks.
+const DEADLINE_OPS: c_int = 1000;
+
+/// State of the progress handler, which serves both the user's handler and
+/// the deadline.
+#[derive(Default)]
+pub struct Progress {
+    handler: Option<Box<dyn FnMut() -> bool + Send>>,
+    num_ops: c_int,
+    // the user's handler is invoked every `every` callbacks
+    every: c_int,
+    calls: c_int,
+    deadline: Option<Instant>,
+    timed_out: bool,
+    // interval of the callback registered with SQLite, 0 if none
+    registered: c_int,
+}
+
+/// Sets a deadline on a connection, restoring the previous one when dropped.
+pub(crate) struct DeadlineGuard<'conn> {
+    conn: &'conn Connection,
+    previous: Option<Instant>,
+}
+
+impl DeadlineGuard<'_> {
+    pub(crate) fn new(conn: &Connection, deadline: Instant) -> DeadlineGuard<'_> {
+        let mut c = conn.db.borrow_mut();
+        let previous = c.progress.as_ref().and_then(|p| p.deadline);
+        let deadline = previous.map_or(deadline, |previous| previous.min(deadline));
+        c.set_deadline(Some(deadline), true);
+        DeadlineGuard { conn, previous }
+    }
+}
+
+impl Drop for DeadlineGuard<'_> {
+    fn drop(&mut self) {
+        self.conn.db.borrow_mut().set_deadline(self.previous, false);
+    }
+}
+
+/// Deadline of an execution of a statement, from the
+/// [timeout](Statement::set_timeout) of the statement when the execution
+/// starts.
+#[derive(Clone, Copy)]
+pub(crate) struct ExecutionDeadline(Option<Instant>);
+
+impl ExecutionDeadline {
+    #[inline]
+    pub(crate) fn start(stmt: &Statement<'_>) -> ExecutionDeadline {
+        ExecutionDeadline(stmt.timeout.map(|timeout| Instant::now() + timeout))
+    }
+
+    /// Run `f`, a step of `stmt`, under the deadline. It is only set on the
+    /// connection during the step, so that the statements run between two
+    /// steps are not subject to it.
+    #[inline]
+    pub(crate) fn step<T, F: FnOnce() -> T>(self, stmt: &Statement<'_>, f: F) -> T {
+        let _guard = self
+            .0
+            .map(|deadline| DeadlineGuard::new(stmt.conn, deadline));
+        f()
+    }
 }
 
 impl InnerConnection {
     #[inline]
     pub fn remove_hooks(&mut self) {
         self.update_hook(None::<fn(Action, &str, &str, i64)>);
+        #[cfg(feature = "preupdate_hook")]
+        if self.free_preupdate_hook.is_some() {
+            let _ =
+                self.preupdate_hook(None::<fn(Action, &str, &str, i64, i64, &PreUpdateCase<'_>)>);
+        }
         self.commit_hook(None::<fn() -> bool>);
         self.rollback_hook(None::<fn()>);
         self.progress_handler(0, None::<fn() -> bool>);
+        self.authorizer(None::<fn(AuthContext<'_>) -> Authorization>);
+        #[cfg(feature = "modern_sqlite")]
+        self.wal_hook(None::<fn(&str, c_int)>);
     }
 
     fn commit_hook<'c, F>(&'c mut self, hook: Option<F>)
@@ -258,53 +868,313 @@ impl InnerConnection {
         self.free_update_hook = free_update_hook;
     }
 
+    #[cfg(feature = "preupdate_hook")]
+    fn preupdate_hook<'c, F>(&'c mut self, hook: Option<F>) -> Result<()>
+    where
+        F: FnMut(Action, &str, &str, i64, i64, &PreUpdateCase<'_>) + Send + 'c,
+    {
+        unsafe extern "C" fn call_boxed_closure<F>(
+            p_arg: *mut c_void,
+            sqlite: *mut ffi::sqlite3,
+            action_code: c_int,
+            db_str: *const c_char,
+            tbl_str: *const c_char,
+            old_row_id: i64,
+            new_row_id: i64,
+        ) where
+            F: FnMut(Action, &str, &str, i64, i64, &PreUpdateCase<'_>),
+        {
+            use std::ffi::CStr;
+            use std::str;
+
+            let action = Action::from(action_code);
+            let db_name = {
+                let c_slice = CStr::from_ptr(db_str).to_bytes();
+                str::from_utf8(c_slice)
+            };
+            let tbl_name = {
+                let c_slice = CStr::from_ptr(tbl_str).to_bytes();
+                str::from_utf8(c_slice)
+            };
+            let case = PreUpdateCase {
+                db: sqlite,
+                action,
+                phantom: PhantomData,
+            };
+
+            let _ = catch_unwind(|| {
+                let boxed_hook: *mut F = p_arg as *mut F;
+                (*boxed_hook)(
+                    action,
+                    db_name.expect("illegal db name"),
+                    tbl_name.expect("illegal table name"),
+                    old_row_id,
+                    new_row_id,
+                    &case,
+                );
+            });
+        }
+
+        #[cfg(feature = "session")]
+        {
+            if self.sessions > 0 {
+                return Err(error_from_sqlite_code(
+                    ffi::SQLITE_MISUSE,
+                    Some("preupdate hook is used by a session".to_owned()),
+                ));
+            }
+        }
+
+        let free_preupdate_hook = if hook.is_some() {
+            Some(free_boxed_hook::<F> as unsafe fn(*mut c_void))
+        } else {
+            None
+        };
+
+        let previous_hook = match hook {
+            Some(hook) => {
+                let boxed_hook: *mut F = Box::into_raw(Box::new(hook));
+                unsafe {
+                    ffi::sqlite3_preupdate_hook(
+                        self.db(),
+                        Some(call_boxed_closure::<F>),
+                        boxed_hook as *mut _,
+                    )
+                }
+            }
+            _ => unsafe { ffi::sqlite3_preupdate_hook(self.db(), None, ptr::null_mut()) },
+        };
+        if !previous_hook.is_null() {
+            if let Some(free_boxed_hook) = self.free_preupdate_hook {
+                unsafe { free_boxed_hook(previous_hook) };
+            }
+        }
+        self.free_preupdate_hook = free_preupdate_hook;
+        Ok(())
+    }
+
     fn progress_handler<F>(&mut self, num_ops: c_int, handler: Option<F>)
     where
         F: FnMut() -> bool + Send + RefUnwindSafe + 'static,
     {
-        unsafe extern "C" fn call_boxed_closure<F>(p_arg: *mut c_void) -> c_int
+        let progress = self.progress.get_or_insert_with(Default::default);
+        progress.handler = match handler {
+            Some(handler) if num_ops > 0 => Some(Box::new(handler)),
+            _ => None,
+        };
+        progress.num_ops = num_ops;
+        self.register_progress();
+    }
+
+    fn set_deadline(&mut self, deadline: Option<Instant>, reset: bool) {
+        let progress = self.progress.get_or_insert_with(Default::default);
+        progress.deadline = deadline;
+        if reset {
+            progress.timed_out = false;
+        }
+        // Without handler, the callback is left registered once the deadline
+        // is removed (it does nothing then): a statement timeout is set
+        // around each step, and would otherwise register it twice per row.
+        if deadline.is_some() || progress.handler.is_some() {
+            self.register_progress();
+        }
+    }
+
+    /// Returns `true` (once) if the last interruption was caused by a
+    /// deadline.
+    pub(crate) fn take_timed_out(&mut self) -> bool {
+        match self.progress {
+            Some(ref mut progress) => std::mem::replace(&mut progress.timed_out, false),
+            None => false,
+        }
+    }
+
+    fn register_progress(&mut self) {
+        unsafe extern "C" fn call_progress(p_arg: *mut c_void) -> c_int {
+            let progress = &mut *(p_arg as *mut Progress);
+            if let Some(deadline) = progress.deadline {
+                if Instant::now() >= deadline {
+                    progress.timed_out = true;
+                    return 1;
+                }
+            }
+            if let Some(ref mut handler) = progress.handler {
+                progress.calls += 1;
+                if progress.calls >= progress.every {
+                    progress.calls = 0;
+                    // `handler` is `RefUnwindSafe`, as required by
+                    // `Connection::progress_handler`.
+                    if let Ok(true) = catch_unwind(AssertUnwindSafe(handler)) {
+                        return 1;
+                    }
+                }
+            }
+            0
+        }
+
+        let db = self.db();
+        let progress = match self.progress {
+            Some(ref mut progress) => progress,
+            None => return,
+        };
+        let num_ops = match (progress.handler.is_some(), progress.deadline.is_some()) {
+            (false, false) => {
+                unsafe { ffi::sqlite3_progress_handler(db, 0, None, ptr::null_mut()) }
+                progress.registered = 0;
+                return;
+            }
+            (true, false) => progress.num_ops,
+            (false, true) => DEADLINE_OPS,
+            (true, true) => progress.num_ops.min(DEADLINE_OPS),
+        };
+        progress.every = (progress.num_ops / num_ops).max(1);
+        progress.calls = 0;
+        if progress.registered == num_ops {
+            return;
+        }
+        progress.registered = num_ops;
+        unsafe {
+            ffi::sqlite3_progress_handler(
+                db,
+                num_ops,
+                Some(call_progress),
+                &mut **progress as *mut Progress as *mut c_void,
+            )
+        }
+    }
+
+    fn authorizer<F>(&mut self, authorizer: Option<F>)
+    where
+        F: for<'r> FnMut(AuthContext<'r>) -> Authorization + Send + RefUnwindSafe + 'static,
+    {
+        unsafe extern "C" fn call_boxed_closure<F>(
+            p_arg: *mut c_void,
+            action_code: c_int,
+            param1: *const c_char,
+            param2: *const c_char,
+            db_name: *const c_char,
+            trigger_or_view_name: *const c_char,
+        ) -> c_int
         where
-            F: FnMut() -> bool,
+            F: for<'r> FnMut(AuthContext<'r>) -> Authorization,
         {
             let r = catch_unwind(|| {
-                let boxed_handler: *mut F = p_arg as *mut F;
-                (*boxed_handler)()
+                let auth_ctx = AuthContext {
+                    action: AuthAction::from_raw(
+                        action_code,
+                        expect_optional_utf8(param1, "authorizer param 1"),
+                        expect_optional_utf8(param2, "authorizer param 2"),
+                    ),
+                    database_name: expect_optional_utf8(db_name, "database name"),
+                    accessor: expect_optional_utf8(trigger_or_view_name, "accessor"),
+                };
+                let boxed_authorizer: *mut F = p_arg as *mut F;
+                (*boxed_authorizer)(auth_ctx)
             });
-            if let Ok(true) = r {
-                1
-            } else {
-                0
+            match r {
+                Ok(auth) => auth.into_raw(),
+                // deny on panic or invalid UTF-8
+                Err(_) => ffi::SQLITE_DENY,
             }
         }
 
-        match handler {
-            Some(handler) => {
-                let boxed_handler = Box::new(handler);
+        match authorizer {
+            Some(authorizer) => {
+                let boxed_authorizer = Box::new(authorizer);
                 unsafe {
-                    ffi::sqlite3_progress_handler(
+                    ffi::sqlite3_set_authorizer(
                         self.db(),
-                        num_ops,
                         Some(call_boxed_closure::<F>),
-                        &*boxed_handler as *const F as *mut _,
+                        &*boxed_authorizer as *const F as *mut _,
                     )
-                }
-                self.progress_handler = Some(boxed_handler);
+                };
+                self.authorizer = Some(boxed_authorizer);
             }
             _ => {
-                unsafe { ffi::sqlite3_progress_handler(self.db(), num_ops, None, ptr::null_mut()) }
-                self.progress_handler = None;
+                unsafe { ffi::sqlite3_set_authorizer(self.db(), None, ptr::null_mut()) };
+                self.authorizer = None;
             }
         };
     }
+
+    #[cfg(feature = "modern_sqlite")]
+    fn wal_hook<F>(&mut self, hook: Option<F>)
+    where
+        F: FnMut(&str, c_int) + Send + RefUnwindSafe + 'static,
+    {
+        unsafe extern "C" fn call_boxed_closure<F>(
+            p_arg: *mut c_void,
+            _: *mut ffi::sqlite3,
+            db_str: *const c_char,
+            n_frames: c_int,
+        ) -> c_int
+        where
+            F: FnMut(&str, c_int),
+        {
+            use std::ffi::CStr;
+            use std::str;
+
+            let db_name = {
+                let c_slice = CStr::from_ptr(db_str).to_bytes();
+                str::from_utf8(c_slice)
+            };
+
+            let _ = catch_unwind(|| {
+                let boxed_hook: *mut F = p_arg as *mut F;
+                (*boxed_hook)(db_name.expect("illegal db name"), n_frames);
+            });
+            ffi::SQLITE_OK
+        }
+
+        // SQLite may replace the hook on its own (`PRAGMA wal_autocheckpoint`),
+        // so the closure is owned here rather than freed from the previous
+        // hook argument.
+        match hook {
+            Some(hook) => {
+                let boxed_hook = Box::new(hook);
+                unsafe {
+                    ffi::sqlite3_wal_hook(
+                        self.db(),
+                        Some(call_boxed_closure::<F>),
+                        &*boxed_hook as *const F as *mut _,
+                    )
+                };
+                self.wal_hook = Some(boxed_hook);
+            }
+            _ => {
+                unsafe { ffi::sqlite3_wal_hook(self.db(), None, ptr::null_mut()) };
+                self.wal_hook = None;
+            }
+        };
+    }
+
+    #[cfg(feature = "modern_sqlite")]
+    fn wal_autocheckpoint(&mut self, n: c_int) -> Result<()> {
+        let r = unsafe { ffi::sqlite3_wal_autocheckpoint(self.db(), n) };
+        self.wal_hook = None;
+        self.decode_result(r)
+    }
 }
 
 unsafe fn free_boxed_hook<F>(p: *mut c_void) {
     drop(Box::from_raw(p as *mut F));
 }
 
+unsafe fn expect_optional_utf8<'a>(
+    p_str: *const c_char,
+    description: &'static str,
+) -> Option<&'a str> {
+    if p_str.is_null() {
+        return None;
+    }
+    std::str::from_utf8(std::ffi::CStr::from_ptr(p_str).to_bytes())
+        .unwrap_or_else(|_| panic!("received non-utf8 string as {}", description))
+        .into()
+}
+
 #[cfg(test)]
 mod test {
-    use super::Action;
+    use super::{Action, AuthAction, AuthContext, Authorization, TransactionOperation};
     use crate::{Connection, Result};
     use std::sync::atomic::{AtomicBool, Ordering};
 
@@ -397,4 +1267,242 @@ mod test {
             .unwrap_err();
         Ok(())
     }
+
+    const ENDLESS: &str =
+        "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c) SELECT COUNT(*) FROM c";
+
+    #[test]
+    fn test_with_deadline() -> Result<()> {
+        use crate::Error;
+        use std::time::{Duration, Instant};
+
+        let db = Connection::open_in_memory()?;
+        let start = Instant::now();
+        let r = db.with_timeout(Duration::from_millis(50), |db| {
+            db.query_row(ENDLESS, [], |r| r.get::<_, i64>(0))
+        });
+        assert_eq!(Err(Error::DeadlineExceeded), r);
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // the deadline is gone
+        let n: i64 = db.query_row(
+            "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c LIMIT 100000) SELECT COUNT(*) FROM c",
+            [],
+            |r| r.get(0),
+        )?;
+        assert_eq!(100_000, n);
+
+        // an inner deadline does not extend an outer one
+        let r = db.with_timeout(Duration::from_millis(50), |db| {
+            db.with_timeout(Duration::from_secs(3600), |db| {
+                db.query_row(ENDLESS, [], |r| r.get::<_, i64>(0))
+            })
+        });
+        assert_eq!(Err(Error::DeadlineExceeded), r);
+        Ok(())
+    }
+
+    #[test]
+    fn test_statement_timeout() -> Result<()> {
+        use crate::Error;
+        use std::time::Duration;
+
+        let db = Connection::open_in_memory()?;
+        let mut stmt = db.prepare(ENDLESS)?;
+        stmt.set_timeout(Some(Duration::from_millis(50)));
+        let r = stmt.query_row([], |r| r.get::<_, i64>(0));
+        assert_eq!(Err(Error::DeadlineExceeded), r);
+
+        db.execute_batch("CREATE TABLE foo(x INTEGER)")?;
+        let mut stmt = db.prepare(
+            "INSERT INTO foo WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c) SELECT x FROM c",
+        )?;
+        stmt.set_timeout(Some(Duration::from_millis(50)));
+        assert_eq!(Err(Error::DeadlineExceeded), stmt.execute([]));
+
+        // the timeout covers all the steps of an execution, but not the
+        // statements run in between
+        let mut stmt = db.prepare(
+            "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c) SELECT x FROM c",
+        )?;
+        stmt.set_timeout(Some(Duration::from_millis(50)));
+        let mut rows = stmt.query([])?;
+        rows.next()?;
+        std::thread::sleep(Duration::from_millis(60));
+        let n: i64 = db.query_row(
+            "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c WHERE x < 10000) SELECT COUNT(*) FROM c",
+            [],
+            |r| r.get(0),
+        )?;
+        assert_eq!(10000, n);
+        let r = loop {
+            if let Err(e) = rows.next() {
+                break e;
+            }
+        };
+        assert_eq!(Error::DeadlineExceeded, r);
+        Ok(())
+    }
+
+    #[test]
+    fn test_deadline_with_progress_handler() -> Result<()> {
+        use crate::{Error, ErrorCode};
+        use std::sync::atomic::AtomicUsize;
+        use std::time::Duration;
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        static INTERRUPT: AtomicBool = AtomicBool::new(false);
+        let db = Connection::open_in_memory()?;
+        db.progress_handler(
+            10_000,
+            Some(|| {
+                CALLS.fetch_add(1, Ordering::Relaxed);
+                INTERRUPT.load(Ordering::Relaxed)
+            }),
+        );
+
+        let r = db.with_timeout(Duration::from_millis(50), |db| {
+            db.query_row(ENDLESS, [], |r| r.get::<_, i64>(0))
+        });
+        assert_eq!(Err(Error::DeadlineExceeded), r);
+        assert!(CALLS.load(Ordering::Relaxed) > 0);
+
+        // interrupted by the handler, before the deadline
+        INTERRUPT.store(true, Ordering::Relaxed);
+        let r = db.with_timeout(Duration::from_secs(3600), |db| {
+            db.query_row(ENDLESS, [], |r| r.get::<_, i64>(0))
+        });
+        match r.unwrap_err() {
+            Error::SqliteFailure(err, _) => assert_eq!(ErrorCode::OperationInterrupted, err.code),
+            err => panic!("Unexpected error {}", err),
+        }
+
+        // the handler is still installed without deadline
+        let calls = CALLS.load(Ordering::Relaxed);
+        db.query_row(ENDLESS, [], |r| r.get::<_, i64>(0))
+            .unwrap_err();
+        assert!(CALLS.load(Ordering::Relaxed) > calls);
+        Ok(())
+    }
+
+    #[test]
+    fn test_authorizer() -> Result<()> {
+        use crate::{Error, ErrorCode};
+
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo (public TEXT, private TEXT)")?;
+
+        let authorizer = move |ctx: AuthContext<'_>| match ctx.action {
+            AuthAction::Read {
+                column: "private", ..
+            } => Authorization::Ignore,
+            AuthAction::Pragma {
+                name: "user_version",
+                arg: Some(_),
+            } => Authorization::Deny,
+            AuthAction::Attach { .. } => Authorization::Deny,
+            AuthAction::Function { name: "random" } => panic!("random"),
+            AuthAction::Transaction {
+                operation: TransactionOperation::Commit,
+            } => Authorization::Deny,
+            _ => Authorization::Allow,
+        };
+
+        db.authorizer(Some(authorizer));
+        db.execute_batch(
+            "INSERT INTO foo VALUES ('pub txt', 'priv txt'); INSERT INTO foo VALUES ('foo', 'bar');",
+        )?;
+
+        let private: Option<String> =
+            db.query_row("SELECT private FROM foo LIMIT 1", [], |r| r.get(0))?;
+        assert_eq!(None, private);
+        let public: String = db.query_row("SELECT public FROM foo LIMIT 1", [], |r| r.get(0))?;
+        assert_eq!("pub txt", public);
+
+        db.pragma_query_value(None, "user_version", |r| r.get::<_, i32>(0))?;
+        match db.pragma_update(None, "user_version", &1) {
+            Err(Error::SqliteFailure(err, _)) => {
+                assert_eq!(ErrorCode::AuthorizationForStatementDenied, err.code)
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+        db.execute_batch("ATTACH ':memory:' AS other").unwrap_err();
+        db.execute_batch("SELECT random()").unwrap_err();
+        db.execute_batch("BEGIN")?;
+        db.execute_batch("COMMIT").unwrap_err();
+        db.execute_batch("ROLLBACK")?;
+
+        db.authorizer(None::<fn(AuthContext<'_>) -> Authorization>);
+        db.execute_batch("ATTACH ':memory:' AS other")?;
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "preupdate_hook")]
+    fn test_preupdate_hook() -> Result<()> {
+        use crate::types::ValueRef;
+
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo (t TEXT, i INTEGER)")?;
+        db.execute_batch("INSERT INTO foo VALUES ('lisa', 1)")?;
+
+        let mut called = false;
+        db.preupdate_hook(Some(
+            |action, db: &str, tbl: &str, old, new, case: &super::PreUpdateCase<'_>| {
+                assert_eq!(Action::SQLITE_UPDATE, action);
+                assert_eq!("main", db);
+                assert_eq!("foo", tbl);
+                assert_eq!(1, old);
+                assert_eq!(1, new);
+                assert_eq!(2, case.count());
+                assert_eq!(0, case.depth());
+                assert_eq!(ValueRef::Text(b"lisa"), case.get_old(0).unwrap());
+                assert_eq!(ValueRef::Text(b"bart"), case.get_new(0).unwrap());
+                assert_eq!(ValueRef::Integer(1), case.get_new(1).unwrap());
+                case.get_new(2).unwrap_err();
+                called = true;
+            },
+        ))?;
+        db.execute_batch("UPDATE foo SET t = 'bart'")?;
+        assert!(called);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "modern_sqlite")]
+    fn test_wal_hook() -> Result<()> {
+        use super::CheckpointMode;
+        use crate::MAIN_DB;
+        use std::sync::atomic::AtomicI32;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("wal.db3");
+        let db = Connection::open(&path)?;
+        let mode: String =
+            db.pragma_update_and_check(None, "journal_mode", &"wal", |r| r.get(0))?;
+        assert_eq!("wal", mode);
+
+        static FRAMES: AtomicI32 = AtomicI32::new(0);
+        db.wal_hook(Some(|db_name: &str, n_frames| {
+            assert_eq!("main", db_name);
+            FRAMES.store(n_frames, Ordering::Relaxed);
+        }));
+        db.execute_batch("CREATE TABLE foo (t TEXT); INSERT INTO foo VALUES ('lisa');")?;
+        let frames = FRAMES.load(Ordering::Relaxed);
+        assert!(frames > 0);
+
+        let (log, ckpt) = db.wal_checkpoint_v2(MAIN_DB, CheckpointMode::Passive)?;
+        assert_eq!(frames, log);
+        assert_eq!(frames, ckpt);
+        assert_eq!(
+            (0, 0),
+            db.wal_checkpoint_v2(MAIN_DB, CheckpointMode::Truncate)?
+        );
+
+        db.wal_autocheckpoint(1)?;
+        FRAMES.store(0, Ordering::Relaxed);
+        db.execute_batch("INSERT INTO foo VALUES ('bart')")?;
+        assert_eq!(0, FRAMES.load(Ordering::Relaxed));
+        Ok(())
+    }
 }
@@ -31,8 +31,23 @@ pub struct InnerConnection {
     pub free_rollback_hook: Option<unsafe fn(*mut ::std::os::raw::c_void)>,
     #[cfg(feature = "hooks")]
     pub free_update_hook: Option<unsafe fn(*mut ::std::os::raw::c_void)>,
+    #[cfg(feature = "preupdate_hook")]
+    pub free_preupdate_hook: Option<unsafe fn(*mut ::std::os::raw::c_void)>,
+    // Number of live sessions: they own the preupdate hook slot.
+    #[cfg(feature = "session")]
+    pub sessions: usize,
     #[cfg(feature = "hooks")]
-    pub progress_handler: Option<Box<dyn FnMut() -> bool + Send>>,
+    pub progress: Option<Box<crate::hooks::Progress>>,
+    #[cfg(feature = "hooks")]
+    pub authorizer: Option<crate::hooks::BoxedAuthorizer>,
+    #[cfg(all(feature = "hooks", feature = "modern_sqlite"))]
+    pub wal_hook: Option<crate::hooks::BoxedWalHook>,
+    // Kept until the connection is closed, to trace the close event.
+    #[cfg(all(feature = "trace", feature = "modern_sqlite"))]
+    pub tracer: Option<crate::trace::BoxedTracer>,
+    #[cfg(feature = "statement_checker")]
+    pub statement_checker: Option<crate::checker::StatementChecker>,
+    pub busy: Option<Box<crate::busy::BusyState>>,
     owned: bool,
 }
 
@@ -49,8 +64,21 @@ impl InnerConnection {
             free_rollback_hook: None,
             #[cfg(feature = "hooks")]
             free_update_hook: None,
+            #[cfg(feature = "preupdate_hook")]
+            free_preupdate_hook: None,
+            #[cfg(feature = "session")]
+            sessions: 0,
+            #[cfg(feature = "hooks")]
+            progress: None,
             #[cfg(feature = "hooks")]
-            progress_handler: None,
+            authorizer: None,
+            #[cfg(all(feature = "hooks", feature = "modern_sqlite"))]
+            wal_hook: None,
+            #[cfg(all(feature = "trace", feature = "modern_sqlite"))]
+            tracer: None,
+            #[cfg(feature = "statement_checker")]
+            statement_checker: None,
+            busy: None,
             owned,
         }
     }
@@ -133,6 +161,10 @@ impl InnerConnection {
 
     #[inline]
     pub fn decode_result(&mut self, code: c_int) -> Result<()> {
+        #[cfg(feature = "hooks")]
+        if code & 0xff == ffi::SQLITE_INTERRUPT && self.take_timed_out() {
+            return Err(Error::DeadlineExceeded);
+        }
         unsafe { InnerConnection::decode_result_raw(self.db(), code) }
     }
 
@@ -151,6 +183,11 @@ impl InnerConnection {
             return Ok(());
         }
         self.remove_hooks();
+        if !self.owned {
+            #[cfg(all(feature = "trace", feature = "modern_sqlite"))]
+            self.remove_tracer();
+            self.remove_busy_policy();
+        }
         let mut shared_handle = self.interrupt_lock.lock().unwrap();
         assert!(
             !shared_handle.is_null(),
@@ -301,6 +338,12 @@ impl InnerConnection {
     #[cfg(not(feature = "hooks"))]
     #[inline]
     fn remove_hooks(&mut self) {}
+
+    #[cfg(all(feature = "trace", feature = "modern_sqlite"))]
+    fn remove_tracer(&mut self) {
+        use crate::trace::{TraceEvent, TraceEventCodes};
+        self.trace_v2(TraceEventCodes::empty(), None::<fn(TraceEvent<'_>)>);
+    }
 }
 
 impl Drop for InnerConnection {
@@ -310,6 +353,9 @@ impl Drop for InnerConnection {
         use std::thread::panicking;
 
         if let Err(e) = self.close() {
+            #[cfg(all(feature = "trace", feature = "modern_sqlite"))]
+            self.remove_tracer();
+            self.remove_busy_policy();
             if panicking() {
                 eprintln!("Error while closing SQLite connection: {:?}", e);
             } else {
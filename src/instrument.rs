@@ -0,0 +1,239 @@
+//! `feature = "tracing"` / `feature = "log"` Statement instrumentation.
+//!
+//! Each execution of a statement, from its first step to its reset, is
+//! reported as a `statement` span (with `tracing`) or a debug record (with
+//! `log`), under the `rusqlite` target, with the SQL text, the number of
+//! parameters, the number of rows returned, the number of changes (for
+//! `execute`) and the elapsed time. Without any of these features, this is a
+//! no-op.
+#[cfg(any(feature = "tracing", feature = "log"))]
+use std::time::Instant;
+
+use crate::{Error, Statement};
+
+pub(crate) struct StatementSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    #[cfg(any(feature = "tracing", feature = "log"))]
+    start: Instant,
+    #[cfg(any(feature = "tracing", feature = "log"))]
+    rows: u64,
+    #[cfg(feature = "log")]
+    error: Option<String>,
+}
+
+impl StatementSpan {
+    #[inline]
+    #[allow(unused_variables)]
+    pub(crate) fn new(stmt: &Statement<'_>) -> StatementSpan {
+        StatementSpan {
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(
+                target: "rusqlite",
+                "statement",
+                sql = %sql(stmt),
+                params = stmt.parameter_count() as u64,
+                rows = tracing::field::Empty,
+                changes = tracing::field::Empty,
+                elapsed_us = tracing::field::Empty,
+                error = tracing::field::Empty,
+            ),
+            #[cfg(any(feature = "tracing", feature = "log"))]
+            start: Instant::now(),
+            #[cfg(any(feature = "tracing", feature = "log"))]
+            rows: 0,
+            #[cfg(feature = "log")]
+            error: None,
+        }
+    }
+
+    /// Run `f` (a step of the statement) inside the span.
+    #[inline]
+    pub(crate) fn in_scope<T, F: FnOnce() -> T>(&self, f: F) -> T {
+        #[cfg(feature = "tracing")]
+        {
+            self.span.in_scope(f)
+        }
+        #[cfg(not(feature = "tracing"))]
+        {
+            f()
+        }
+    }
+
+    #[inline]
+    pub(crate) fn row(&mut self) {
+        #[cfg(any(feature = "tracing", feature = "log"))]
+        {
+            self.rows += 1;
+        }
+    }
+
+    #[inline]
+    #[allow(unused_variables)]
+    pub(crate) fn error(&mut self, err: &Error) {
+        #[cfg(feature = "tracing")]
+        self.span.record("error", tracing::field::display(err));
+        #[cfg(feature = "log")]
+        {
+            self.error = Some(err.to_string());
+        }
+    }
+
+    #[inline]
+    #[allow(unused_variables)]
+    pub(crate) fn finish(self, stmt: &Statement<'_>, changes: Option<usize>) {
+        #[cfg(any(feature = "tracing", feature = "log"))]
+        let elapsed = self.start.elapsed();
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("rows", self.rows);
+            if let Some(changes) = changes {
+                self.span.record("changes", changes as u64);
+            }
+            self.span.record("elapsed_us", elapsed.as_micros() as u64);
+        }
+        #[cfg(feature = "log")]
+        log::debug!(
+            target: "rusqlite",
+            "{} params={} rows={} changes={:?} elapsed={:?} error={:?}",
+            sql(stmt),
+            stmt.parameter_count(),
+            self.rows,
+            changes,
+            elapsed,
+            self.error,
+        );
+    }
+}
+
+#[cfg(any(feature = "tracing", feature = "log"))]
+fn sql<'s>(stmt: &'s Statement<'_>) -> std::borrow::Cow<'s, str> {
+    stmt.stmt
+        .sql()
+        .map_or(std::borrow::Cow::Borrowed(""), |sql| sql.to_string_lossy())
+}
+
+#[cfg(all(test, any(feature = "tracing", feature = "log")))]
+mod test {
+    use crate::{Connection, Result};
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn test_log_statements() -> Result<()> {
+        use std::sync::Mutex;
+
+        struct Logger;
+
+        static RECORDS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        impl log::Log for Logger {
+            fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+                metadata.target() == "rusqlite"
+            }
+
+            fn log(&self, record: &log::Record<'_>) {
+                if self.enabled(record.metadata()) {
+                    RECORDS.lock().unwrap().push(record.args().to_string());
+                }
+            }
+
+            fn flush(&self) {}
+        }
+
+        log::set_logger(&Logger).unwrap();
+        log::set_max_level(log::LevelFilter::Debug);
+
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE log_foo(x INTEGER)")?;
+        db.execute("INSERT INTO log_foo VALUES (?)", [1])?;
+        let mut stmt = db.prepare("SELECT x FROM log_foo WHERE x > ?")?;
+        assert_eq!(1, stmt.query_map([0], |r| r.get::<_, i64>(0))?.count());
+
+        let records = RECORDS.lock().unwrap();
+        let insert = records
+            .iter()
+            .find(|r| r.starts_with("INSERT INTO log_foo"))
+            .unwrap();
+        assert!(insert.contains("params=1 rows=0 changes=Some(1)"));
+        let select = records
+            .iter()
+            .find(|r| r.starts_with("SELECT x FROM log_foo"))
+            .unwrap();
+        assert!(select.contains("params=1 rows=1 changes=None"));
+        Ok(())
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_tracing_spans() -> Result<()> {
+        use std::collections::BTreeMap;
+        use std::fmt;
+        use std::sync::{Arc, Mutex};
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        type Fields = BTreeMap<&'static str, String>;
+
+        struct Visitor<'a>(&'a mut Fields);
+
+        impl Visit for Visitor<'_> {
+            fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                self.0.insert(field.name(), format!("{:?}", value));
+            }
+        }
+
+        #[derive(Clone, Default)]
+        struct Collector(Arc<Mutex<Vec<Fields>>>);
+
+        impl Subscriber for Collector {
+            fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+                metadata.target() == "rusqlite"
+            }
+
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut fields = Fields::new();
+                span.record(&mut Visitor(&mut fields));
+                let mut spans = self.0.lock().unwrap();
+                spans.push(fields);
+                Id::from_u64(spans.len() as u64)
+            }
+
+            fn record(&self, span: &Id, values: &Record<'_>) {
+                let mut spans = self.0.lock().unwrap();
+                values.record(&mut Visitor(&mut spans[span.into_u64() as usize - 1]));
+            }
+
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+            fn event(&self, _: &Event<'_>) {}
+
+            fn enter(&self, _: &Id) {}
+
+            fn exit(&self, _: &Id) {}
+        }
+
+        let collector = Collector::default();
+        tracing::subscriber::with_default(collector.clone(), || -> Result<()> {
+            let db = Connection::open_in_memory()?;
+            db.execute_batch("CREATE TABLE foo(x INTEGER)")?;
+            db.execute("INSERT INTO foo VALUES (?)", [1])?;
+            let mut stmt = db.prepare("SELECT x FROM foo WHERE x > ?")?;
+            assert_eq!(1, stmt.query_map([0], |r| r.get::<_, i64>(0))?.count());
+            db.execute("INSERT INTO bar VALUES (?)", [1]).unwrap_err();
+            Ok(())
+        })?;
+
+        let spans = collector.0.lock().unwrap();
+        assert_eq!(2, spans.len());
+        assert_eq!("INSERT INTO foo VALUES (?)", spans[0]["sql"]);
+        assert_eq!("1", spans[0]["params"]);
+        assert_eq!("1", spans[0]["changes"]);
+        assert_eq!("0", spans[0]["rows"]);
+        assert!(spans[0].contains_key("elapsed_us"));
+        assert_eq!("SELECT x FROM foo WHERE x > ?", spans[1]["sql"]);
+        assert_eq!("1", spans[1]["rows"]);
+        assert!(!spans[1].contains_key("changes"));
+        Ok(())
+    }
+}
@@ -0,0 +1,788 @@
+//! `feature = "journal"` Change journal built on the
+//! [session extension](crate::session).
+//!
+//! A [`Journal`] records the changes made through a connection as one
+//! changeset per committed transaction, in an append-only [`JournalStore`]
+//! (a [file](FileStore) or a [side table](TableStore)), along with the commit
+//! time. The journal can then [replay](replay) a base snapshot of the
+//! database forward to a point in time, or [undo](Journal::undo) the most
+//! recent transactions.
+//!
+//! SQLite does not allow the changeset to be generated from inside the commit
+//! hook, so the hook only notes the commit time, and the entry is cut once
+//! the statement which committed has returned. This is done by
+//! [`Journal::execute`], [`Journal::execute_batch`] and
+//! [`Journal::transaction`]. The transactions committed by statements run
+//! directly on the connection are not cut: they are merged into a single
+//! entry, with the time of the last commit, by the next call to
+//! [`Journal::record`] (or to any of these methods).
+//!
+//! Only the tables with a declared `PRIMARY KEY` are journaled: the session
+//! extension ignores the changes to the other ones.
+//!
+//! ```rust,no_run
+//! use rusqlite::journal::{replay, FileStore, Journal};
+//! use rusqlite::{Connection, Result};
+//! use std::time::SystemTime;
+//!
+//! fn journal(db: &Connection, base: &Connection) -> Result<()> {
+//!     let mut journal = Journal::new(db, FileStore::open("changes.journal")?)?;
+//!     journal.execute("INSERT INTO foo VALUES (1, 1)", [])?;
+//!     let before = SystemTime::now();
+//!     journal.execute_batch("BEGIN; UPDATE foo SET x = 2; DELETE FROM bar; COMMIT;")?;
+//!     // bring `base` (a copy of `db` taken before the journal was created)
+//!     // to the state of `db` at `before`
+//!     replay(journal.store_mut(), base, before)?;
+//!     // and revert `db` to this state
+//!     journal.undo(before)?;
+//!     Ok(())
+//! }
+//! ```
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::error_from_sqlite_code;
+use crate::ffi;
+use crate::session::{invert_strm, ConflictAction, Session};
+use crate::{params, Connection, Error, Params, Result, Transaction, TransactionBehavior};
+
+/// `feature = "journal"` A changeset recorded by a [`Journal`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    /// Commit time of the (last) transaction of the changeset, truncated to
+    /// the microsecond.
+    pub timestamp: SystemTime,
+    /// Changeset, as written by
+    /// [`Session::changeset_strm`](crate::session::Session::changeset_strm)
+    pub changeset: Vec<u8>,
+}
+
+/// `feature = "journal"` Append-only storage of journal entries.
+pub trait JournalStore {
+    /// Append `entry` after all the stored entries.
+    fn append(&mut self, entry: &Entry) -> Result<()>;
+
+    /// Returns all the stored entries, oldest first.
+    fn entries(&mut self) -> Result<Vec<Entry>>;
+}
+
+/// `feature = "journal"` Journal entries stored in an append-only file.
+///
+/// Each entry is written as its timestamp (microseconds since the Unix epoch,
+/// as a big-endian `i64`), the length of its changeset (as a big-endian
+/// `u32`), the changeset itself and the CRC-32 of all of these (as a
+/// big-endian `u32`). An entry left incomplete by a crash (or a failed
+/// write) while being appended is removed when the file is opened (or after
+/// the failure).
+#[derive(Debug)]
+pub struct FileStore {
+    file: File,
+    // end of the last complete entry
+    len: u64,
+}
+
+/// Size of an entry without its changeset.
+const ENTRY_OVERHEAD: usize = 16;
+
+impl FileStore {
+    /// Open (or create) the journal file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<FileStore> {
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .map_err(io_error)?;
+        let mut store = FileStore { file, len: 0 };
+        let (_, len) = store.read()?;
+        store.len = len;
+        if store.file.metadata().map_err(io_error)?.len() != len {
+            store.file.set_len(len).map_err(io_error)?;
+        }
+        Ok(store)
+    }
+
+    /// Returns the complete entries at the start of the file, and where they
+    /// end.
+    fn read(&mut self) -> Result<(Vec<Entry>, u64)> {
+        let mut buf = Vec::new();
+        self.file.seek(SeekFrom::Start(0)).map_err(io_error)?;
+        self.file.read_to_end(&mut buf).map_err(io_error)?;
+        let mut entries = Vec::new();
+        let mut rest = &buf[..];
+        while rest.len() >= ENTRY_OVERHEAD {
+            let timestamp = i64::from_be_bytes(rest[..8].try_into().unwrap());
+            let len = u32::from_be_bytes(rest[8..12].try_into().unwrap()) as usize;
+            if rest.len() - ENTRY_OVERHEAD < len {
+                break;
+            }
+            let (data, tail) = rest.split_at(12 + len);
+            let checksum = u32::from_be_bytes(tail[..4].try_into().unwrap());
+            if crc32(data) != checksum {
+                break;
+            }
+            entries.push(Entry {
+                timestamp: from_micros(timestamp),
+                changeset: data[12..].to_vec(),
+            });
+            rest = &tail[4..];
+        }
+        Ok((entries, (buf.len() - rest.len()) as u64))
+    }
+}
+
+impl JournalStore for FileStore {
+    fn append(&mut self, entry: &Entry) -> Result<()> {
+        let len: u32 = entry
+            .changeset
+            .len()
+            .try_into()
+            .map_err(|_| error_from_sqlite_code(ffi::SQLITE_TOOBIG, None))?;
+        let mut buf = Vec::with_capacity(ENTRY_OVERHEAD + entry.changeset.len());
+        buf.extend_from_slice(&to_micros(entry.timestamp).to_be_bytes());
+        buf.extend_from_slice(&len.to_be_bytes());
+        buf.extend_from_slice(&entry.changeset);
+        let checksum = crc32(&buf);
+        buf.extend_from_slice(&checksum.to_be_bytes());
+        let r = self
+            .file
+            .write_all(&buf)
+            .and_then(|_| self.file.sync_data());
+        if let Err(err) = r {
+            // drop what may have been written, so that the next entries are
+            // appended after the last complete one
+            let _ = self.file.set_len(self.len);
+            return Err(io_error(err));
+        }
+        self.len += buf.len() as u64;
+        Ok(())
+    }
+
+    fn entries(&mut self) -> Result<Vec<Entry>> {
+        self.read().map(|(entries, _)| entries)
+    }
+}
+
+/// `feature = "journal"` Journal entries stored in a side table.
+///
+/// The table has an `id` (in append order), a `timestamp` (microseconds since
+/// the Unix epoch) and a `changeset` column. It is accessed through its own
+/// connection (possibly to the journaled database file), so that writing the
+/// entries is not recorded by the journal.
+#[derive(Debug)]
+pub struct TableStore {
+    conn: Connection,
+    table: String,
+}
+
+impl TableStore {
+    /// Store the entries in `table` (created if needed) through `conn`.
+    pub fn new(conn: Connection, table: &str) -> Result<TableStore> {
+        let table = format!("\"{}\"", table.replace('"', "\"\""));
+        conn.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {}(
+                 id INTEGER PRIMARY KEY,
+                 timestamp INTEGER NOT NULL,
+                 changeset BLOB NOT NULL
+             )",
+            table
+        ))?;
+        Ok(TableStore { conn, table })
+    }
+
+    /// Returns the connection used to access the table.
+    #[inline]
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+}
+
+impl JournalStore for TableStore {
+    fn append(&mut self, entry: &Entry) -> Result<()> {
+        self.conn.execute(
+            &format!(
+                "INSERT INTO {}(timestamp, changeset) VALUES (?, ?)",
+                self.table
+            ),
+            params![to_micros(entry.timestamp), entry.changeset],
+        )?;
+        Ok(())
+    }
+
+    fn entries(&mut self) -> Result<Vec<Entry>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT timestamp, changeset FROM {} ORDER BY id",
+            self.table
+        ))?;
+        let entries = stmt.query_map([], |row| {
+            Ok(Entry {
+                timestamp: from_micros(row.get(0)?),
+                changeset: row.get(1)?,
+            })
+        })?;
+        entries.collect()
+    }
+}
+
+/// `feature = "journal"` Records the changes made through a connection,
+/// one entry per committed transaction, to the tables with a `PRIMARY KEY`.
+///
+/// Transactions are only cut into separate entries when they are run
+/// through the journal ([`execute`](Journal::execute),
+/// [`execute_batch`](Journal::execute_batch) or
+/// [`transaction`](Journal::transaction)), see the [module](self)
+/// documentation.
+///
+/// The journal installs a commit hook on the connection, and removes it when
+/// dropped. The connection's commit hook must not be replaced in between.
+pub struct Journal<'conn, S: JournalStore> {
+    conn: &'conn Connection,
+    session: Session<'conn>,
+    committed: Arc<Mutex<Option<SystemTime>>>,
+    store: S,
+}
+
+impl<'conn, S: JournalStore> Journal<'conn, S> {
+    /// Start recording the changes made through `conn` to all the tables of
+    /// its main database into `store`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` with `SQLITE_MISUSE` if a commit hook is already
+    /// registered on `conn`.
+    pub fn new(conn: &'conn Connection, store: S) -> Result<Journal<'conn, S>> {
+        if conn.db.borrow().free_commit_hook.is_some() {
+            return Err(error_from_sqlite_code(
+                ffi::SQLITE_MISUSE,
+                Some("commit hook is already in use".to_owned()),
+            ));
+        }
+        let session = new_session(conn)?;
+        let committed = Arc::new(Mutex::new(None));
+        let c = committed.clone();
+        conn.commit_hook(Some(move || {
+            *c.lock().unwrap() = Some(SystemTime::now());
+            false
+        }));
+        Ok(Journal {
+            conn,
+            session,
+            committed,
+            store,
+        })
+    }
+
+    /// Append the changes of the transactions committed since the last call
+    /// to the store, and returns `true` if there were any.
+    ///
+    /// Must be called outside of any transaction, after each transaction to
+    /// get one entry per transaction: the changes of several transactions
+    /// committed in between are merged into a single entry. If the entry
+    /// cannot be stored, the changes are kept for the next call.
+    pub fn record(&mut self) -> Result<bool> {
+        if !self.conn.is_autocommit() {
+            return Err(error_from_sqlite_code(
+                ffi::SQLITE_MISUSE,
+                Some("cannot record a journal entry inside a transaction".to_owned()),
+            ));
+        }
+        if Arc::strong_count(&self.committed) == 1 {
+            return Err(error_from_sqlite_code(
+                ffi::SQLITE_MISUSE,
+                Some("the journal commit hook has been replaced".to_owned()),
+            ));
+        }
+        let timestamp = match self.committed.lock().unwrap().take() {
+            Some(timestamp) => from_micros(to_micros(timestamp)),
+            None => return Ok(false),
+        };
+        let r = self.append(timestamp);
+        if r.is_err() {
+            self.committed.lock().unwrap().get_or_insert(timestamp);
+        }
+        r
+    }
+
+    fn append(&mut self, timestamp: SystemTime) -> Result<bool> {
+        let mut changeset = Vec::new();
+        self.session.changeset_strm(&mut changeset)?;
+        // changes are recorded from scratch for the next transaction, once
+        // these ones are stored
+        let session = new_session(self.conn)?;
+        let recorded = !changeset.is_empty();
+        if recorded {
+            self.store.append(&Entry {
+                timestamp,
+                changeset,
+            })?;
+        }
+        self.session = session;
+        Ok(recorded)
+    }
+
+    /// Execute a statement on the connection, see [`Connection::execute`],
+    /// and record the transaction it committed, if any, as one entry.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the statement fails, or if its changes cannot be
+    /// recorded. In the latter case, they are recorded by the next call to
+    /// [`record`](Journal::record).
+    pub fn execute<P: Params>(&mut self, sql: &str, params: P) -> Result<usize> {
+        let changes = self.conn.execute(sql, params)?;
+        self.record_committed()?;
+        Ok(changes)
+    }
+
+    /// Execute the statements of `sql` on the connection, see
+    /// [`Connection::execute_batch`], and record each transaction they
+    /// commit as one entry.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a statement fails, or if the changes cannot be
+    /// recorded. In the latter case, they are recorded by the next call to
+    /// [`record`](Journal::record).
+    pub fn execute_batch(&mut self, sql: &str) -> Result<()> {
+        let mut sql = sql;
+        while !sql.is_empty() {
+            let tail = {
+                let stmt = self.conn.prepare(sql)?;
+                if !stmt.stmt.is_null() && stmt.step()? && cfg!(feature = "extra_check") {
+                    // Some PRAGMA may return rows
+                    return Err(Error::ExecuteReturnedResults);
+                }
+                stmt.stmt.tail()
+            };
+            self.record_committed()?;
+            if tail == 0 || tail >= sql.len() {
+                break;
+            }
+            sql = &sql[tail..];
+        }
+        Ok(())
+    }
+
+    /// Record an entry if a transaction has been committed and no other one
+    /// is open.
+    fn record_committed(&mut self) -> Result<()> {
+        if self.conn.is_autocommit() {
+            self.record()?;
+        }
+        Ok(())
+    }
+
+    /// Run `f` in a transaction, committed if `f` succeeds and rolled back
+    /// otherwise, and record its changes as one entry. Pending changes are
+    /// recorded first.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `f` fails, or if the transaction cannot be
+    /// committed or recorded. In the latter case, the committed changes are
+    /// recorded by the next call to [`record`](Journal::record).
+    pub fn transaction<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&Transaction<'_>) -> Result<T>,
+    {
+        self.record()?;
+        let tx = Transaction::new_unchecked(self.conn, TransactionBehavior::Deferred)?;
+        let r = f(&tx)?;
+        tx.commit()?;
+        self.record()?;
+        Ok(r)
+    }
+
+    /// Revert the changes of the transactions committed after `since`, in a
+    /// single transaction, which is itself recorded. Returns the number of
+    /// entries reverted.
+    pub fn undo(&mut self, since: SystemTime) -> Result<usize> {
+        self.record()?;
+        let entries = self.store.entries()?;
+        let tx = Transaction::new_unchecked(self.conn, TransactionBehavior::Immediate)?;
+        let mut n = 0;
+        for entry in entries.iter().rev().take_while(|e| e.timestamp > since) {
+            let mut inverted = Vec::new();
+            invert_strm(&mut entry.changeset.as_slice(), &mut inverted)?;
+            apply(&tx, &inverted)?;
+            n += 1;
+        }
+        tx.commit()?;
+        self.record()?;
+        Ok(n)
+    }
+
+    /// Returns the store of the journal.
+    #[inline]
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Returns the store of the journal.
+    #[inline]
+    pub fn store_mut(&mut self) -> &mut S {
+        &mut self.store
+    }
+}
+
+impl<S: JournalStore> Drop for Journal<'_, S> {
+    fn drop(&mut self) {
+        // The hook holds the other reference: it is not removed if it has
+        // been replaced.
+        if Arc::strong_count(&self.committed) > 1 {
+            self.conn.commit_hook(None::<fn() -> bool>);
+        }
+    }
+}
+
+/// `feature = "journal"` Apply the entries of `store` committed up to
+/// `until` to `db`, in a single transaction. `db` must contain the base
+/// snapshot of the journaled database, as it was when the journal was
+/// created. Returns the number of entries applied.
+pub fn replay<S: JournalStore + ?Sized>(
+    store: &mut S,
+    db: &Connection,
+    until: SystemTime,
+) -> Result<usize> {
+    let entries = store.entries()?;
+    let tx = Transaction::new_unchecked(db, TransactionBehavior::Immediate)?;
+    let mut n = 0;
+    for entry in entries.iter().take_while(|e| e.timestamp <= until) {
+        apply(&tx, &entry.changeset)?;
+        n += 1;
+    }
+    tx.commit()?;
+    Ok(n)
+}
+
+fn new_session(conn: &Connection) -> Result<Session<'_>> {
+    let mut session = Session::new(conn)?;
+    session.attach(None)?;
+    Ok(session)
+}
+
+/// Apply `changeset`, failing on the first conflict.
+fn apply(db: &Connection, changeset: &[u8]) -> Result<()> {
+    db.apply_strm(
+        &mut &changeset[..],
+        None::<fn(&str) -> bool>,
+        |_conflict_type, _item| ConflictAction::SQLITE_CHANGESET_ABORT,
+    )
+}
+
+/// CRC-32 (IEEE) of `data`.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn io_error(err: io::Error) -> crate::Error {
+    error_from_sqlite_code(ffi::SQLITE_IOERR, Some(err.to_string()))
+}
+
+fn to_micros(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_micros() as i64,
+        Err(e) => -(e.duration().as_micros() as i64),
+    }
+}
+
+fn from_micros(micros: i64) -> SystemTime {
+    if micros >= 0 {
+        UNIX_EPOCH + Duration::from_micros(micros as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_micros(micros.wrapping_neg() as u64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread::sleep;
+    use std::time::{Duration, SystemTime};
+
+    use super::{replay, Entry, FileStore, Journal, JournalStore, TableStore};
+    use crate::{ffi, params, Connection, Error, Result};
+
+    const SCHEMA: &str = "CREATE TABLE foo(id INTEGER PRIMARY KEY, x TEXT);";
+
+    fn values(db: &Connection) -> Result<Vec<(i64, String)>> {
+        let mut stmt = db.prepare("SELECT id, x FROM foo ORDER BY id")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    // Two journaled transactions, returning the time between them.
+    fn two_transactions<S: JournalStore>(
+        db: &Connection,
+        journal: &mut Journal<'_, S>,
+    ) -> Result<SystemTime> {
+        journal.transaction(|tx| tx.execute_batch("INSERT INTO foo VALUES (1, 'a'), (2, 'b')"))?;
+        sleep(Duration::from_millis(2));
+        let between = SystemTime::now();
+        sleep(Duration::from_millis(2));
+        db.execute("UPDATE foo SET x = 'c' WHERE id = 1", [])?;
+        assert!(journal.record()?);
+        Ok(between)
+    }
+
+    #[test]
+    fn test_record() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.journal");
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(SCHEMA)?;
+        let mut journal = Journal::new(&db, FileStore::open(&path)?)?;
+        assert!(!journal.record()?);
+
+        two_transactions(&db, &mut journal)?;
+        db.execute_batch("BEGIN; DELETE FROM foo; ROLLBACK;")?;
+        assert!(!journal.record()?);
+
+        db.execute_batch("BEGIN; DELETE FROM foo WHERE id = 2;")?;
+        assert!(journal.record().is_err());
+        db.execute_batch("COMMIT")?;
+        assert!(journal.record()?);
+
+        let entries = journal.store_mut().entries()?;
+        assert_eq!(3, entries.len());
+        assert!(entries[0].timestamp <= entries[1].timestamp);
+        drop(journal);
+
+        // appended to the existing file
+        let mut store = FileStore::open(&path)?;
+        assert_eq!(entries, store.entries()?);
+        let entry = Entry {
+            timestamp: SystemTime::now(),
+            changeset: vec![],
+        };
+        store.append(&entry)?;
+        assert_eq!(4, store.entries()?.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_store_incomplete_entry() -> Result<()> {
+        use std::fs;
+        use std::io::Write;
+        use std::time::UNIX_EPOCH;
+
+        assert_eq!(0xCBF4_3926, super::crc32(b"123456789"));
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.journal");
+        let entry = |n: u8| Entry {
+            timestamp: UNIX_EPOCH + Duration::from_secs(u64::from(n)),
+            changeset: vec![n; 10],
+        };
+        let (first, second, third) = (entry(1), entry(2), entry(3));
+        let mut store = FileStore::open(&path)?;
+        store.append(&first)?;
+        store.append(&second)?;
+        drop(store);
+
+        // the header of the second entry is complete, not its checksum
+        let len = fs::metadata(&path).unwrap().len();
+        let file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(len - 2).unwrap();
+        drop(file);
+        let mut store = FileStore::open(&path)?;
+        assert_eq!(vec![first.clone()], store.entries()?);
+        store.append(&third)?;
+        assert_eq!(vec![first.clone(), third.clone()], store.entries()?);
+        drop(store);
+
+        // a complete entry with a wrong checksum
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 42, 0, 0, 0, 0])
+            .unwrap();
+        drop(file);
+        let mut store = FileStore::open(&path)?;
+        assert_eq!(vec![first.clone(), third.clone()], store.entries()?);
+        store.append(&second)?;
+        assert_eq!(vec![first, third, second], store.entries()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_replay() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(SCHEMA)?;
+        let base = Connection::open_in_memory()?;
+        base.execute_batch(SCHEMA)?;
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = FileStore::open(temp_dir.path().join("test.journal"))?;
+        let mut journal = Journal::new(&db, store)?;
+        let between = two_transactions(&db, &mut journal)?;
+
+        assert_eq!(1, replay(journal.store_mut(), &base, between)?);
+        assert_eq!(
+            vec![(1, "a".to_owned()), (2, "b".to_owned())],
+            values(&base)?
+        );
+
+        let base = Connection::open_in_memory()?;
+        base.execute_batch(SCHEMA)?;
+        assert_eq!(2, replay(journal.store_mut(), &base, SystemTime::now())?);
+        assert_eq!(values(&db)?, values(&base)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let db = Connection::open(&path)?;
+        db.execute_batch(SCHEMA)?;
+        let store = TableStore::new(Connection::open(&path)?, "journal")?;
+        let mut journal = Journal::new(&db, store)?;
+        let between = two_transactions(&db, &mut journal)?;
+
+        assert_eq!(1, journal.undo(between)?);
+        assert_eq!(vec![(1, "a".to_owned()), (2, "b".to_owned())], values(&db)?);
+        // the undo is journaled as well
+        let entries = journal.store_mut().entries()?;
+        assert_eq!(3, entries.len());
+
+        let n: i64 = db.query_row("SELECT COUNT(*) FROM journal", [], |r| r.get(0))?;
+        assert_eq!(3, n);
+        Ok(())
+    }
+
+    #[derive(Default)]
+    struct FailingStore {
+        fail: bool,
+        entries: Vec<Entry>,
+    }
+
+    impl JournalStore for FailingStore {
+        fn append(&mut self, entry: &Entry) -> Result<()> {
+            if self.fail {
+                return Err(Error::SqliteFailure(
+                    ffi::Error::new(ffi::SQLITE_FULL),
+                    None,
+                ));
+            }
+            self.entries.push(entry.clone());
+            Ok(())
+        }
+
+        fn entries(&mut self) -> Result<Vec<Entry>> {
+            Ok(self.entries.clone())
+        }
+    }
+
+    #[test]
+    fn test_transaction() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(SCHEMA)?;
+        let mut journal = Journal::new(&db, FailingStore::default())?;
+
+        // rolled back
+        journal
+            .transaction(|tx| {
+                tx.execute_batch("INSERT INTO foo VALUES (1, 'a')")?;
+                Err::<(), _>(Error::QueryReturnedNoRows)
+            })
+            .unwrap_err();
+        assert!(values(&db)?.is_empty());
+        assert!(!journal.record()?);
+
+        // committed, but not stored
+        journal.store_mut().fail = true;
+        journal
+            .transaction(|tx| tx.execute_batch("INSERT INTO foo VALUES (1, 'a')"))
+            .unwrap_err();
+        assert_eq!(1, values(&db)?.len());
+        journal.record().unwrap_err();
+        journal.store_mut().fail = false;
+        journal.transaction(|tx| tx.execute_batch("INSERT INTO foo VALUES (2, 'b')"))?;
+        // the pending changes are stored before the next transaction
+        assert_eq!(2, journal.store().entries.len());
+
+        let base = Connection::open_in_memory()?;
+        base.execute_batch(SCHEMA)?;
+        let first = journal.store().entries[0].timestamp;
+        assert_eq!(1, replay(journal.store_mut(), &base, first)?);
+        assert_eq!(vec![(1, "a".to_owned())], values(&base)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(SCHEMA)?;
+        db.execute_batch("CREATE TABLE bar(x TEXT)")?;
+        let mut journal = Journal::new(&db, FailingStore::default())?;
+
+        assert_eq!(
+            1,
+            journal.execute("INSERT INTO foo VALUES (?, ?)", params![1, "a"])?
+        );
+        assert_eq!(1, journal.store().entries.len());
+        journal.execute_batch(
+            "BEGIN; INSERT INTO foo VALUES (2, 'b'); UPDATE foo SET x = 'c' WHERE id = 1; COMMIT;
+             INSERT INTO foo VALUES (3, 'd');
+             BEGIN; INSERT INTO foo VALUES (4, 'e');",
+        )?;
+        // the open transaction is not recorded
+        assert_eq!(3, journal.store().entries.len());
+        journal.execute("COMMIT", [])?;
+        assert_eq!(4, journal.store().entries.len());
+
+        // the table without a primary key is not journaled
+        journal.execute("INSERT INTO bar VALUES ('x')", [])?;
+        assert_eq!(4, journal.store().entries.len());
+
+        let base = Connection::open_in_memory()?;
+        base.execute_batch(SCHEMA)?;
+        let second = journal.store().entries[1].timestamp;
+        assert_eq!(2, replay(journal.store_mut(), &base, second)?);
+        assert_eq!(
+            vec![(1, "c".to_owned()), (2, "b".to_owned())],
+            values(&base)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_hook() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(SCHEMA)?;
+        db.commit_hook(Some(|| false));
+        assert!(Journal::new(&db, FailingStore::default()).is_err());
+        db.commit_hook(None::<fn() -> bool>);
+
+        let journal = Journal::new(&db, FailingStore::default())?;
+        drop(journal);
+        // the hook of the journal is removed
+        Journal::new(&db, FailingStore::default())?;
+
+        let mut journal = Journal::new(&db, FailingStore::default())?;
+        let called = Arc::new(AtomicBool::new(false));
+        let c = called.clone();
+        db.commit_hook(Some(move || {
+            c.store(true, Ordering::Relaxed);
+            false
+        }));
+        db.execute_batch("INSERT INTO foo VALUES (1, 'a')")?;
+        journal.record().unwrap_err();
+        drop(journal);
+        // a replacing hook is kept
+        called.store(false, Ordering::Relaxed);
+        db.execute_batch("INSERT INTO foo VALUES (2, 'b')")?;
+        assert!(called.load(Ordering::Relaxed));
+        Ok(())
+    }
+}
@@ -71,56 +71,93 @@ use crate::types::ValueRef;
 
 pub use crate::cache::CachedStatement;
 pub use crate::column::Column;
+#[cfg(feature = "column_metadata")]
+pub use crate::column::ColumnMetadata;
+#[cfg(feature = "serde")]
+pub use crate::de::DeserializedRows;
 pub use crate::error::Error;
 pub use crate::ffi::ErrorCode;
+#[cfg(all(feature = "hooks", feature = "modern_sqlite"))]
+pub use crate::hooks::CheckpointMode;
+#[cfg(feature = "preupdate_hook")]
+pub use crate::hooks::PreUpdateCase;
 #[cfg(feature = "hooks")]
-pub use crate::hooks::Action;
+pub use crate::hooks::{Action, AuthAction, AuthContext, Authorization, TransactionOperation};
 #[cfg(feature = "load_extension")]
 pub use crate::load_extension_guard::LoadExtensionGuard;
+pub use crate::open_options::{JournalMode, OpenOptions, Synchronous};
 pub use crate::params::{params_from_iter, Params, ParamsFromIter};
-pub use crate::row::{AndThenRows, Map, MappedRows, Row, RowIndex, Rows};
+#[cfg(feature = "serde")]
+pub use crate::params::{params_from_serialize, ParamsFromSerialize};
+pub use crate::row::{AndThenRows, FromRow, Map, MappedRows, Row, RowIndex, Rows};
 pub use crate::statement::{Statement, StatementStatus};
-pub use crate::transaction::{DropBehavior, Savepoint, Transaction, TransactionBehavior};
+pub use crate::transaction::{
+    DropBehavior, RetryPolicy, Savepoint, Transaction, TransactionBehavior,
+};
 pub use crate::types::ToSql;
 pub use crate::version::*;
+#[cfg(feature = "derive")]
+pub use rusqlite_derive::FromRow;
 
 #[macro_use]
 mod error;
 
+#[cfg(feature = "async")]
+pub mod async_connection;
 #[cfg(feature = "backup")]
 pub mod backup;
 #[cfg(feature = "blob")]
 pub mod blob;
-mod busy;
+pub mod busy;
 mod cache;
+#[cfg(feature = "statement_checker")]
+pub mod checker;
 #[cfg(feature = "collation")]
 mod collation;
 mod column;
 pub mod config;
 #[cfg(any(feature = "functions", feature = "vtab"))]
 mod context;
+#[cfg(feature = "serde")]
+mod de;
 #[cfg(feature = "functions")]
 pub mod functions;
 #[cfg(feature = "hooks")]
 mod hooks;
 mod inner_connection;
+mod instrument;
+#[cfg(feature = "journal")]
+pub mod journal;
 #[cfg(feature = "limits")]
 pub mod limits;
 #[cfg(feature = "load_extension")]
 mod load_extension_guard;
+#[cfg(feature = "migrations")]
+pub mod migrations;
+mod open_options;
 mod params;
+#[cfg(feature = "pool")]
+pub mod pool;
 mod pragma;
+pub mod query_plan;
 mod raw_statement;
 mod row;
+#[cfg(feature = "serde")]
+mod ser;
+#[cfg(feature = "serialize")]
+pub mod serialize;
 #[cfg(feature = "session")]
 pub mod session;
 mod statement;
+pub mod status;
 #[cfg(feature = "trace")]
 pub mod trace;
 mod transaction;
 pub mod types;
 mod unlock_notify;
 mod version;
+#[cfg(feature = "vfs")]
+pub mod vfs;
 #[cfg(feature = "vtab")]
 pub mod vtab;
 
@@ -305,6 +342,7 @@ pub const TEMP_DB: DatabaseName<'static> = DatabaseName::Temp;
 #[cfg(any(
     feature = "backup",
     feature = "blob",
+    feature = "column_metadata",
     feature = "session",
     feature = "modern_sqlite"
 ))]
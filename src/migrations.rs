@@ -0,0 +1,379 @@
+//! `feature = "migrations"` Schema migrations driven by
+//! [`PRAGMA user_version`](https://sqlite.org/pragma.html#pragma_user_version).
+//!
+//! The `user_version` of the database is the number of migrations which have
+//! been applied: migration `i` (0-based) takes the schema from version `i` to
+//! version `i + 1`.
+//!
+//! ```rust
+//! # use rusqlite::{Connection, Result};
+//! # use rusqlite::migrations::{Migrations, M};
+//! fn migrate(conn: &mut Connection) -> Result<()> {
+//!     let migrations = Migrations::new(vec![
+//!         M::up("CREATE TABLE person(id INTEGER PRIMARY KEY, name TEXT)")
+//!             .down("DROP TABLE person"),
+//!         M::up_with(|tx| {
+//!             tx.execute_batch("ALTER TABLE person ADD COLUMN email TEXT")
+//!         }),
+//!     ]);
+//!     migrations.to_latest(conn)
+//! }
+//! # let mut conn = Connection::open_in_memory().unwrap();
+//! # migrate(&mut conn).unwrap();
+//! ```
+use std::fmt;
+use std::os::raw::c_int;
+
+use crate::error::error_from_sqlite_code;
+use crate::ffi;
+use crate::{Connection, Error, Result, Transaction};
+
+// Not in the bindings of old SQLite versions.
+const SQLITE_CONSTRAINT_FOREIGNKEY: c_int = ffi::SQLITE_CONSTRAINT | (3 << 8);
+
+type StepFn<'u> = dyn Fn(&Transaction<'_>) -> Result<()> + 'u;
+
+enum Step<'u> {
+    Sql(&'u str),
+    Fn(Box<StepFn<'u>>),
+}
+
+impl Step<'_> {
+    fn run(&self, tx: &Transaction<'_>) -> Result<()> {
+        match *self {
+            Step::Sql(sql) => tx.execute_batch(sql),
+            Step::Fn(ref f) => f(tx),
+        }
+    }
+}
+
+impl fmt::Debug for Step<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Step::Sql(sql) => f.debug_tuple("Sql").field(&sql).finish(),
+            Step::Fn(_) => f.debug_tuple("Fn").finish(),
+        }
+    }
+}
+
+/// `feature = "migrations"` A single migration, with an `up` step and an
+/// optional `down` step to revert it.
+#[derive(Debug)]
+pub struct M<'u> {
+    up: Step<'u>,
+    down: Option<Step<'u>>,
+}
+
+impl<'u> M<'u> {
+    /// Create a migration which runs `sql` (with
+    /// [`execute_batch`](Connection::execute_batch)).
+    pub fn up(sql: &'u str) -> M<'u> {
+        M {
+            up: Step::Sql(sql),
+            down: None,
+        }
+    }
+
+    /// Create a migration which calls `f`.
+    pub fn up_with<F>(f: F) -> M<'u>
+    where
+        F: Fn(&Transaction<'_>) -> Result<()> + 'u,
+    {
+        M {
+            up: Step::Fn(Box::new(f)),
+            down: None,
+        }
+    }
+
+    /// Revert this migration by running `sql`.
+    #[must_use]
+    pub fn down(mut self, sql: &'u str) -> M<'u> {
+        self.down = Some(Step::Sql(sql));
+        self
+    }
+
+    /// Revert this migration by calling `f`.
+    #[must_use]
+    pub fn down_with<F>(mut self, f: F) -> M<'u>
+    where
+        F: Fn(&Transaction<'_>) -> Result<()> + 'u,
+    {
+        self.down = Some(Step::Fn(Box::new(f)));
+        self
+    }
+}
+
+/// `feature = "migrations"` Ordered list of migrations.
+#[derive(Debug)]
+pub struct Migrations<'m> {
+    ms: Vec<M<'m>>,
+}
+
+impl<'m> Migrations<'m> {
+    /// Create a set of migrations, applied in order.
+    pub fn new(ms: Vec<M<'m>>) -> Migrations<'m> {
+        Migrations { ms }
+    }
+
+    /// Return the current schema version of the database, i.e. the number of
+    /// applied migrations.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `user_version` cannot be read.
+    pub fn current_version(&self, conn: &Connection) -> Result<usize> {
+        conn.pragma_query_value(None, "user_version", |row| row.get::<_, i64>(0))
+            .map(|v| v as usize)
+    }
+
+    /// Apply all pending migrations.
+    ///
+    /// # Failure
+    ///
+    /// See [`to_version`](Migrations::to_version).
+    pub fn to_latest(&self, conn: &mut Connection) -> Result<()> {
+        self.to_version(conn, self.ms.len())
+    }
+
+    /// Migrate the database up or down to `version`.
+    ///
+    /// All the migrations are run in a single transaction, which is rolled
+    /// back if any of them fails. If foreign keys are enforced, they are
+    /// disabled while migrating, so that tables can be rebuilt, and
+    /// `PRAGMA foreign_key_check` is run after each migration instead.
+    ///
+    /// # Failure
+    ///
+    /// Will return [`Error::MigrationFailed`] with the index of the migration
+    /// if it fails, has no down step when reverting, or leaves foreign key
+    /// violations. Will return `Err` with `SQLITE_MISUSE` if `version`, or
+    /// the current version of the database, is greater than the number of
+    /// migrations.
+    pub fn to_version(&self, conn: &mut Connection, version: usize) -> Result<()> {
+        let current = self.current_version(conn)?;
+        if current > self.ms.len() {
+            return Err(error_from_sqlite_code(
+                ffi::SQLITE_MISUSE,
+                Some(format!(
+                    "database version {} is greater than the number of migrations {}",
+                    current,
+                    self.ms.len()
+                )),
+            ));
+        }
+        if version > self.ms.len() {
+            return Err(error_from_sqlite_code(
+                ffi::SQLITE_MISUSE,
+                Some(format!(
+                    "version {} is greater than the number of migrations {}",
+                    version,
+                    self.ms.len()
+                )),
+            ));
+        }
+        if version == current {
+            return Ok(());
+        }
+        // `PRAGMA foreign_keys` is a no-op inside a transaction.
+        let foreign_keys: bool = conn.pragma_query_value(None, "foreign_keys", |r| r.get(0))?;
+        if foreign_keys {
+            conn.pragma_update(None, "foreign_keys", &false)?;
+        }
+        let r = self.migrate(conn, current, version, foreign_keys);
+        if foreign_keys {
+            conn.pragma_update(None, "foreign_keys", &true)?;
+        }
+        r
+    }
+
+    fn migrate(
+        &self,
+        conn: &mut Connection,
+        current: usize,
+        version: usize,
+        check_foreign_keys: bool,
+    ) -> Result<()> {
+        let tx = conn.transaction()?;
+        if version > current {
+            for i in current..version {
+                self.ms[i]
+                    .up
+                    .run(&tx)
+                    .and_then(|_| check(&tx, check_foreign_keys))
+                    .map_err(|err| Error::MigrationFailed(i, Box::new(err)))?;
+            }
+        } else {
+            for i in (version..current).rev() {
+                match self.ms[i].down {
+                    Some(ref down) => down.run(&tx),
+                    None => Err(error_from_sqlite_code(
+                        ffi::SQLITE_MISUSE,
+                        Some("no down step".to_owned()),
+                    )),
+                }
+                .and_then(|_| check(&tx, check_foreign_keys))
+                .map_err(|err| Error::MigrationFailed(i, Box::new(err)))?;
+            }
+        }
+        tx.pragma_update(None, "user_version", &(version as i64))?;
+        tx.commit()
+    }
+
+    /// Apply all the migrations to a new in-memory database, then, if they
+    /// can all be reverted, revert them and apply them again.
+    ///
+    /// # Failure
+    ///
+    /// See [`to_version`](Migrations::to_version).
+    pub fn validate(&self) -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+        self.to_latest(&mut conn)?;
+        if self.ms.iter().all(|m| m.down.is_some()) {
+            self.to_version(&mut conn, 0)?;
+            self.to_latest(&mut conn)?;
+        }
+        Ok(())
+    }
+}
+
+fn check(tx: &Transaction<'_>, foreign_keys: bool) -> Result<()> {
+    if !foreign_keys {
+        return Ok(());
+    }
+    let mut stmt = tx.prepare("PRAGMA foreign_key_check")?;
+    let mut rows = stmt.query([])?;
+    if let Some(row) = rows.next()? {
+        let table: String = row.get(0)?;
+        let parent: String = row.get(2)?;
+        return Err(error_from_sqlite_code(
+            SQLITE_CONSTRAINT_FOREIGNKEY,
+            Some(format!(
+                "foreign key violation from table {} to table {}",
+                table, parent
+            )),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Migrations, M};
+    use crate::{Connection, Error, ErrorCode, Result};
+
+    fn migrations() -> Migrations<'static> {
+        Migrations::new(vec![
+            M::up("CREATE TABLE person(id INTEGER PRIMARY KEY, name TEXT NOT NULL)")
+                .down("DROP TABLE person"),
+            M::up(
+                "CREATE TABLE pet(id INTEGER PRIMARY KEY, \
+                 owner INTEGER NOT NULL REFERENCES person(id));",
+            )
+            .down("DROP TABLE pet"),
+            // Table rebuild, with foreign keys pointing to the table.
+            M::up_with(|tx| {
+                tx.execute_batch(
+                    "CREATE TABLE new_person(id INTEGER PRIMARY KEY, name TEXT NOT NULL, \
+                     age INTEGER);
+                     INSERT INTO new_person(id, name) SELECT id, name FROM person;
+                     DROP TABLE person;
+                     ALTER TABLE new_person RENAME TO person;",
+                )
+            })
+            .down(
+                "CREATE TABLE old_person(id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+                 INSERT INTO old_person SELECT id, name FROM person;
+                 DROP TABLE person;
+                 ALTER TABLE old_person RENAME TO person;",
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_to_latest_and_back() -> Result<()> {
+        let mut db = Connection::open_in_memory()?;
+        db.execute_batch("PRAGMA foreign_keys = ON")?;
+        let ms = migrations();
+        assert_eq!(0, ms.current_version(&db)?);
+
+        ms.to_version(&mut db, 2)?;
+        assert_eq!(2, ms.current_version(&db)?);
+        db.execute_batch("INSERT INTO person VALUES (1, 'Alice'); INSERT INTO pet VALUES (1, 1);")?;
+
+        ms.to_latest(&mut db)?;
+        assert_eq!(3, ms.current_version(&db)?);
+        let fk: bool = db.pragma_query_value(None, "foreign_keys", |r| r.get(0))?;
+        assert!(fk);
+        let owner: String = db.query_row(
+            "SELECT person.name FROM pet JOIN person ON pet.owner = person.id",
+            [],
+            |r| r.get(0),
+        )?;
+        assert_eq!("Alice", owner);
+
+        ms.to_version(&mut db, 1)?;
+        assert_eq!(1, ms.current_version(&db)?);
+        db.execute_batch("SELECT * FROM pet").unwrap_err();
+        Ok(())
+    }
+
+    #[test]
+    fn test_failing_migration() -> Result<()> {
+        let mut db = Connection::open_in_memory()?;
+        db.execute_batch("PRAGMA foreign_keys = ON")?;
+        let ms = Migrations::new(vec![
+            M::up("CREATE TABLE foo(x INTEGER)"),
+            M::up("INSERT INTO missing VALUES (1)"),
+        ]);
+        match ms.to_latest(&mut db).unwrap_err() {
+            Error::MigrationFailed(1, err) => assert!(matches!(*err, Error::SqliteFailure(..))),
+            err => panic!("unexpected error {}", err),
+        }
+        // Rolled back.
+        assert_eq!(0, ms.current_version(&db)?);
+        db.execute_batch("SELECT * FROM foo").unwrap_err();
+
+        // No down step.
+        Migrations::new(vec![M::up("CREATE TABLE foo(x INTEGER)")]).to_latest(&mut db)?;
+        let err = ms.to_version(&mut db, 0).unwrap_err();
+        assert!(matches!(err, Error::MigrationFailed(0, _)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_foreign_key_violation() -> Result<()> {
+        let mut db = Connection::open_in_memory()?;
+        db.execute_batch("PRAGMA foreign_keys = ON")?;
+        let ms = Migrations::new(vec![
+            M::up(
+                "CREATE TABLE person(id INTEGER PRIMARY KEY);
+                 CREATE TABLE pet(owner INTEGER REFERENCES person(id));",
+            ),
+            M::up("INSERT INTO pet VALUES (42)"),
+        ]);
+        match ms.to_latest(&mut db).unwrap_err() {
+            Error::MigrationFailed(1, err) => match *err {
+                Error::SqliteFailure(err, _) => {
+                    assert_eq!(ErrorCode::ConstraintViolation, err.code)
+                }
+                err => panic!("unexpected error {}", err),
+            },
+            err => panic!("unexpected error {}", err),
+        }
+        let fk: bool = db.pragma_query_value(None, "foreign_keys", |r| r.get(0))?;
+        assert!(fk);
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate() {
+        migrations().validate().unwrap();
+        Migrations::new(vec![M::up("CREATE TABLE")])
+            .validate()
+            .unwrap_err();
+        let err = migrations()
+            .to_version(&mut Connection::open_in_memory().unwrap(), 4)
+            .unwrap_err();
+        assert!(matches!(err, Error::SqliteFailure(..)));
+    }
+}
@@ -0,0 +1,522 @@
+//! Builder for opening and configuring a [`Connection`] in one go.
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config::DbConfig;
+use crate::error::error_from_sqlite_code;
+use crate::ffi;
+use crate::{Connection, Error, OpenFlags, Result};
+
+type InitFn = dyn Fn(&mut Connection) -> Result<()> + Send + Sync;
+
+/// Value of the [`journal_mode`](https://sqlite.org/pragma.html#pragma_journal_mode)
+/// pragma.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum JournalMode {
+    /// Delete the rollback journal at the end of each transaction.
+    Delete,
+    /// Truncate the rollback journal to zero-length instead of deleting it.
+    Truncate,
+    /// Overwrite the rollback journal header with zeros instead of deleting
+    /// it.
+    Persist,
+    /// Store the rollback journal in volatile RAM.
+    Memory,
+    /// Use a write-ahead log instead of a rollback journal.
+    Wal,
+    /// Disable the rollback journal.
+    Off,
+}
+
+impl JournalMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            JournalMode::Delete => "DELETE",
+            JournalMode::Truncate => "TRUNCATE",
+            JournalMode::Persist => "PERSIST",
+            JournalMode::Memory => "MEMORY",
+            JournalMode::Wal => "WAL",
+            JournalMode::Off => "OFF",
+        }
+    }
+}
+
+/// Value of the [`synchronous`](https://sqlite.org/pragma.html#pragma_synchronous)
+/// pragma.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Synchronous {
+    /// Hand off data to the operating system without syncing.
+    Off,
+    /// Sync at the most critical moments only.
+    Normal,
+    /// Sync to ensure that a power loss cannot corrupt the database.
+    Full,
+    /// Like `Full`, and also sync the directory of the rollback journal.
+    Extra,
+}
+
+impl Synchronous {
+    fn as_str(self) -> &'static str {
+        match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+            Synchronous::Extra => "EXTRA",
+        }
+    }
+}
+
+enum InitStep {
+    Sql(String),
+    Fn(Box<InitFn>),
+}
+
+/// Options and flags which can be used to configure how a [`Connection`] is
+/// opened.
+///
+/// Once the database is open, settings are applied in this order,
+/// regardless of the order in which they were given:
+///
+/// 1. [`busy_timeout`](OpenOptions::busy_timeout),
+/// 2. [`db_config`](OpenOptions::db_config) values, in the order given,
+/// 3. [`journal_mode`](OpenOptions::journal_mode),
+/// 4. [`synchronous`](OpenOptions::synchronous),
+/// 5. [`foreign_keys`](OpenOptions::foreign_keys),
+/// 6. [`cache_size`](OpenOptions::cache_size),
+/// 7. [`mmap_size`](OpenOptions::mmap_size),
+/// 8. [`init_sql`](OpenOptions::init_sql) and [`init`](OpenOptions::init)
+///    steps, in the order given.
+///
+/// If any step fails, the connection is closed and
+/// [`Error::OpenStepFailed`] is returned with the name of the step.
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, JournalMode, OpenOptions, Result};
+/// # use std::time::Duration;
+/// fn open() -> Result<Connection> {
+///     OpenOptions::new()
+///         .busy_timeout(Duration::from_secs(1))
+///         .journal_mode(JournalMode::Wal)
+///         .foreign_keys(true)
+///         .init_sql("CREATE TABLE IF NOT EXISTS foo(x INTEGER)")
+///         .open("app.db")
+/// }
+/// ```
+pub struct OpenOptions {
+    flags: OpenFlags,
+    uri_params: Vec<(String, String)>,
+    memory_name: Option<String>,
+    busy_timeout: Option<Duration>,
+    db_config: Vec<(DbConfig, bool)>,
+    journal_mode: Option<JournalMode>,
+    synchronous: Option<Synchronous>,
+    foreign_keys: Option<bool>,
+    cache_size: Option<i64>,
+    mmap_size: Option<i64>,
+    init: Vec<InitStep>,
+}
+
+impl Default for OpenOptions {
+    fn default() -> OpenOptions {
+        OpenOptions::new()
+    }
+}
+
+impl OpenOptions {
+    /// Create a blank set of options, using [`OpenFlags::default`].
+    pub fn new() -> OpenOptions {
+        OpenOptions {
+            flags: OpenFlags::default(),
+            uri_params: Vec::new(),
+            memory_name: None,
+            busy_timeout: None,
+            db_config: Vec::new(),
+            journal_mode: None,
+            synchronous: None,
+            foreign_keys: None,
+            cache_size: None,
+            mmap_size: None,
+            init: Vec::new(),
+        }
+    }
+
+    /// Set the flags passed to `sqlite3_open_v2`.
+    #[must_use]
+    pub fn flags(mut self, flags: OpenFlags) -> OpenOptions {
+        self.flags = flags;
+        self
+    }
+
+    /// Add a [URI parameter](https://sqlite.org/uri.html#recognized_query_parameters).
+    ///
+    /// The path is then turned into a `file:` URI, and
+    /// [`OpenFlags::SQLITE_OPEN_URI`] is set.
+    #[must_use]
+    pub fn uri_param(mut self, key: &str, value: &str) -> OpenOptions {
+        self.uri_params.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Set the `mode` URI parameter (`ro`, `rw`, `rwc` or `memory`).
+    #[must_use]
+    pub fn mode(self, mode: &str) -> OpenOptions {
+        self.uri_param("mode", mode)
+    }
+
+    /// Set the `cache` URI parameter to `shared` or `private`.
+    #[must_use]
+    pub fn shared_cache(self, shared: bool) -> OpenOptions {
+        self.uri_param("cache", if shared { "shared" } else { "private" })
+    }
+
+    /// Set the `immutable` URI parameter: the database file is assumed not to
+    /// change, even from other processes.
+    #[must_use]
+    pub fn immutable(self, immutable: bool) -> OpenOptions {
+        self.uri_param("immutable", if immutable { "1" } else { "0" })
+    }
+
+    /// Set the `vfs` URI parameter.
+    #[must_use]
+    pub fn vfs(self, vfs: &str) -> OpenOptions {
+        self.uri_param("vfs", vfs)
+    }
+
+    /// Use a named in-memory database, shared by all the connections opened
+    /// with the same `name` in the process, when calling
+    /// [`open_in_memory`](OpenOptions::open_in_memory).
+    #[must_use]
+    pub fn shared_memory(mut self, name: &str) -> OpenOptions {
+        self.memory_name = Some(name.to_owned());
+        self
+    }
+
+    /// Set the busy timeout (see [`Connection::busy_timeout`]).
+    #[must_use]
+    pub fn busy_timeout(mut self, timeout: Duration) -> OpenOptions {
+        self.busy_timeout = Some(timeout);
+        self
+    }
+
+    /// Set a database configuration option (see
+    /// [`Connection::set_db_config`]).
+    #[must_use]
+    pub fn db_config(mut self, config: DbConfig, value: bool) -> OpenOptions {
+        self.db_config.push((config, value));
+        self
+    }
+
+    /// Set the `journal_mode` pragma. Opening fails if the database ends up
+    /// in a different mode (e.g. `Wal` for an in-memory database).
+    #[must_use]
+    pub fn journal_mode(mut self, mode: JournalMode) -> OpenOptions {
+        self.journal_mode = Some(mode);
+        self
+    }
+
+    /// Set the `synchronous` pragma.
+    #[must_use]
+    pub fn synchronous(mut self, synchronous: Synchronous) -> OpenOptions {
+        self.synchronous = Some(synchronous);
+        self
+    }
+
+    /// Set the `foreign_keys` pragma.
+    #[must_use]
+    pub fn foreign_keys(mut self, enabled: bool) -> OpenOptions {
+        self.foreign_keys = Some(enabled);
+        self
+    }
+
+    /// Set the `cache_size` pragma: a number of pages if positive, or of
+    /// KiB if negative.
+    #[must_use]
+    pub fn cache_size(mut self, size: i64) -> OpenOptions {
+        self.cache_size = Some(size);
+        self
+    }
+
+    /// Set the `mmap_size` pragma, in bytes.
+    #[must_use]
+    pub fn mmap_size(mut self, size: i64) -> OpenOptions {
+        self.mmap_size = Some(size);
+        self
+    }
+
+    /// Add SQL statements to run (with
+    /// [`execute_batch`](Connection::execute_batch)) once the connection is
+    /// configured.
+    #[must_use]
+    pub fn init_sql(mut self, sql: &str) -> OpenOptions {
+        self.init.push(InitStep::Sql(sql.to_owned()));
+        self
+    }
+
+    /// Add a closure to call once the connection is configured.
+    #[must_use]
+    pub fn init<F>(mut self, f: F) -> OpenOptions
+    where
+        F: Fn(&mut Connection) -> Result<()> + Send + Sync + 'static,
+    {
+        self.init.push(InitStep::Fn(Box::new(f)));
+        self
+    }
+
+    /// Open and configure a connection to the database at `path`.
+    ///
+    /// # Failure
+    ///
+    /// Will return [`Error::OpenStepFailed`] naming the step (`"open"` if
+    /// the database itself cannot be opened) and wrapping the underlying
+    /// error.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<Connection> {
+        let path = path.as_ref();
+        let conn = if self.uri_params.is_empty() {
+            Connection::open_with_flags(path, self.flags)
+        } else {
+            path.to_str()
+                .ok_or_else(|| Error::InvalidPath(path.to_owned()))
+                .and_then(|path| self.open_uri(path, false))
+        };
+        self.configure(step("open", conn)?)
+    }
+
+    /// Open and configure a connection to an in-memory database, named if
+    /// [`shared_memory`](OpenOptions::shared_memory) was called.
+    ///
+    /// # Failure
+    ///
+    /// See [`open`](OpenOptions::open).
+    pub fn open_in_memory(&self) -> Result<Connection> {
+        let conn = match self.memory_name {
+            Some(ref name) => self.open_uri(name, true),
+            None if self.uri_params.is_empty() => Connection::open_in_memory_with_flags(self.flags),
+            None => self.open_uri(":memory:", false),
+        };
+        self.configure(step("open", conn)?)
+    }
+
+    fn open_uri(&self, path: &str, shared_memory: bool) -> Result<Connection> {
+        let mut uri = String::with_capacity(path.len() + 5);
+        if path.starts_with("file:") {
+            uri.push_str(path);
+        } else {
+            uri.push_str("file:");
+            push_encoded(&mut uri, path);
+        }
+        let mut params = Vec::with_capacity(self.uri_params.len() + 2);
+        if shared_memory {
+            params.push(("mode", "memory"));
+            params.push(("cache", "shared"));
+        }
+        params.extend(
+            self.uri_params
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str())),
+        );
+        let mut sep = if uri.contains('?') { '&' } else { '?' };
+        for (key, value) in params {
+            uri.push(sep);
+            push_encoded(&mut uri, key);
+            uri.push('=');
+            push_encoded(&mut uri, value);
+            sep = '&';
+        }
+        Connection::open_with_flags(uri, self.flags | OpenFlags::SQLITE_OPEN_URI)
+    }
+
+    fn configure(&self, mut conn: Connection) -> Result<Connection> {
+        if let Some(timeout) = self.busy_timeout {
+            step("busy_timeout", conn.busy_timeout(timeout))?;
+        }
+        for &(config, value) in &self.db_config {
+            step(
+                &format!("db_config({:?})", config),
+                conn.set_db_config(config, value),
+            )?;
+        }
+        if let Some(mode) = self.journal_mode {
+            // SQLite silently keeps the current mode if the requested one is
+            // not supported (WAL for an in-memory database, ...).
+            let r = conn
+                .pragma_update_and_check(None, "journal_mode", &mode.as_str(), |r| {
+                    r.get::<_, String>(0)
+                })
+                .and_then(|actual| {
+                    if actual.eq_ignore_ascii_case(mode.as_str()) {
+                        Ok(())
+                    } else {
+                        Err(error_from_sqlite_code(
+                            ffi::SQLITE_ERROR,
+                            Some(format!("journal_mode is {}", actual)),
+                        ))
+                    }
+                });
+            step("journal_mode", r)?;
+        }
+        if let Some(synchronous) = self.synchronous {
+            step(
+                "synchronous",
+                conn.pragma_update(None, "synchronous", &synchronous.as_str()),
+            )?;
+        }
+        if let Some(enabled) = self.foreign_keys {
+            step(
+                "foreign_keys",
+                conn.pragma_update(None, "foreign_keys", &enabled),
+            )?;
+        }
+        if let Some(size) = self.cache_size {
+            step("cache_size", conn.pragma_update(None, "cache_size", &size))?;
+        }
+        if let Some(size) = self.mmap_size {
+            // The new size is returned, unless memory-mapped I/O is disabled.
+            let r = conn.pragma_update_and_check(None, "mmap_size", &size, |_| Ok(()));
+            step(
+                "mmap_size",
+                match r {
+                    Err(Error::QueryReturnedNoRows) => Ok(()),
+                    r => r,
+                },
+            )?;
+        }
+        for (i, init) in self.init.iter().enumerate() {
+            let r = match init {
+                InitStep::Sql(sql) => conn.execute_batch(sql),
+                InitStep::Fn(f) => f(&mut conn),
+            };
+            step(&format!("init[{}]", i), r)?;
+        }
+        Ok(conn)
+    }
+}
+
+impl fmt::Debug for OpenOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OpenOptions")
+            .field("flags", &self.flags)
+            .field("uri_params", &self.uri_params)
+            .field("memory_name", &self.memory_name)
+            .field("busy_timeout", &self.busy_timeout)
+            .field("db_config", &self.db_config)
+            .field("journal_mode", &self.journal_mode)
+            .field("synchronous", &self.synchronous)
+            .field("foreign_keys", &self.foreign_keys)
+            .field("cache_size", &self.cache_size)
+            .field("mmap_size", &self.mmap_size)
+            .field("init", &self.init.len())
+            .finish()
+    }
+}
+
+fn step<T>(name: &str, r: Result<T>) -> Result<T> {
+    r.map_err(|err| Error::OpenStepFailed(name.to_owned(), Box::new(err)))
+}
+
+// Percent-encode the characters which have a special meaning in a URI.
+fn push_encoded(uri: &mut String, s: &str) {
+    for c in s.chars() {
+        match c {
+            '%' | '?' | '#' | '&' | '=' => uri.push_str(&format!("%{:02X}", c as u32)),
+            _ => uri.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{JournalMode, OpenOptions, Synchronous};
+    use crate::config::DbConfig;
+    use crate::{Error, Result};
+    use std::time::Duration;
+
+    #[test]
+    fn test_open_options() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = OpenOptions::new()
+            .busy_timeout(Duration::from_millis(100))
+            .db_config(DbConfig::SQLITE_DBCONFIG_ENABLE_TRIGGER, false)
+            .journal_mode(JournalMode::Wal)
+            .synchronous(Synchronous::Normal)
+            .foreign_keys(true)
+            .cache_size(-1024)
+            .mmap_size(0)
+            .init_sql("CREATE TABLE foo(x INTEGER)")
+            .init(|conn| conn.execute_batch("INSERT INTO foo VALUES (42)"))
+            .open(temp_dir.path().join("test.db3"))?;
+
+        let mode: String = db.query_row("PRAGMA journal_mode", [], |r| r.get(0))?;
+        assert_eq!("wal", mode);
+        let synchronous: i64 = db.query_row("PRAGMA synchronous", [], |r| r.get(0))?;
+        assert_eq!(1, synchronous);
+        let fk: bool = db.query_row("PRAGMA foreign_keys", [], |r| r.get(0))?;
+        assert!(fk);
+        let cache_size: i64 = db.query_row("PRAGMA cache_size", [], |r| r.get(0))?;
+        assert_eq!(-1024, cache_size);
+        assert!(!db.db_config(DbConfig::SQLITE_DBCONFIG_ENABLE_TRIGGER)?);
+        let x: i64 = db.query_row("SELECT x FROM foo", [], |r| r.get(0))?;
+        assert_eq!(42, x);
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_memory() -> Result<()> {
+        let options = OpenOptions::new().shared_memory("test_shared_memory");
+        let db1 = options.open_in_memory()?;
+        db1.execute_batch("CREATE TABLE foo(x INTEGER)")?;
+        let db2 = options.open_in_memory()?;
+        db2.execute_batch("INSERT INTO foo VALUES (1)")?;
+
+        // A private in-memory database is not shared.
+        let db3 = OpenOptions::new().open_in_memory()?;
+        db3.execute_batch("SELECT * FROM foo").unwrap_err();
+        Ok(())
+    }
+
+    #[test]
+    fn test_uri_params() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test?.db3");
+        OpenOptions::new()
+            .init_sql("CREATE TABLE foo(x INTEGER)")
+            .open(&path)?;
+        let db = OpenOptions::new().mode("ro").open(&path)?;
+        db.execute_batch("SELECT * FROM foo")?;
+        db.execute_batch("INSERT INTO foo VALUES (1)").unwrap_err();
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_step() {
+        let err = OpenOptions::new()
+            .foreign_keys(true)
+            .init_sql("PRAGMA user_version = 1")
+            .init_sql("INSERT INTO missing VALUES (1)")
+            .open_in_memory()
+            .unwrap_err();
+        match err {
+            Error::OpenStepFailed(ref step, ref err) => {
+                assert_eq!("init[1]", step);
+                assert!(matches!(**err, Error::SqliteFailure(..)));
+            }
+            err => panic!("unexpected error {}", err),
+        }
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let err = OpenOptions::new()
+            .mode("ro")
+            .open(temp_dir.path().join("missing.db3"))
+            .unwrap_err();
+        assert!(matches!(err, Error::OpenStepFailed(ref step, _) if step == "open"));
+
+        // WAL is not supported by in-memory databases.
+        let err = OpenOptions::new()
+            .journal_mode(JournalMode::Wal)
+            .open_in_memory()
+            .unwrap_err();
+        assert!(matches!(err, Error::OpenStepFailed(ref step, _) if step == "journal_mode"));
+    }
+}
@@ -1,4 +1,8 @@
+#[cfg(feature = "serde")]
+use crate::Error;
 use crate::{Result, Statement, ToSql};
+#[cfg(feature = "serde")]
+use serde::Serialize;
 
 mod sealed {
     /// This trait exists just to ensure that the only impls of `trait Params`
@@ -355,3 +359,83 @@ where
         stmt.bind_parameters(self.0)
     }
 }
+
+/// `feature = "serde"` Adapter type which allows any [`Serialize`] struct (or
+/// map) to implement [`Params`], binding each field to the named parameter of
+/// the same name (`:field`, `@field` or `$field`).
+///
+/// This struct is created by the [`params_from_serialize`] function.
+///
+/// Fields must serialize to a single SQL value: an integer, float, string,
+/// byte buffer, `None` (for `NULL`), a newtype around one of those, or a unit
+/// enum variant (bound as its name).
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, Result, params_from_serialize};
+/// #[derive(serde::Serialize)]
+/// struct Person {
+///     name: String,
+///     age: Option<u8>,
+/// }
+///
+/// fn insert(conn: &Connection, person: &Person) -> Result<usize> {
+///     conn.execute(
+///         "INSERT INTO person (name, age) VALUES (:name, :age)",
+///         params_from_serialize(person),
+///     )
+/// }
+/// ```
+///
+/// # Failure
+///
+/// Binding fails with [`Error::InvalidParameterName`] if a field has no
+/// matching parameter in the SQL, and with [`Error::InvalidParameterCount`]
+/// if some parameters are not bound by any field.
+#[cfg(feature = "serde")]
+#[derive(Clone, Copy, Debug)]
+pub struct ParamsFromSerialize<'a, T: ?Sized>(&'a T);
+
+/// `feature = "serde"` Constructor function for a [`ParamsFromSerialize`].
+/// See its documentation for more.
+#[cfg(feature = "serde")]
+#[inline]
+pub fn params_from_serialize<T>(value: &T) -> ParamsFromSerialize<'_, T>
+where
+    T: Serialize + ?Sized,
+{
+    ParamsFromSerialize(value)
+}
+
+#[cfg(feature = "serde")]
+impl<T> Sealed for ParamsFromSerialize<'_, T> where T: Serialize + ?Sized {}
+
+#[cfg(feature = "serde")]
+impl<T> Params for ParamsFromSerialize<'_, T>
+where
+    T: Serialize + ?Sized,
+{
+    fn bind_in(self, stmt: &mut Statement<'_>) -> Result<()> {
+        let values = crate::ser::to_named_values(self.0)?;
+        for (name, value) in &values {
+            let mut index = None;
+            for prefix in &[":", "@", "$"] {
+                index = stmt.parameter_index(&format!("{}{}", prefix, name))?;
+                if index.is_some() {
+                    break;
+                }
+            }
+            match index {
+                Some(i) => stmt.raw_bind_parameter(i, value)?,
+                None => return Err(Error::InvalidParameterName(format!(":{}", name))),
+            }
+        }
+        let expected = stmt.parameter_count();
+        if values.len() != expected {
+            Err(Error::InvalidParameterCount(values.len(), expected))
+        } else {
+            Ok(())
+        }
+    }
+}
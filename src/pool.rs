@@ -0,0 +1,458 @@
+//! `feature = "pool"` Connection pool for WAL databases.
+//!
+//! SQLite allows only one writer at a time, but in
+//! [WAL mode](https://sqlite.org/wal.html) readers do not block the writer
+//! nor each other. A [`Pool`] therefore hands out a single exclusive writer
+//! connection and up to N read-only connections, opened with
+//! [`OpenFlags::SQLITE_OPEN_READ_ONLY`].
+//!
+//! ```rust,no_run
+//! # use rusqlite::{pool::Pool, Result};
+//! # use std::time::Duration;
+//! fn pool() -> Result<Pool> {
+//!     let pool = Pool::builder("app.db")
+//!         .readers(8)
+//!         .checkout_timeout(Duration::from_secs(5))
+//!         .init(|conn| conn.execute_batch("PRAGMA foreign_keys = ON"))
+//!         .build()?;
+//!     pool.writer()?
+//!         .execute_batch("CREATE TABLE IF NOT EXISTS foo(x INTEGER)")?;
+//!     let n: i64 = pool
+//!         .reader()?
+//!         .query_row("SELECT count(*) FROM foo", [], |r| r.get(0))?;
+//!     assert_eq!(0, n);
+//!     Ok(pool)
+//! }
+//! ```
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::error_from_sqlite_code;
+use crate::ffi;
+use crate::{Connection, OpenFlags, Result};
+
+type InitFn = dyn Fn(&mut Connection) -> Result<()> + Send + Sync;
+
+/// `feature = "pool"` Builder for a [`Pool`].
+pub struct PoolBuilder {
+    path: PathBuf,
+    readers: usize,
+    checkout_timeout: Duration,
+    init: Option<Box<InitFn>>,
+}
+
+impl PoolBuilder {
+    /// Set the maximum number of read-only connections (4 by default). Must
+    /// be at least one.
+    #[must_use]
+    pub fn readers(mut self, readers: usize) -> PoolBuilder {
+        self.readers = readers;
+        self
+    }
+
+    /// Set how long [`Pool::writer`] and [`Pool::reader`] wait for a
+    /// connection to be returned before failing (30 seconds by default).
+    #[must_use]
+    pub fn checkout_timeout(mut self, timeout: Duration) -> PoolBuilder {
+        self.checkout_timeout = timeout;
+        self
+    }
+
+    /// Set a closure called on each new connection, writer or reader, before
+    /// it is first handed out: to set pragmas, register functions or
+    /// collations...
+    #[must_use]
+    pub fn init<F>(mut self, init: F) -> PoolBuilder
+    where
+        F: Fn(&mut Connection) -> Result<()> + Send + Sync + 'static,
+    {
+        self.init = Some(Box::new(init));
+        self
+    }
+
+    /// Open the writer connection, switch the database to WAL mode and
+    /// create the pool. Readers are opened lazily, on checkout.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` with `SQLITE_MISUSE` if the number of readers is zero.
+    /// Will return `Err` if the writer connection cannot be opened, if WAL
+    /// mode cannot be enabled or if the init closure fails.
+    pub fn build(self) -> Result<Pool> {
+        if self.readers == 0 {
+            return Err(error_from_sqlite_code(
+                ffi::SQLITE_MISUSE,
+                Some("a pool needs at least one reader".to_owned()),
+            ));
+        }
+        let shared = Arc::new(Shared {
+            path: self.path,
+            checkout_timeout: self.checkout_timeout,
+            init: self.init,
+            writer: Slot::new(1),
+            readers: Slot::new(self.readers),
+        });
+        // Open the writer eagerly: readers cannot create the database.
+        let writer = shared
+            .writer
+            .checkout(Instant::now(), || shared.open(Kind::Writer))?;
+        shared.writer.checkin(Some(writer));
+        Ok(Pool { shared })
+    }
+}
+
+impl fmt::Debug for PoolBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoolBuilder")
+            .field("path", &self.path)
+            .field("readers", &self.readers)
+            .field("checkout_timeout", &self.checkout_timeout)
+            .finish()
+    }
+}
+
+/// `feature = "pool"` Pool of one writer and many reader connections to the
+/// same database.
+///
+/// Cloning a `Pool` is cheap: clones share the same connections.
+#[derive(Clone)]
+pub struct Pool {
+    shared: Arc<Shared>,
+}
+
+impl Pool {
+    /// Create a [`PoolBuilder`] for the database at `path`.
+    pub fn builder<P: AsRef<Path>>(path: P) -> PoolBuilder {
+        PoolBuilder {
+            path: path.as_ref().to_path_buf(),
+            readers: 4,
+            checkout_timeout: Duration::from_secs(30),
+            init: None,
+        }
+    }
+
+    /// Create a pool for the database at `path` with the default settings.
+    ///
+    /// # Failure
+    ///
+    /// See [`PoolBuilder::build`].
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Pool> {
+        Pool::builder(path).build()
+    }
+
+    /// Check out the writer connection, waiting for it to be returned if it
+    /// is in use.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` with `SQLITE_BUSY` if the checkout timeout expires,
+    /// or if a replacement connection cannot be opened.
+    pub fn writer(&self) -> Result<PooledConnection> {
+        self.checkout(Kind::Writer)
+    }
+
+    /// Check out a read-only connection, opening a new one if fewer than the
+    /// maximum are open, or waiting for one to be returned otherwise.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` with `SQLITE_BUSY` if the checkout timeout expires,
+    /// or if a new connection cannot be opened.
+    pub fn reader(&self) -> Result<PooledConnection> {
+        self.checkout(Kind::Reader)
+    }
+
+    fn checkout(&self, kind: Kind) -> Result<PooledConnection> {
+        let deadline = Instant::now() + self.shared.checkout_timeout;
+        let conn = self
+            .shared
+            .slot(kind)
+            .checkout(deadline, || self.shared.open(kind))?;
+        Ok(PooledConnection {
+            conn: Some(conn),
+            shared: self.shared.clone(),
+            kind,
+        })
+    }
+}
+
+impl fmt::Debug for Pool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pool")
+            .field("path", &self.shared.path)
+            .field("readers", &self.shared.readers.max)
+            .finish()
+    }
+}
+
+/// `feature = "pool"` Connection checked out of a [`Pool`].
+///
+/// It is returned to the pool when dropped. A connection left inside a
+/// transaction (see [`Connection::is_autocommit`]), or dropped during a
+/// panic, is considered broken: it is closed and replaced by a new one on a
+/// later checkout.
+pub struct PooledConnection {
+    conn: Option<Connection>,
+    shared: Arc<Shared>,
+    kind: Kind,
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    #[inline]
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledConnection {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        let conn = self
+            .conn
+            .take()
+            .filter(|conn| !thread::panicking() && conn.is_autocommit());
+        self.shared.slot(self.kind).checkin(conn);
+    }
+}
+
+impl fmt::Debug for PooledConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledConnection")
+            .field("conn", &self.conn)
+            .field("kind", &self.kind)
+            .finish()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Kind {
+    Writer,
+    Reader,
+}
+
+struct Shared {
+    path: PathBuf,
+    checkout_timeout: Duration,
+    init: Option<Box<InitFn>>,
+    writer: Slot,
+    readers: Slot,
+}
+
+impl Shared {
+    fn slot(&self, kind: Kind) -> &Slot {
+        match kind {
+            Kind::Writer => &self.writer,
+            Kind::Reader => &self.readers,
+        }
+    }
+
+    fn open(&self, kind: Kind) -> Result<Connection> {
+        let mut conn = match kind {
+            Kind::Writer => {
+                let conn = Connection::open(&self.path)?;
+                conn.pragma_update_and_check(None, "journal_mode", &"WAL", |_| Ok(()))?;
+                conn
+            }
+            Kind::Reader => Connection::open_with_flags(
+                &self.path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY
+                    | OpenFlags::SQLITE_OPEN_URI
+                    | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )?,
+        };
+        if let Some(ref init) = self.init {
+            init(&mut conn)?;
+        }
+        Ok(conn)
+    }
+}
+
+struct Slot {
+    max: usize,
+    state: Mutex<SlotState>,
+    available: Condvar,
+}
+
+struct SlotState {
+    idle: Vec<Connection>,
+    open: usize,
+}
+
+impl Slot {
+    fn new(max: usize) -> Slot {
+        Slot {
+            max,
+            state: Mutex::new(SlotState {
+                idle: Vec::new(),
+                open: 0,
+            }),
+            available: Condvar::new(),
+        }
+    }
+
+    fn checkout<F>(&self, deadline: Instant, open: F) -> Result<Connection>
+    where
+        F: FnOnce() -> Result<Connection>,
+    {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(conn) = state.idle.pop() {
+                return Ok(conn);
+            }
+            if state.open < self.max {
+                state.open += 1;
+                drop(state);
+                // Open without holding the lock, and give the slot back on
+                // failure.
+                let conn = open();
+                if conn.is_err() {
+                    self.checkin(None);
+                }
+                return conn;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(error_from_sqlite_code(
+                    ffi::SQLITE_BUSY,
+                    Some("timed out waiting for a pooled connection".to_owned()),
+                ));
+            }
+            state = self
+                .available
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    // `None` releases the slot of a broken (or never opened) connection.
+    fn checkin(&self, conn: Option<Connection>) {
+        let mut state = self.state.lock().unwrap();
+        match conn {
+            Some(conn) => state.idle.push(conn),
+            None => state.open -= 1,
+        }
+        self.available.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Pool;
+    use crate::{Error, ErrorCode, Result};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_writer_and_readers() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let pool = Pool::open(temp_dir.path().join("test.db3"))?;
+        pool.writer()?
+            .execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES (42);")?;
+
+        let reader = pool.reader()?;
+        let mode: String = reader.query_row("PRAGMA journal_mode", [], |r| r.get(0))?;
+        assert_eq!("wal", mode);
+        let x: i64 = reader.query_row("SELECT x FROM foo", [], |r| r.get(0))?;
+        assert_eq!(42, x);
+        match reader
+            .execute("INSERT INTO foo VALUES (?)", [1])
+            .unwrap_err()
+        {
+            Error::SqliteFailure(err, _) => assert_eq!(ErrorCode::ReadOnly, err.code),
+            err => panic!("unexpected error {}", err),
+        }
+
+        // Readers do not block the writer in WAL mode.
+        pool.writer()?.execute("INSERT INTO foo VALUES (?)", [43])?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkout_timeout() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let pool = Pool::builder(temp_dir.path().join("test.db3"))
+            .readers(1)
+            .checkout_timeout(Duration::from_millis(50))
+            .build()?;
+
+        let writer = pool.writer()?;
+        match pool.writer().unwrap_err() {
+            Error::SqliteFailure(err, _) => assert_eq!(ErrorCode::DatabaseBusy, err.code),
+            err => panic!("unexpected error {}", err),
+        }
+        let reader = pool.reader()?;
+        pool.reader().unwrap_err();
+
+        // A returned connection wakes up a waiting checkout.
+        let pool2 = pool.clone();
+        let waiting = thread::spawn(move || pool2.reader().map(|_| ()));
+        thread::sleep(Duration::from_millis(10));
+        drop(reader);
+        waiting.join().unwrap()?;
+        drop(writer);
+        pool.writer()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_readers() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        match Pool::builder(temp_dir.path().join("test.db3"))
+            .readers(0)
+            .build()
+            .unwrap_err()
+        {
+            Error::SqliteFailure(err, _) => assert_eq!(ErrorCode::APIMisuse, err.code),
+            err => panic!("unexpected error {}", err),
+        }
+    }
+
+    #[test]
+    fn test_init_and_replace_broken() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let opened = Arc::new(AtomicUsize::new(0));
+        let opened2 = opened.clone();
+        let pool = Pool::builder(temp_dir.path().join("test.db3"))
+            .init(move |conn| {
+                opened2.fetch_add(1, Ordering::SeqCst);
+                conn.execute_batch("PRAGMA foreign_keys = ON")
+            })
+            .build()?;
+        assert_eq!(1, opened.load(Ordering::SeqCst));
+
+        let fk: bool = pool
+            .reader()?
+            .query_row("PRAGMA foreign_keys", [], |r| r.get(0))?;
+        assert!(fk);
+        assert_eq!(2, opened.load(Ordering::SeqCst));
+
+        // Healthy connections are reused.
+        pool.writer()?.execute_batch("CREATE TABLE foo(x)")?;
+        assert_eq!(2, opened.load(Ordering::SeqCst));
+
+        // A connection left in a transaction is replaced.
+        pool.writer()?
+            .execute_batch("BEGIN; INSERT INTO foo VALUES (1);")?;
+        let writer = pool.writer()?;
+        assert_eq!(3, opened.load(Ordering::SeqCst));
+        assert!(writer.is_autocommit());
+        let n: i64 = writer.query_row("SELECT count(*) FROM foo", [], |r| r.get(0))?;
+        assert_eq!(0, n);
+        Ok(())
+    }
+}
@@ -0,0 +1,393 @@
+//! Structured `EXPLAIN QUERY PLAN` output and scan-status profiling
+//!
+//! ```rust
+//! use rusqlite::{Connection, Result};
+//!
+//! fn uses_index() -> Result<()> {
+//!     let db = Connection::open_in_memory()?;
+//!     db.execute_batch("CREATE TABLE foo(x INTEGER); CREATE INDEX foo_x ON foo(x);")?;
+//!     let stmt = db.prepare("SELECT * FROM foo WHERE x = ?")?;
+//!     let plan = stmt.query_plan()?;
+//!     assert!(plan.uses_index("foo_x"));
+//!     assert!(!plan.has_full_scan());
+//!     Ok(())
+//! }
+//! ```
+use std::fmt;
+#[cfg(feature = "scanstatus")]
+use std::os::raw::{c_char, c_int, c_void};
+
+use crate::ffi;
+use crate::{Result, Statement};
+
+/// A node of a query plan, as reported by `EXPLAIN QUERY PLAN`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryPlanNode {
+    id: i32,
+    parent: i32,
+    detail: String,
+    children: Vec<QueryPlanNode>,
+}
+
+impl QueryPlanNode {
+    /// Returns the id of the node.
+    #[inline]
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Returns the id of the parent node (0 for a top-level node).
+    #[inline]
+    pub fn parent(&self) -> i32 {
+        self.parent
+    }
+
+    /// Returns the description of the step (like `SCAN foo` or `SEARCH foo
+    /// USING INDEX foo_x (x=?)`).
+    ///
+    /// The format of this text is not guaranteed to be stable across SQLite
+    /// releases.
+    #[inline]
+    pub fn detail(&self) -> &str {
+        &self.detail
+    }
+
+    /// Returns the child nodes.
+    #[inline]
+    pub fn children(&self) -> &[QueryPlanNode] {
+        &self.children
+    }
+
+    /// Returns `true` if this step visits every row of a table (or of an
+    /// index, when the index is only used for its ordering or as a covering
+    /// index).
+    pub fn is_full_scan(&self) -> bool {
+        self.detail.starts_with("SCAN ") && !self.detail.starts_with("SCAN CONSTANT ROW")
+    }
+
+    /// Returns `true` if this step builds a temporary B-tree (for `ORDER BY`,
+    /// `GROUP BY`, `DISTINCT` or a compound query).
+    pub fn is_temp_b_tree(&self) -> bool {
+        self.detail.contains("USE TEMP B-TREE")
+    }
+
+    /// Returns `true` if this step uses an automatic (transient) index.
+    pub fn is_automatic_index(&self) -> bool {
+        self.detail.contains("AUTOMATIC")
+    }
+
+    /// Returns the name of the index used by this step, if any.
+    pub fn index(&self) -> Option<&str> {
+        if self.is_automatic_index() {
+            return None;
+        }
+        let start = self.detail.find(" INDEX ")? + " INDEX ".len();
+        self.detail[start..].split(' ').next()
+    }
+}
+
+/// The query plan of a statement, as reported by `EXPLAIN QUERY PLAN`.
+///
+/// See [The Query Planner](https://sqlite.org/eqp.html) for the meaning of the
+/// nodes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryPlan {
+    nodes: Vec<QueryPlanNode>,
+}
+
+impl QueryPlan {
+    fn new(mut rows: Vec<(i32, i32, String)>) -> QueryPlan {
+        fn children(rows: &mut Vec<(i32, i32, String)>, parent: i32) -> Vec<QueryPlanNode> {
+            let mut nodes = Vec::new();
+            let mut i = 0;
+            while i < rows.len() {
+                if rows[i].1 == parent {
+                    let (id, parent, detail) = rows.remove(i);
+                    nodes.push(QueryPlanNode {
+                        id,
+                        parent,
+                        detail,
+                        children: Vec::new(),
+                    });
+                } else {
+                    i += 1;
+                }
+            }
+            for node in &mut nodes {
+                node.children = children(rows, node.id);
+            }
+            nodes
+        }
+        QueryPlan {
+            nodes: children(&mut rows, 0),
+        }
+    }
+
+    /// Returns the top-level nodes.
+    #[inline]
+    pub fn nodes(&self) -> &[QueryPlanNode] {
+        &self.nodes
+    }
+
+    /// Returns an iterator over all the nodes, depth first.
+    pub fn iter(&self) -> impl Iterator<Item = &QueryPlanNode> {
+        let mut stack: Vec<&QueryPlanNode> = self.nodes.iter().rev().collect();
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children.iter().rev());
+            Some(node)
+        })
+    }
+
+    /// Returns `true` if any step visits every row of a table.
+    #[inline]
+    pub fn has_full_scan(&self) -> bool {
+        self.iter().any(QueryPlanNode::is_full_scan)
+    }
+
+    /// Returns `true` if any step builds a temporary B-tree.
+    #[inline]
+    pub fn has_temp_b_tree(&self) -> bool {
+        self.iter().any(QueryPlanNode::is_temp_b_tree)
+    }
+
+    /// Returns `true` if any step uses an automatic index.
+    #[inline]
+    pub fn has_automatic_index(&self) -> bool {
+        self.iter().any(QueryPlanNode::is_automatic_index)
+    }
+
+    /// Returns `true` if any step uses the index `name`.
+    #[inline]
+    pub fn uses_index(&self, name: &str) -> bool {
+        self.iter().any(|node| node.index() == Some(name))
+    }
+}
+
+/// Renders the plan like the `sqlite3` shell does.
+impl fmt::Display for QueryPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn write_nodes(
+            f: &mut fmt::Formatter<'_>,
+            nodes: &[QueryPlanNode],
+            prefix: &str,
+        ) -> fmt::Result {
+            for (i, node) in nodes.iter().enumerate() {
+                let last = i + 1 == nodes.len();
+                writeln!(
+                    f,
+                    "{}{}{}",
+                    prefix,
+                    if last { "`--" } else { "|--" },
+                    node.detail
+                )?;
+                let prefix = format!("{}{}", prefix, if last { "   " } else { "|  " });
+                write_nodes(f, &node.children, &prefix)?;
+            }
+            Ok(())
+        }
+        writeln!(f, "QUERY PLAN")?;
+        write_nodes(f, &self.nodes, "")
+    }
+}
+
+impl Statement<'_> {
+    /// Returns the query plan of the statement, as reported by `EXPLAIN QUERY
+    /// PLAN`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the SQLite version is older than 3.24.0 (whose
+    /// `EXPLAIN QUERY PLAN` output has a different shape).
+    pub fn query_plan(&self) -> Result<QueryPlan> {
+        if crate::version_number() < 3_024_000 {
+            return Err(crate::error::error_from_sqlite_code(
+                ffi::SQLITE_MISUSE,
+                Some("EXPLAIN QUERY PLAN requires SQLite 3.24.0 or later".to_owned()),
+            ));
+        }
+        let sql = self
+            .stmt
+            .sql()
+            .map_or(std::borrow::Cow::Borrowed(""), |sql| sql.to_string_lossy());
+        let mut explain = self.conn.prepare(&format!("EXPLAIN QUERY PLAN {}", sql))?;
+        // Parameters of the statement are parameters of the EXPLAIN too, but
+        // they need not be bound.
+        let rows = explain
+            .raw_query()
+            .mapped(|row| Ok((row.get(0)?, row.get(1)?, row.get(3)?)))
+            .collect::<Result<_>>()?;
+        Ok(QueryPlan::new(rows))
+    }
+
+    /// `feature = "scanstatus"` Returns the profiling counters of each loop of
+    /// the statement, since it was prepared or since the last call to
+    /// [`Statement::reset_scan_status`].
+    ///
+    /// Requires SQLite compiled with `SQLITE_ENABLE_STMT_SCANSTATUS` (which
+    /// the bundled build does when this feature is enabled).
+    #[cfg(feature = "scanstatus")]
+    pub fn scan_status(&self) -> Vec<ScanStatus> {
+        let mut loops = Vec::new();
+        for idx in 0.. {
+            let mut n_loop: i64 = 0;
+            if self.stmt_scanstatus(idx, ffi::SQLITE_SCANSTAT_NLOOP, &mut n_loop) != 0 {
+                break;
+            }
+            let mut n_visit: i64 = 0;
+            let mut estimated: f64 = 0.0;
+            let mut name: *const c_char = std::ptr::null();
+            let mut explain: *const c_char = std::ptr::null();
+            let mut select_id: c_int = 0;
+            self.stmt_scanstatus(idx, ffi::SQLITE_SCANSTAT_NVISIT, &mut n_visit);
+            self.stmt_scanstatus(idx, ffi::SQLITE_SCANSTAT_EST, &mut estimated);
+            self.stmt_scanstatus(idx, ffi::SQLITE_SCANSTAT_NAME, &mut name);
+            self.stmt_scanstatus(idx, ffi::SQLITE_SCANSTAT_EXPLAIN, &mut explain);
+            self.stmt_scanstatus(idx, ffi::SQLITE_SCANSTAT_SELECTID, &mut select_id);
+            let to_string = |s: *const c_char| {
+                if s.is_null() {
+                    None
+                } else {
+                    Some(
+                        unsafe { std::ffi::CStr::from_ptr(s) }
+                            .to_string_lossy()
+                            .into_owned(),
+                    )
+                }
+            };
+            loops.push(ScanStatus {
+                loops: n_loop,
+                visits: n_visit,
+                estimated_rows: estimated,
+                name: to_string(name),
+                explain: to_string(explain),
+                select_id,
+            });
+        }
+        loops
+    }
+
+    /// `feature = "scanstatus"` Resets the counters returned by
+    /// [`Statement::scan_status`].
+    #[cfg(feature = "scanstatus")]
+    #[inline]
+    pub fn reset_scan_status(&self) {
+        unsafe { ffi::sqlite3_stmt_scanstatus_reset(self.stmt.ptr()) }
+    }
+
+    #[cfg(feature = "scanstatus")]
+    #[inline]
+    fn stmt_scanstatus<T>(&self, idx: c_int, op: c_int, out: &mut T) -> c_int {
+        unsafe {
+            ffi::sqlite3_stmt_scanstatus(self.stmt.ptr(), idx, op, out as *mut T as *mut c_void)
+        }
+    }
+}
+
+/// `feature = "scanstatus"` Profiling counters of one loop of a statement.
+/// See [sqlite3_stmt_scanstatus](https://sqlite.org/c3ref/stmt_scanstatus.html).
+#[cfg(feature = "scanstatus")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScanStatus {
+    /// Number of times the loop has run
+    pub loops: i64,
+    /// Number of rows visited by the loop
+    pub visits: i64,
+    /// Number of rows the query planner estimated the loop would output per
+    /// run
+    pub estimated_rows: f64,
+    /// Name of the table or index scanned by the loop
+    pub name: Option<String>,
+    /// `EXPLAIN QUERY PLAN` description of the loop
+    pub explain: Option<String>,
+    /// Id of the `EXPLAIN QUERY PLAN` node of the loop
+    pub select_id: i32,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Connection, Result};
+
+    fn db() -> Result<Connection> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE foo(x INTEGER, y TEXT);
+             CREATE INDEX foo_x ON foo(x);
+             CREATE TABLE bar(x INTEGER, z TEXT);",
+        )?;
+        Ok(db)
+    }
+
+    #[test]
+    fn test_query_plan_index() -> Result<()> {
+        let db = db()?;
+        let stmt = db.prepare("SELECT y FROM foo WHERE x = ?")?;
+        let plan = stmt.query_plan()?;
+        assert_eq!(1, plan.nodes().len());
+        let node = &plan.nodes()[0];
+        assert_eq!(0, node.parent());
+        assert!(node.detail().starts_with("SEARCH "));
+        assert_eq!(Some("foo_x"), node.index());
+        assert!(plan.uses_index("foo_x"));
+        assert!(!plan.has_full_scan());
+        assert!(!plan.has_temp_b_tree());
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_plan_full_scan() -> Result<()> {
+        let db = db()?;
+        let stmt = db.prepare("SELECT * FROM foo ORDER BY y")?;
+        let plan = stmt.query_plan()?;
+        assert!(plan.has_full_scan());
+        assert!(plan.has_temp_b_tree());
+        assert!(!plan.uses_index("foo_x"));
+        let rendered = plan.to_string();
+        assert!(rendered.starts_with("QUERY PLAN\n|--SCAN "));
+        assert!(rendered.contains("`--USE TEMP B-TREE FOR ORDER BY"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_plan_tree() -> Result<()> {
+        let db = db()?;
+        let stmt = db.prepare(
+            "SELECT * FROM foo WHERE x IN (SELECT x FROM bar) UNION SELECT x, z FROM bar",
+        )?;
+        let plan = stmt.query_plan()?;
+        assert!(plan.nodes().iter().any(|node| !node.children().is_empty()));
+        for node in plan.iter() {
+            for child in node.children() {
+                assert_eq!(node.id(), child.parent());
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_plan_automatic_index() -> Result<()> {
+        let db = db()?;
+        let stmt = db.prepare("SELECT * FROM foo, bar WHERE foo.y = bar.z")?;
+        let plan = stmt.query_plan()?;
+        assert!(plan.has_automatic_index());
+        assert!(plan.iter().all(|node| node.index().is_none()));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "scanstatus")]
+    fn test_scan_status() -> Result<()> {
+        let db = db()?;
+        db.execute_batch("INSERT INTO foo VALUES (1, 'a'), (2, 'b'), (3, 'c');")?;
+        let mut stmt = db.prepare("SELECT y FROM foo WHERE y > 'a'")?;
+        assert_eq!(2, stmt.query_map([], |_| Ok(()))?.count());
+        let loops = stmt.scan_status();
+        assert_eq!(1, loops.len());
+        assert_eq!(1, loops[0].loops);
+        assert_eq!(3, loops[0].visits);
+        assert_eq!(Some("foo"), loops[0].name.as_deref());
+        stmt.reset_scan_status();
+        assert_eq!(0, stmt.scan_status()[0].visits);
+        Ok(())
+    }
+}
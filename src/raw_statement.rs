@@ -83,6 +83,24 @@ impl RawStatement {
         }
     }
 
+    #[cfg(feature = "column_metadata")]
+    #[inline]
+    pub fn column_database_name(&self, idx: usize) -> Option<&CStr> {
+        unsafe { opt_cstr(ffi::sqlite3_column_database_name(self.ptr, idx as c_int)) }
+    }
+
+    #[cfg(feature = "column_metadata")]
+    #[inline]
+    pub fn column_table_name(&self, idx: usize) -> Option<&CStr> {
+        unsafe { opt_cstr(ffi::sqlite3_column_table_name(self.ptr, idx as c_int)) }
+    }
+
+    #[cfg(feature = "column_metadata")]
+    #[inline]
+    pub fn column_origin_name(&self, idx: usize) -> Option<&CStr> {
+        unsafe { opt_cstr(ffi::sqlite3_column_origin_name(self.ptr, idx as c_int)) }
+    }
+
     #[inline]
     pub fn column_name(&self, idx: usize) -> Option<&CStr> {
         let idx = idx as c_int;
@@ -205,3 +223,13 @@ impl Drop for RawStatement {
         self.finalize_();
     }
 }
+
+#[cfg(feature = "column_metadata")]
+#[inline]
+unsafe fn opt_cstr<'a>(ptr: *const std::os::raw::c_char) -> Option<&'a CStr> {
+    if ptr.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(ptr))
+    }
+}
@@ -3,6 +3,7 @@ use fallible_streaming_iterator::FallibleStreamingIterator;
 use std::convert;
 
 use super::{Error, Result, Statement};
+use crate::instrument::StatementSpan;
 use crate::types::{FromSql, FromSqlError, ValueRef};
 
 /// An handle for the resulting rows of a query.
@@ -10,6 +11,11 @@ use crate::types::{FromSql, FromSqlError, ValueRef};
 pub struct Rows<'stmt> {
     pub(crate) stmt: Option<&'stmt Statement<'stmt>>,
     row: Option<Row<'stmt>>,
+    span: Option<StatementSpan>,
+    #[cfg(feature = "hooks")]
+    deadline: crate::hooks::ExecutionDeadline,
+    #[cfg(feature = "statement_checker")]
+    counts: Option<crate::checker::Counts>,
 }
 
 impl<'stmt> Rows<'stmt> {
@@ -17,6 +23,13 @@ impl<'stmt> Rows<'stmt> {
     fn reset(&mut self) {
         if let Some(stmt) = self.stmt.take() {
             stmt.reset();
+            #[cfg(feature = "statement_checker")]
+            if let Some(counts) = self.counts.take() {
+                counts.finish(stmt);
+            }
+            if let Some(span) = self.span.take() {
+                span.finish(stmt, None);
+            }
         }
     }
 
@@ -85,6 +98,24 @@ impl<'stmt> Rows<'stmt> {
         Rows {
             stmt: Some(stmt),
             row: None,
+            span: Some(StatementSpan::new(stmt)),
+            #[cfg(feature = "hooks")]
+            deadline: crate::hooks::ExecutionDeadline::start(stmt),
+            #[cfg(feature = "statement_checker")]
+            counts: crate::checker::Counts::start(stmt),
+        }
+    }
+
+    /// Step `stmt`, under the deadline of the execution.
+    #[inline]
+    fn step(&self, stmt: &Statement<'_>) -> Result<bool> {
+        #[cfg(feature = "hooks")]
+        {
+            self.deadline.step(stmt, || stmt.step())
+        }
+        #[cfg(not(feature = "hooks"))]
+        {
+            stmt.step()
         }
     }
 
@@ -202,8 +233,15 @@ impl<'stmt> FallibleStreamingIterator for Rows<'stmt> {
     #[inline]
     fn advance(&mut self) -> Result<()> {
         match self.stmt {
-            Some(ref stmt) => match stmt.step() {
+            Some(stmt) => match self
+                .span
+                .as_ref()
+                .map_or_else(|| self.step(stmt), |span| span.in_scope(|| self.step(stmt)))
+            {
                 Ok(true) => {
+                    if let Some(ref mut span) = self.span {
+                        span.row();
+                    }
                     self.row = Some(Row { stmt });
                     Ok(())
                 }
@@ -213,6 +251,9 @@ impl<'stmt> FallibleStreamingIterator for Rows<'stmt> {
                     Ok(())
                 }
                 Err(e) => {
+                    if let Some(ref mut span) = self.span {
+                        span.error(&e);
+                    }
                     self.reset();
                     self.row = None;
                     Err(e)
@@ -376,6 +417,38 @@ impl RowIndex for &'_ str {
     }
 }
 
+/// A trait for types that can be created from a result row.
+///
+/// It can be derived with `#[derive(FromRow)]` when the `derive` feature is
+/// enabled, and used as the mapping function of a query:
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, FromRow, Result, Row};
+/// struct Person {
+///     name: String,
+///     age: Option<u8>,
+/// }
+///
+/// impl FromRow for Person {
+///     fn from_row(row: &Row<'_>) -> Result<Person> {
+///         Ok(Person {
+///             name: row.get("name")?,
+///             age: row.get("age")?,
+///         })
+///     }
+/// }
+///
+/// fn people(conn: &Connection) -> Result<Vec<Person>> {
+///     let mut stmt = conn.prepare("SELECT name, age FROM person")?;
+///     let rows = stmt.query_map([], Person::from_row)?;
+///     rows.collect()
+/// }
+/// ```
+pub trait FromRow: Sized {
+    /// Converts the current row into `Self`.
+    fn from_row(row: &Row<'_>) -> Result<Self>;
+}
+
 macro_rules! tuple_try_from_row {
     ($($field:ident),*) => {
         impl<'a, $($field,)*> convert::TryFrom<&'a Row<'a>> for ($($field,)*) where $($field: FromSql,)* {
@@ -0,0 +1,524 @@
+//! `feature = "serde"` Serialize Rust types into named parameters.
+use std::error;
+use std::fmt;
+
+use serde::ser::{self, Impossible, Serialize, SerializeMap, SerializeStruct, Serializer};
+
+use crate::types::Value;
+use crate::{Error, Result};
+
+/// Flatten a struct (or a map with string keys) into `(name, value)` pairs,
+/// in field order.
+pub(crate) fn to_named_values<T: Serialize + ?Sized>(value: &T) -> Result<Vec<(String, Value)>> {
+    value.serialize(ParamsSerializer).map_err(|err| err.0)
+}
+
+#[derive(Debug)]
+struct SerError(Error);
+
+impl fmt::Display for SerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl error::Error for SerError {}
+
+impl ser::Error for SerError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerError(Error::ToSqlConversionFailure(msg.to_string().into()))
+    }
+}
+
+fn unsupported<T>(what: &str) -> std::result::Result<T, SerError> {
+    Err(ser::Error::custom(format_args!(
+        "cannot bind {} as a parameter",
+        what
+    )))
+}
+
+type SerResult<T> = std::result::Result<T, SerError>;
+
+/// Top level serializer: only structs and maps are accepted.
+struct ParamsSerializer;
+
+impl Serializer for ParamsSerializer {
+    type Ok = Vec<(String, Value)>;
+    type Error = SerError;
+    type SerializeSeq = Impossible<Self::Ok, SerError>;
+    type SerializeTuple = Impossible<Self::Ok, SerError>;
+    type SerializeTupleStruct = Impossible<Self::Ok, SerError>;
+    type SerializeTupleVariant = Impossible<Self::Ok, SerError>;
+    type SerializeMap = NamedValues;
+    type SerializeStruct = NamedValues;
+    type SerializeStructVariant = Impossible<Self::Ok, SerError>;
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> SerResult<NamedValues> {
+        Ok(NamedValues::with_capacity(len))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> SerResult<NamedValues> {
+        Ok(NamedValues::with_capacity(len.unwrap_or(0)))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> SerResult<Self::Ok> {
+        value.serialize(self)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> SerResult<Self::Ok> {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, _: bool) -> SerResult<Self::Ok> {
+        unsupported("a scalar")
+    }
+
+    fn serialize_i8(self, _: i8) -> SerResult<Self::Ok> {
+        unsupported("a scalar")
+    }
+
+    fn serialize_i16(self, _: i16) -> SerResult<Self::Ok> {
+        unsupported("a scalar")
+    }
+
+    fn serialize_i32(self, _: i32) -> SerResult<Self::Ok> {
+        unsupported("a scalar")
+    }
+
+    fn serialize_i64(self, _: i64) -> SerResult<Self::Ok> {
+        unsupported("a scalar")
+    }
+
+    fn serialize_u8(self, _: u8) -> SerResult<Self::Ok> {
+        unsupported("a scalar")
+    }
+
+    fn serialize_u16(self, _: u16) -> SerResult<Self::Ok> {
+        unsupported("a scalar")
+    }
+
+    fn serialize_u32(self, _: u32) -> SerResult<Self::Ok> {
+        unsupported("a scalar")
+    }
+
+    fn serialize_u64(self, _: u64) -> SerResult<Self::Ok> {
+        unsupported("a scalar")
+    }
+
+    fn serialize_f32(self, _: f32) -> SerResult<Self::Ok> {
+        unsupported("a scalar")
+    }
+
+    fn serialize_f64(self, _: f64) -> SerResult<Self::Ok> {
+        unsupported("a scalar")
+    }
+
+    fn serialize_char(self, _: char) -> SerResult<Self::Ok> {
+        unsupported("a scalar")
+    }
+
+    fn serialize_str(self, _: &str) -> SerResult<Self::Ok> {
+        unsupported("a scalar")
+    }
+
+    fn serialize_bytes(self, _: &[u8]) -> SerResult<Self::Ok> {
+        unsupported("a scalar")
+    }
+
+    fn serialize_none(self) -> SerResult<Self::Ok> {
+        unsupported("a scalar")
+    }
+
+    fn serialize_unit(self) -> SerResult<Self::Ok> {
+        Ok(Vec::new())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> SerResult<Self::Ok> {
+        Ok(Vec::new())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> SerResult<Self::Ok> {
+        unsupported("an enum")
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> SerResult<Self::Ok> {
+        unsupported("an enum")
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> SerResult<Self::SerializeSeq> {
+        unsupported("a sequence")
+    }
+
+    fn serialize_tuple(self, _len: usize) -> SerResult<Self::SerializeTuple> {
+        unsupported("a tuple")
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> SerResult<Self::SerializeTupleStruct> {
+        unsupported("a tuple struct")
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> SerResult<Self::SerializeTupleVariant> {
+        unsupported("an enum")
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> SerResult<Self::SerializeStructVariant> {
+        unsupported("an enum")
+    }
+}
+
+struct NamedValues {
+    values: Vec<(String, Value)>,
+    key: Option<String>,
+}
+
+impl NamedValues {
+    fn with_capacity(len: usize) -> NamedValues {
+        NamedValues {
+            values: Vec::with_capacity(len),
+            key: None,
+        }
+    }
+}
+
+impl SerializeStruct for NamedValues {
+    type Ok = Vec<(String, Value)>;
+    type Error = SerError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> SerResult<()> {
+        let value = value.serialize(ValueSerializer)?;
+        self.values.push((key.to_owned(), value));
+        Ok(())
+    }
+
+    fn end(self) -> SerResult<Self::Ok> {
+        Ok(self.values)
+    }
+}
+
+impl SerializeMap for NamedValues {
+    type Ok = Vec<(String, Value)>;
+    type Error = SerError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> SerResult<()> {
+        match key.serialize(ValueSerializer)? {
+            Value::Text(key) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => unsupported("a map with non-string keys"),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> SerResult<()> {
+        let key = self
+            .key
+            .take()
+            .expect("serialize_value called before serialize_key");
+        let value = value.serialize(ValueSerializer)?;
+        self.values.push((key, value));
+        Ok(())
+    }
+
+    fn end(self) -> SerResult<Self::Ok> {
+        Ok(self.values)
+    }
+}
+
+/// Field level serializer: only scalars are accepted.
+struct ValueSerializer;
+
+impl Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = SerError;
+    type SerializeSeq = Impossible<Value, SerError>;
+    type SerializeTuple = Impossible<Value, SerError>;
+    type SerializeTupleStruct = Impossible<Value, SerError>;
+    type SerializeTupleVariant = Impossible<Value, SerError>;
+    type SerializeMap = Impossible<Value, SerError>;
+    type SerializeStruct = Impossible<Value, SerError>;
+    type SerializeStructVariant = Impossible<Value, SerError>;
+
+    fn serialize_bool(self, v: bool) -> SerResult<Value> {
+        Ok(Value::Integer(v as i64))
+    }
+
+    fn serialize_i8(self, v: i8) -> SerResult<Value> {
+        Ok(Value::Integer(i64::from(v)))
+    }
+
+    fn serialize_i16(self, v: i16) -> SerResult<Value> {
+        Ok(Value::Integer(i64::from(v)))
+    }
+
+    fn serialize_i32(self, v: i32) -> SerResult<Value> {
+        Ok(Value::Integer(i64::from(v)))
+    }
+
+    fn serialize_i64(self, v: i64) -> SerResult<Value> {
+        Ok(Value::Integer(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> SerResult<Value> {
+        Ok(Value::Integer(i64::from(v)))
+    }
+
+    fn serialize_u16(self, v: u16) -> SerResult<Value> {
+        Ok(Value::Integer(i64::from(v)))
+    }
+
+    fn serialize_u32(self, v: u32) -> SerResult<Value> {
+        Ok(Value::Integer(i64::from(v)))
+    }
+
+    fn serialize_u64(self, v: u64) -> SerResult<Value> {
+        use std::convert::TryFrom;
+        i64::try_from(v)
+            .map(Value::Integer)
+            .map_err(|err| SerError(Error::ToSqlConversionFailure(err.into())))
+    }
+
+    fn serialize_f32(self, v: f32) -> SerResult<Value> {
+        Ok(Value::Real(f64::from(v)))
+    }
+
+    fn serialize_f64(self, v: f64) -> SerResult<Value> {
+        Ok(Value::Real(v))
+    }
+
+    fn serialize_char(self, v: char) -> SerResult<Value> {
+        Ok(Value::Text(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> SerResult<Value> {
+        Ok(Value::Text(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> SerResult<Value> {
+        Ok(Value::Blob(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> SerResult<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> SerResult<Value> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> SerResult<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> SerResult<Value> {
+        Ok(Value::Null)
+    }
+
+    // consistent with the deserializer, which reads unit variants from text
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> SerResult<Value> {
+        Ok(Value::Text(variant.to_owned()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> SerResult<Value> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> SerResult<Value> {
+        unsupported("a non-unit enum variant")
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> SerResult<Self::SerializeSeq> {
+        unsupported("a sequence")
+    }
+
+    fn serialize_tuple(self, _len: usize) -> SerResult<Self::SerializeTuple> {
+        unsupported("a tuple")
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> SerResult<Self::SerializeTupleStruct> {
+        unsupported("a tuple struct")
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> SerResult<Self::SerializeTupleVariant> {
+        unsupported("a non-unit enum variant")
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> SerResult<Self::SerializeMap> {
+        unsupported("a nested map")
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> SerResult<Self::SerializeStruct> {
+        unsupported("a nested struct")
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> SerResult<Self::SerializeStructVariant> {
+        unsupported("a non-unit enum variant")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use serde::Serialize;
+
+    use crate::{params_from_serialize, Connection, Error, Result};
+
+    #[derive(Serialize)]
+    struct PersonId(i64);
+
+    #[derive(Serialize)]
+    enum Kind {
+        Admin,
+    }
+
+    #[derive(Serialize)]
+    struct Person<'a> {
+        id: PersonId,
+        name: &'a str,
+        age: Option<u8>,
+        kind: Kind,
+    }
+
+    fn checked_memory_handle() -> Result<Connection> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT NOT NULL, age INTEGER, \
+             kind TEXT)",
+        )?;
+        Ok(db)
+    }
+
+    #[test]
+    fn test_params_from_serialize() -> Result<()> {
+        let db = checked_memory_handle()?;
+        let alice = Person {
+            id: PersonId(1),
+            name: "Alice",
+            age: None,
+            kind: Kind::Admin,
+        };
+        db.execute(
+            "INSERT INTO person (id, name, age, kind) VALUES (:id, @name, $age, :kind)",
+            params_from_serialize(&alice),
+        )?;
+        let (name, age, kind): (String, Option<i64>, String) =
+            db.query_row("SELECT name, age, kind FROM person WHERE id = 1", [], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?))
+            })?;
+        assert_eq!(("Alice", None, "Admin"), (&*name, age, &*kind));
+
+        let mut map = BTreeMap::new();
+        map.insert("name", "Bob");
+        let n: i64 = db.query_row(
+            "SELECT count(*) FROM person WHERE name != :name",
+            params_from_serialize(&map),
+            |r| r.get(0),
+        )?;
+        assert_eq!(1, n);
+        Ok(())
+    }
+
+    #[test]
+    fn test_params_from_serialize_errors() -> Result<()> {
+        let db = checked_memory_handle()?;
+        let alice = Person {
+            id: PersonId(1),
+            name: "Alice",
+            age: Some(42),
+            kind: Kind::Admin,
+        };
+
+        let err = db
+            .execute(
+                "INSERT INTO person (id, name, age) VALUES (:id, :name, :age)",
+                params_from_serialize(&alice),
+            )
+            .unwrap_err();
+        assert_eq!(Error::InvalidParameterName(":kind".to_owned()), err);
+
+        let err = db
+            .execute(
+                "INSERT INTO person (id, name, age, kind) VALUES (:id, :name, :age, :kind) \
+                 ON CONFLICT DO UPDATE SET age = :new_age",
+                params_from_serialize(&alice),
+            )
+            .unwrap_err();
+        assert_eq!(Error::InvalidParameterCount(4, 5), err);
+
+        match db.execute("SELECT :x", params_from_serialize(&(1, 2))) {
+            Err(Error::ToSqlConversionFailure(_)) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+        Ok(())
+    }
+}
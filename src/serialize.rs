@@ -0,0 +1,206 @@
+//! `feature = "serialize"` Serialize a database to, or deserialize it from,
+//! an in-memory byte buffer.
+//!
+//! The serialized image is the same sequence of bytes which would be written
+//! to disk if the database were backed up to a file.
+//!
+//! ```rust
+//! # use rusqlite::{Connection, Result, MAIN_DB};
+//! fn copy_db(src: &Connection) -> Result<Connection> {
+//!     let image = src.serialize(MAIN_DB)?;
+//!     let mut dst = Connection::open_in_memory()?;
+//!     dst.deserialize(MAIN_DB, image, false)?;
+//!     Ok(dst)
+//! }
+//! ```
+//!
+//! (See [SQLite doc](https://sqlite.org/c3ref/serialize.html))
+use std::ops::Deref;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+use std::slice;
+
+use crate::error::error_from_sqlite_code;
+use crate::ffi;
+use crate::{Connection, DatabaseName, Result};
+
+/// `feature = "serialize"` Serialized database image returned by
+/// [`Connection::serialize_data`].
+#[derive(Debug)]
+pub enum Data<'conn> {
+    /// The memory used by SQLite itself to store the database, which is
+    /// available without copy for databases loaded by
+    /// [`Connection::deserialize`].
+    Shared(&'conn [u8]),
+    /// A copy of the database content.
+    Owned(Vec<u8>),
+}
+
+impl Deref for Data<'_> {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        match *self {
+            Data::Shared(data) => data,
+            Data::Owned(ref data) => data,
+        }
+    }
+}
+
+impl From<Data<'_>> for Vec<u8> {
+    #[inline]
+    fn from(data: Data<'_>) -> Vec<u8> {
+        match data {
+            Data::Shared(data) => data.to_vec(),
+            Data::Owned(data) => data,
+        }
+    }
+}
+
+impl Connection {
+    /// `feature = "serialize"` Return a copy of the `schema` database.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `schema` is not an attached database or if memory
+    /// cannot be allocated.
+    pub fn serialize(&self, schema: DatabaseName<'_>) -> Result<Vec<u8>> {
+        let c_schema = schema.to_cstring()?;
+        let db = self.db.borrow().db();
+        let mut sz: ffi::sqlite3_int64 = -1;
+        let ptr = unsafe { ffi::sqlite3_serialize(db, c_schema.as_ptr(), &mut sz, 0) };
+        if ptr.is_null() {
+            return if sz == 0 {
+                // empty database
+                Ok(Vec::new())
+            } else {
+                Err(error_from_sqlite_code(ffi::SQLITE_NOMEM, None))
+            };
+        }
+        let data = unsafe { slice::from_raw_parts(ptr, sz as usize) }.to_vec();
+        unsafe { ffi::sqlite3_free(ptr as *mut c_void) };
+        Ok(data)
+    }
+
+    /// `feature = "serialize"` Return the `schema` database, without copy
+    /// when SQLite stores it in contiguous memory (see
+    /// [`deserialize`](Connection::deserialize)).
+    ///
+    /// The connection is exclusively borrowed while the returned [`Data`] is
+    /// alive, because any change to the database may invalidate it.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `schema` is not an attached database or if memory
+    /// cannot be allocated.
+    pub fn serialize_data(&mut self, schema: DatabaseName<'_>) -> Result<Data<'_>> {
+        let c_schema = schema.to_cstring()?;
+        let db = self.db.borrow().db();
+        let mut sz: ffi::sqlite3_int64 = -1;
+        let ptr = unsafe {
+            ffi::sqlite3_serialize(
+                db,
+                c_schema.as_ptr(),
+                &mut sz,
+                ffi::SQLITE_SERIALIZE_NOCOPY as _,
+            )
+        };
+        if ptr.is_null() {
+            self.serialize(schema).map(Data::Owned)
+        } else {
+            Ok(Data::Shared(unsafe {
+                slice::from_raw_parts(ptr, sz as usize)
+            }))
+        }
+    }
+
+    /// `feature = "serialize"` Replace the `schema` database with the
+    /// serialized image `data`.
+    ///
+    /// The database is then held in memory, like with
+    /// [`open_in_memory`](Connection::open_in_memory). Unless `read_only` is
+    /// `true`, it can be modified and grow as needed.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `schema` is not an attached database, if it is
+    /// currently in use (in a read transaction or involved in a backup), or if
+    /// memory cannot be allocated.
+    pub fn deserialize(
+        &mut self,
+        schema: DatabaseName<'_>,
+        data: Vec<u8>,
+        read_only: bool,
+    ) -> Result<()> {
+        let c_schema = schema.to_cstring()?;
+        let sz = data.len() as ffi::sqlite3_int64;
+        // The buffer is owned and freed by SQLite.
+        let ptr = if data.is_empty() {
+            ptr::null_mut()
+        } else {
+            let ptr = unsafe { ffi::sqlite3_malloc64(data.len() as ffi::sqlite3_uint64) };
+            if ptr.is_null() {
+                return Err(error_from_sqlite_code(ffi::SQLITE_NOMEM, None));
+            }
+            unsafe { ptr::copy_nonoverlapping(data.as_ptr(), ptr as *mut u8, data.len()) };
+            ptr as *mut u8
+        };
+        let mut flags = ffi::SQLITE_DESERIALIZE_FREEONCLOSE;
+        if read_only {
+            flags |= ffi::SQLITE_DESERIALIZE_READONLY;
+        } else {
+            flags |= ffi::SQLITE_DESERIALIZE_RESIZEABLE;
+        }
+        let mut c = self.db.borrow_mut();
+        let r = unsafe {
+            ffi::sqlite3_deserialize(c.db(), c_schema.as_ptr(), ptr, sz, sz, flags as c_int as _)
+        };
+        c.decode_result(r)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Data;
+    use crate::{Connection, DatabaseName, Result, MAIN_DB};
+
+    #[test]
+    fn test_serialize_deserialize() -> Result<()> {
+        let src = Connection::open_in_memory()?;
+        src.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES(42);")?;
+        let image = src.serialize(MAIN_DB)?;
+        assert!(!image.is_empty());
+
+        let mut dst = Connection::open_in_memory()?;
+        dst.deserialize(MAIN_DB, image.clone(), false)?;
+        dst.execute("INSERT INTO foo VALUES(43)", [])?;
+        let sum: i64 = dst.query_row("SELECT sum(x) FROM foo", [], |r| r.get(0))?;
+        assert_eq!(85, sum);
+
+        match dst.serialize_data(MAIN_DB)? {
+            Data::Shared(data) => assert_eq!(image.len(), data.len()),
+            Data::Owned(_) => panic!("expected shared data"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_read_only() -> Result<()> {
+        let src = Connection::open_in_memory()?;
+        src.execute_batch("CREATE TABLE foo(x INTEGER)")?;
+        let image = src.serialize(MAIN_DB)?;
+
+        let mut dst = Connection::open_in_memory()?;
+        dst.deserialize(MAIN_DB, image, true)?;
+        dst.execute("INSERT INTO foo VALUES(1)", []).unwrap_err();
+        Ok(())
+    }
+
+    #[test]
+    fn test_serialize_unknown_schema() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.serialize(DatabaseName::Attached("unknown")).unwrap_err();
+        Ok(())
+    }
+}
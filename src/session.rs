@@ -14,7 +14,7 @@ use fallible_streaming_iterator::FallibleStreamingIterator;
 use crate::error::error_from_sqlite_code;
 use crate::ffi;
 use crate::hooks::Action;
-use crate::types::ValueRef;
+use crate::types::{Value, ValueRef};
 use crate::{errmsg_to_string, str_to_cstring, Connection, DatabaseName, Result};
 
 // https://sqlite.org/session.html
@@ -22,7 +22,7 @@ use crate::{errmsg_to_string, str_to_cstring, Connection, DatabaseName, Result};
 /// `feature = "session"` An instance of this object is a session that can be
 /// used to record changes to a database.
 pub struct Session<'conn> {
-    phantom: PhantomData<&'conn Connection>,
+    db: &'conn Connection,
     s: *mut ffi::sqlite3_session,
     filter: Option<Box<dyn Fn(&str) -> bool>>,
 }
@@ -35,6 +35,12 @@ impl Session<'_> {
     }
 
     /// Create a new session object
+    ///
+    /// # Failure
+    ///
+    /// SQLite has a single preupdate hook per connection: will return `Err`
+    /// with `SQLITE_MISUSE` if one has been registered with
+    /// [`Connection::preupdate_hook`].
     #[inline]
     pub fn new_with_name<'conn>(
         db: &'conn Connection,
@@ -42,13 +48,20 @@ impl Session<'_> {
     ) -> Result<Session<'conn>> {
         let name = name.to_cstring()?;
 
-        let db = db.db.borrow_mut().db;
+        let mut c = db.db.borrow_mut();
+        if c.free_preupdate_hook.is_some() {
+            return Err(error_from_sqlite_code(
+                ffi::SQLITE_MISUSE,
+                Some("preupdate hook is used by the connection".to_owned()),
+            ));
+        }
 
         let mut s: *mut ffi::sqlite3_session = ptr::null_mut();
-        check!(unsafe { ffi::sqlite3session_create(db, name.as_ptr(), &mut s) });
+        check!(unsafe { ffi::sqlite3session_create(c.db(), name.as_ptr(), &mut s) });
+        c.sessions += 1;
 
         Ok(Session {
-            phantom: PhantomData,
+            db,
             s,
             filter: None,
         })
@@ -220,6 +233,7 @@ impl Drop for Session<'_> {
             self.table_filter(None::<fn(&str) -> bool>);
         }
         unsafe { ffi::sqlite3session_delete(self.s) };
+        self.db.db.borrow_mut().sessions -= 1;
     }
 }
 
@@ -302,6 +316,74 @@ impl Changeset {
         });
         Ok(Changeset { cs, n })
     }
+
+    /// Copy all the changes of this changeset.
+    pub fn to_changes(&self) -> Result<Vec<Change>> {
+        let mut changes = Vec::new();
+        let mut iter = self.iter()?;
+        while let Some(item) = iter.next()? {
+            changes.push(item.to_owned()?);
+        }
+        Ok(changes)
+    }
+
+    /// Build a changeset from a list of changes, in the binary format
+    /// documented [here](https://sqlite.org/sessionintro.html#changeset_format).
+    ///
+    /// Each change must have as many `old` (for a DELETE or an UPDATE) and
+    /// `new` (for an INSERT or an UPDATE) values as `pk_columns`, and no
+    /// values on the other side.
+    pub fn from_changes<'c, I>(changes: I) -> Result<Changeset>
+    where
+        I: IntoIterator<Item = &'c Change>,
+    {
+        let mut buf = Vec::new();
+        let mut table: Option<(&str, &[u8])> = None;
+        for change in changes {
+            let n = change.pk_columns.len();
+            let (old, new) = match change.op {
+                Action::SQLITE_INSERT => (0, n),
+                Action::SQLITE_DELETE => (n, 0),
+                Action::SQLITE_UPDATE => (n, n),
+                _ => return Err(invalid_change(change, "unknown operation")),
+            };
+            if change.old.len() != old || change.new.len() != new {
+                return Err(invalid_change(change, "wrong number of values"));
+            }
+            if table != Some((&change.table, &change.pk_columns)) {
+                buf.push(b'T');
+                put_varint(&mut buf, n as u64);
+                buf.extend_from_slice(&change.pk_columns);
+                buf.extend_from_slice(str_to_cstring(&change.table)?.as_bytes_with_nul());
+                table = Some((&change.table, &change.pk_columns));
+            }
+            buf.push(change.op as u8);
+            buf.push(change.indirect as u8);
+            for value in change.old.iter().chain(change.new.iter()) {
+                put_value(&mut buf, value.as_ref());
+            }
+        }
+        if buf.len() > c_int::MAX as usize {
+            return Err(error_from_sqlite_code(ffi::SQLITE_TOOBIG, None));
+        }
+        if buf.is_empty() {
+            return Ok(Changeset {
+                cs: ptr::null_mut(),
+                n: 0,
+            });
+        }
+        unsafe {
+            let cs = ffi::sqlite3_malloc(buf.len() as c_int);
+            if cs.is_null() {
+                return Err(error_from_sqlite_code(ffi::SQLITE_NOMEM, None));
+            }
+            ptr::copy_nonoverlapping(buf.as_ptr(), cs as *mut u8, buf.len());
+            Ok(Changeset {
+                cs,
+                n: buf.len() as c_int,
+            })
+        }
+    }
 }
 
 impl Drop for Changeset {
@@ -514,6 +596,81 @@ impl ChangesetItem {
             Ok(from_raw_parts(pks, number_of_columns as usize))
         }
     }
+
+    /// Copy the current change, so that it can outlive the iterator.
+    pub fn to_owned(&self) -> Result<Change> {
+        let op = self.op()?;
+        let n = op.number_of_columns();
+        let (old, new) = match op.code() {
+            Action::SQLITE_INSERT => (false, true),
+            Action::SQLITE_DELETE => (true, false),
+            _ => (true, true),
+        };
+        type ValueFn = unsafe extern "C" fn(
+            *mut ffi::sqlite3_changeset_iter,
+            c_int,
+            *mut *mut ffi::sqlite3_value,
+        ) -> c_int;
+        let values = |f: ValueFn| -> Result<Vec<Option<Value>>> {
+            (0..n)
+                .map(|col| unsafe {
+                    let mut p_value: *mut ffi::sqlite3_value = ptr::null_mut();
+                    check!(f(self.it, col, &mut p_value));
+                    if p_value.is_null() {
+                        return Ok(None);
+                    }
+                    Ok(Some(match ValueRef::from_value(p_value) {
+                        ValueRef::Text(s) => Value::Text(std::str::from_utf8(s)?.to_owned()),
+                        value => Value::from(value),
+                    }))
+                })
+                .collect()
+        };
+        Ok(Change {
+            table: op.table_name().to_owned(),
+            op: op.code(),
+            indirect: op.indirect(),
+            pk_columns: self.pk()?.to_vec(),
+            old: if old {
+                values(ffi::sqlite3changeset_old)?
+            } else {
+                Vec::new()
+            },
+            new: if new {
+                values(ffi::sqlite3changeset_new)?
+            } else {
+                Vec::new()
+            },
+        })
+    }
+}
+
+/// `feature = "session"` An owned copy of a change, as returned by
+/// [`ChangesetItem::to_owned`], to be logged, filtered or transformed, and
+/// turned back into a changeset with [`Changeset::from_changes`].
+///
+/// With `feature = "serde"`, changes can be serialized, e.g. as JSON to
+/// inspect a changeset. Values are then tagged with their type
+/// (`{"Integer": 1}`, `"Null"`), and undefined values are `null`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Change {
+    /// Name of the table
+    pub table: String,
+    /// INSERT, UPDATE or DELETE
+    pub op: Action,
+    /// `true` for an 'indirect' change
+    pub indirect: bool,
+    /// For each column of the table, its 1-based position in the primary
+    /// key, or 0 if the column is not part of the primary key.
+    pub pk_columns: Vec<u8>,
+    /// old.* values of a DELETE or UPDATE, empty for an INSERT. For an UPDATE,
+    /// values of unmodified columns outside the primary key are undefined
+    /// (`None`).
+    pub old: Vec<Option<Value>>,
+    /// new.* values of an INSERT or UPDATE, empty for a DELETE. For an UPDATE,
+    /// values of unmodified columns are undefined (`None`).
+    pub new: Vec<Option<Value>>,
 }
 
 /// `feature = "session"` Used to combine two or more changesets or
@@ -585,6 +742,122 @@ impl Drop for Changegroup {
     }
 }
 
+/// `feature = "session"` Rebase buffer returned by
+/// [`Connection::apply_v2`](crate::Connection::apply_v2), describing how the
+/// conflicts met while applying a changeset were resolved, to be passed to
+/// [`Rebaser::configure`].
+#[cfg(feature = "modern_sqlite")] // 3.22.0
+pub struct RebaseBuffer {
+    buf: *mut c_void,
+    n: c_int,
+}
+
+#[cfg(feature = "modern_sqlite")] // 3.22.0
+impl RebaseBuffer {
+    /// Content of the rebase buffer.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        if self.buf.is_null() {
+            &[]
+        } else {
+            unsafe { from_raw_parts(self.buf as *const u8, self.n as usize) }
+        }
+    }
+}
+
+#[cfg(feature = "modern_sqlite")] // 3.22.0
+impl Drop for RebaseBuffer {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe {
+            ffi::sqlite3_free(self.buf);
+        }
+    }
+}
+
+/// `feature = "session"` Used to rebase a local changeset on top of the
+/// changes applied from a remote one, as described by the rebase buffer
+/// returned by [`Connection::apply_v2`](crate::Connection::apply_v2).
+/// See [here](https://sqlite.org/session/rebaser.html) for details.
+#[cfg(feature = "modern_sqlite")] // 3.22.0
+pub struct Rebaser {
+    r: *mut ffi::sqlite3_rebaser,
+}
+
+#[cfg(feature = "modern_sqlite")] // 3.22.0
+impl Rebaser {
+    /// Create a new rebaser.
+    #[inline]
+    pub fn new() -> Result<Self> {
+        let mut r = ptr::null_mut();
+        check!(unsafe { ffi::sqlite3rebaser_create(&mut r) });
+        Ok(Rebaser { r })
+    }
+
+    /// Add a rebase buffer, as returned by
+    /// [`Connection::apply_v2`](crate::Connection::apply_v2), to the
+    /// changes this rebaser rebases on.
+    #[inline]
+    pub fn configure(&mut self, rebase: &RebaseBuffer) -> Result<()> {
+        check!(unsafe { ffi::sqlite3rebaser_configure(self.r, rebase.n, rebase.buf) });
+        Ok(())
+    }
+
+    /// Add a rebase buffer stored from [`RebaseBuffer::as_bytes`] (e.g. to
+    /// rebase local changes once a remote changeset has been applied by
+    /// another process).
+    #[inline]
+    pub fn configure_bytes(&mut self, rebase: &[u8]) -> Result<()> {
+        if rebase.len() > c_int::MAX as usize {
+            return Err(error_from_sqlite_code(ffi::SQLITE_TOOBIG, None));
+        }
+        check!(unsafe {
+            ffi::sqlite3rebaser_configure(
+                self.r,
+                rebase.len() as c_int,
+                rebase.as_ptr() as *const c_void,
+            )
+        });
+        Ok(())
+    }
+
+    /// Rebase a changeset
+    #[inline]
+    pub fn rebase(&mut self, cs: &Changeset) -> Result<Changeset> {
+        let mut n = 0;
+        let mut output: *mut c_void = ptr::null_mut();
+        check!(unsafe { ffi::sqlite3rebaser_rebase(self.r, cs.n, cs.cs, &mut n, &mut output) });
+        Ok(Changeset { cs: output, n })
+    }
+
+    /// Rebase the changeset read from `input` and write it to `output`.
+    #[inline]
+    pub fn rebase_strm(&mut self, input: &mut dyn Read, output: &mut dyn Write) -> Result<()> {
+        let input_ref = &input;
+        let output_ref = &output;
+        check!(unsafe {
+            ffi::sqlite3rebaser_rebase_strm(
+                self.r,
+                Some(x_input),
+                input_ref as *const &mut dyn Read as *mut c_void,
+                Some(x_output),
+                output_ref as *const &mut dyn Write as *mut c_void,
+            )
+        });
+        Ok(())
+    }
+}
+
+#[cfg(feature = "modern_sqlite")] // 3.22.0
+impl Drop for Rebaser {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe {
+            ffi::sqlite3rebaser_delete(self.r);
+        }
+    }
+}
+
 impl Connection {
     /// `feature = "session"` Apply a changeset to a database
     pub fn apply<F, C>(&self, cs: &Changeset, filter: Option<F>, conflict: C) -> Result<()>
@@ -659,6 +932,88 @@ impl Connection {
         });
         Ok(())
     }
+
+    /// `feature = "session"` Apply a changeset to a database, with `flags`,
+    /// and return the rebase buffer to be used with a [`Rebaser`].
+    #[cfg(feature = "modern_sqlite")] // 3.22.0
+    pub fn apply_v2<F, C>(
+        &self,
+        cs: &Changeset,
+        filter: Option<F>,
+        conflict: C,
+        flags: ApplyFlags,
+    ) -> Result<RebaseBuffer>
+    where
+        F: Fn(&str) -> bool + Send + RefUnwindSafe + 'static,
+        C: Fn(ConflictType, ChangesetItem) -> ConflictAction + Send + RefUnwindSafe + 'static,
+    {
+        let db = self.db.borrow_mut().db;
+
+        let mut n = 0;
+        let mut rebase: *mut c_void = ptr::null_mut();
+        let filtered = filter.is_some();
+        let tuple = &mut (filter, conflict);
+        check!(unsafe {
+            ffi::sqlite3changeset_apply_v2(
+                db,
+                cs.n,
+                cs.cs,
+                if filtered {
+                    Some(call_filter::<F, C>)
+                } else {
+                    None
+                },
+                Some(call_conflict::<F, C>),
+                tuple as *mut (Option<F>, C) as *mut c_void,
+                &mut rebase,
+                &mut n,
+                flags.bits(),
+            )
+        });
+        Ok(RebaseBuffer { buf: rebase, n })
+    }
+
+    /// `feature = "session"` Apply a changeset read from `input` to a
+    /// database, with `flags`, and return the rebase buffer to be used with a
+    /// [`Rebaser`].
+    #[cfg(feature = "modern_sqlite")] // 3.22.0
+    pub fn apply_v2_strm<F, C>(
+        &self,
+        input: &mut dyn Read,
+        filter: Option<F>,
+        conflict: C,
+        flags: ApplyFlags,
+    ) -> Result<RebaseBuffer>
+    where
+        F: Fn(&str) -> bool + Send + RefUnwindSafe + 'static,
+        C: Fn(ConflictType, ChangesetItem) -> ConflictAction + Send + RefUnwindSafe + 'static,
+    {
+        let input_ref = &input;
+        let db = self.db.borrow_mut().db;
+
+        let mut n = 0;
+        let mut rebase: *mut c_void = ptr::null_mut();
+        let filtered = filter.is_some();
+        let tuple = &mut (filter, conflict);
+        check!(unsafe {
+            ffi::sqlite3changeset_apply_v2_strm(
+                db,
+                Some(x_input),
+                input_ref as *const &mut dyn Read as *mut c_void,
+                if filtered {
+                    Some(call_filter::<F, C>)
+                } else {
+                    None
+                },
+                Some(call_conflict::<F, C>),
+                tuple as *mut (Option<F>, C) as *mut c_void,
+                &mut rebase,
+                &mut n,
+                flags.bits(),
+            )
+        });
+        Ok(RebaseBuffer { buf: rebase, n })
+    }
 }
 
 /// `feature = "session"` Constants passed to the conflict handler
@@ -700,6 +1055,20 @@ pub enum ConflictAction {
     SQLITE_CHANGESET_ABORT = ffi::SQLITE_CHANGESET_ABORT,
 }
 
+#[cfg(feature = "modern_sqlite")] // 3.22.0
+bitflags::bitflags! {
+    /// `feature = "session"` Flags for
+    /// [`Connection::apply_v2`](crate::Connection::apply_v2).
+    /// See [here](https://sqlite.org/session/c_changesetapply_invert.html) for details.
+    #[repr(C)]
+    pub struct ApplyFlags: c_int {
+        /// Do not wrap the application of the changeset in a savepoint.
+        const SQLITE_CHANGESETAPPLY_NOSAVEPOINT = 0x0001; // 3.22.0
+        /// Invert the changeset before applying it.
+        const SQLITE_CHANGESETAPPLY_INVERT      = 0x0002; // 3.26.0
+    }
+}
+
 unsafe extern "C" fn call_filter<F, C>(p_ctx: *mut c_void, tbl_str: *const c_char) -> c_int
 where
     F: Fn(&str) -> bool + Send + RefUnwindSafe + 'static,
@@ -743,6 +1112,55 @@ where
     }
 }
 
+fn invalid_change(change: &Change, msg: &str) -> crate::Error {
+    error_from_sqlite_code(
+        ffi::SQLITE_MISUSE,
+        Some(format!("invalid change on {}: {}", change.table, msg)),
+    )
+}
+
+// SQLite variable-length integer, as written by `sqlite3PutVarint` for
+// values below 2^56.
+fn put_varint(buf: &mut Vec<u8>, mut v: u64) {
+    let mut bytes = [0u8; 8];
+    let mut n = 0;
+    loop {
+        bytes[n] = (v & 0x7f) as u8 | 0x80;
+        n += 1;
+        v >>= 7;
+        if v == 0 {
+            break;
+        }
+    }
+    bytes[0] &= 0x7f;
+    buf.extend(bytes[..n].iter().rev());
+}
+
+fn put_value(buf: &mut Vec<u8>, value: Option<&Value>) {
+    match value {
+        None => buf.push(0),
+        Some(Value::Integer(i)) => {
+            buf.push(ffi::SQLITE_INTEGER as u8);
+            buf.extend_from_slice(&i.to_be_bytes());
+        }
+        Some(Value::Real(f)) => {
+            buf.push(ffi::SQLITE_FLOAT as u8);
+            buf.extend_from_slice(&f.to_bits().to_be_bytes());
+        }
+        Some(Value::Text(s)) => {
+            buf.push(ffi::SQLITE_TEXT as u8);
+            put_varint(buf, s.len() as u64);
+            buf.extend_from_slice(s.as_bytes());
+        }
+        Some(Value::Blob(b)) => {
+            buf.push(ffi::SQLITE_BLOB as u8);
+            put_varint(buf, b.len() as u64);
+            buf.extend_from_slice(b);
+        }
+        Some(Value::Null) => buf.push(ffi::SQLITE_NULL as u8),
+    }
+}
+
 unsafe extern "C" fn x_input(p_in: *mut c_void, data: *mut c_void, len: *mut c_int) -> c_int {
     if p_in.is_null() {
         return ffi::SQLITE_MISUSE;
@@ -779,8 +1197,11 @@ mod test {
     use std::io::Read;
     use std::sync::atomic::{AtomicBool, Ordering};
 
+    #[cfg(feature = "modern_sqlite")]
+    use super::{ApplyFlags, Rebaser};
     use super::{Changeset, ChangesetIter, ConflictAction, ConflictType, Session};
     use crate::hooks::Action;
+    use crate::types::Value;
     use crate::{Connection, Result};
 
     fn one_changeset() -> Result<Changeset> {
@@ -906,6 +1327,281 @@ mod test {
         Ok(())
     }
 
+    #[cfg(feature = "modern_sqlite")]
+    fn foo_db(value: &str) -> Result<Connection> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(id INTEGER PRIMARY KEY, t TEXT NOT NULL);")?;
+        db.execute("INSERT INTO foo (id, t) VALUES (1, ?);", [value])?;
+        Ok(db)
+    }
+
+    #[cfg(feature = "modern_sqlite")]
+    fn update_foo(db: &Connection, value: &str) -> Result<Changeset> {
+        let mut session = Session::new(db)?;
+        session.attach(None)?;
+        db.execute("UPDATE foo SET t = ? WHERE id = 1;", [value])?;
+        session.changeset()
+    }
+
+    #[cfg(feature = "modern_sqlite")]
+    fn foo_value(db: &Connection) -> Result<String> {
+        db.query_row("SELECT t FROM foo WHERE id = 1", [], |row| row.get(0))
+    }
+
+    #[test]
+    #[cfg(feature = "modern_sqlite")]
+    fn test_changeset_apply_v2_flags() -> Result<()> {
+        const SCHEMA: &str = "CREATE TABLE foo(t TEXT PRIMARY KEY NOT NULL);
+                              CREATE TABLE bar(t TEXT PRIMARY KEY NOT NULL);";
+        // the change to `bar` comes after the one to `foo`
+        let changeset = {
+            let db = Connection::open_in_memory()?;
+            db.execute_batch(SCHEMA)?;
+            let mut session = Session::new(&db)?;
+            session.attach(None)?;
+            db.execute_batch("INSERT INTO foo VALUES ('foo'); INSERT INTO bar VALUES ('bar');")?;
+            session.changeset()?
+        };
+
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(SCHEMA)?;
+        db.execute_batch("INSERT INTO bar VALUES ('bar')")?;
+        // without savepoint, the change applied before the aborting conflict
+        // is kept in the enclosing transaction
+        for &(flags, expected) in [
+            (ApplyFlags::empty(), 0),
+            (ApplyFlags::SQLITE_CHANGESETAPPLY_NOSAVEPOINT, 1),
+        ]
+        .iter()
+        {
+            db.execute_batch("BEGIN")?;
+            let r = db.apply_v2(
+                &changeset,
+                None::<fn(&str) -> bool>,
+                |conflict_type, _item| {
+                    assert_eq!(ConflictType::SQLITE_CHANGESET_CONFLICT, conflict_type);
+                    ConflictAction::SQLITE_CHANGESET_ABORT
+                },
+                flags,
+            );
+            assert!(r.is_err());
+            let count: i64 = db.query_row("SELECT COUNT(*) FROM foo", [], |row| row.get(0))?;
+            assert_eq!(expected, count);
+            db.execute_batch("ROLLBACK")?;
+        }
+
+        let changeset = one_changeset()?;
+        db.execute("INSERT INTO foo (t) VALUES (?);", ["bar"])?;
+        db.apply_v2(
+            &changeset,
+            None::<fn(&str) -> bool>,
+            |_conflict_type, _item| ConflictAction::SQLITE_CHANGESET_ABORT,
+            ApplyFlags::SQLITE_CHANGESETAPPLY_INVERT,
+        )?;
+        let count: i64 = db.query_row("SELECT COUNT(*) FROM foo", [], |row| row.get(0))?;
+        assert_eq!(0, count);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "modern_sqlite")]
+    fn test_rebaser() -> Result<()> {
+        let local = foo_db("orig")?;
+        let remote = foo_db("orig")?;
+        let local_changeset = update_foo(&local, "local")?;
+        let remote_changeset = update_foo(&remote, "remote")?;
+
+        // keep the local value when applying the remote change locally
+        let rebase = local.apply_v2(
+            &remote_changeset,
+            None::<fn(&str) -> bool>,
+            |conflict_type, _item| {
+                assert_eq!(ConflictType::SQLITE_CHANGESET_DATA, conflict_type);
+                ConflictAction::SQLITE_CHANGESET_OMIT
+            },
+            ApplyFlags::empty(),
+        )?;
+        assert_eq!("local", foo_value(&local)?);
+        assert!(!rebase.as_bytes().is_empty());
+
+        let mut rebaser = Rebaser::new()?;
+        rebaser.configure(&rebase)?;
+        let rebased = rebaser.rebase(&local_changeset)?;
+        {
+            let mut iter = rebased.iter()?;
+            let item = iter.next()?.unwrap();
+            assert_eq!(Ok("remote"), item.old_value(1)?.as_str());
+            assert_eq!(Ok("local"), item.new_value(1)?.as_str());
+        }
+
+        // a stored rebase buffer can be used later on
+        let stored = rebase.as_bytes().to_vec();
+        drop(rebase);
+        let mut rebaser = Rebaser::new()?;
+        rebaser.configure_bytes(&stored)?;
+        let rebased = rebaser.rebase(&local_changeset)?;
+        {
+            let mut iter = rebased.iter()?;
+            let item = iter.next()?.unwrap();
+            assert_eq!(Ok("remote"), item.old_value(1)?.as_str());
+            assert_eq!(Ok("local"), item.new_value(1)?.as_str());
+        }
+
+        // the rebased local change applies cleanly remotely
+        remote.apply(
+            &rebased,
+            None::<fn(&str) -> bool>,
+            |_conflict_type, _item| ConflictAction::SQLITE_CHANGESET_ABORT,
+        )?;
+        assert_eq!("local", foo_value(&remote)?);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "modern_sqlite")]
+    fn test_rebaser_strm() -> Result<()> {
+        let local = foo_db("orig")?;
+        let remote = foo_db("orig")?;
+        let mut local_changeset = Vec::new();
+        {
+            let mut session = Session::new(&local)?;
+            session.attach(None)?;
+            local.execute("UPDATE foo SET t = 'local' WHERE id = 1;", [])?;
+            session.changeset_strm(&mut local_changeset)?;
+        }
+        let mut remote_changeset = Vec::new();
+        {
+            let mut session = Session::new(&remote)?;
+            session.attach(None)?;
+            remote.execute("UPDATE foo SET t = 'remote' WHERE id = 1;", [])?;
+            session.changeset_strm(&mut remote_changeset)?;
+        }
+
+        let rebase = local.apply_v2_strm(
+            &mut remote_changeset.as_slice(),
+            None::<fn(&str) -> bool>,
+            |_conflict_type, _item| ConflictAction::SQLITE_CHANGESET_OMIT,
+            ApplyFlags::empty(),
+        )?;
+
+        let mut rebaser = Rebaser::new()?;
+        rebaser.configure(&rebase)?;
+        let mut rebased = Vec::new();
+        rebaser.rebase_strm(&mut local_changeset.as_slice(), &mut rebased)?;
+
+        remote.apply_strm(
+            &mut rebased.as_slice(),
+            None::<fn(&str) -> bool>,
+            |_conflict_type, _item| ConflictAction::SQLITE_CHANGESET_ABORT,
+        )?;
+        assert_eq!("local", foo_value(&remote)?);
+        Ok(())
+    }
+
+    fn typed_changeset() -> Result<Changeset> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE foo(a INTEGER, b, c TEXT, PRIMARY KEY(c, a));
+             INSERT INTO foo VALUES (1, 1.5, 'x'), (2, x'0102', 'y');",
+        )?;
+        let mut session = Session::new(&db)?;
+        session.attach(None)?;
+        db.execute_batch(
+            "INSERT INTO foo VALUES (3, NULL, 'z');
+             UPDATE foo SET b = 2.5 WHERE a = 1;
+             DELETE FROM foo WHERE a = 2;",
+        )?;
+        session.changeset()
+    }
+
+    #[test]
+    fn test_changeset_to_owned() -> Result<()> {
+        let changeset = typed_changeset()?;
+        let changes = changeset.to_changes()?;
+        assert_eq!(3, changes.len());
+
+        let insert = changes
+            .iter()
+            .find(|c| c.op == Action::SQLITE_INSERT)
+            .unwrap();
+        assert_eq!("foo", insert.table);
+        assert!(!insert.indirect);
+        assert_eq!(vec![2, 0, 1], insert.pk_columns);
+        assert!(insert.old.is_empty());
+        assert_eq!(
+            vec![
+                Some(Value::Integer(3)),
+                Some(Value::Null),
+                Some(Value::Text("z".to_owned()))
+            ],
+            insert.new
+        );
+
+        let update = changes
+            .iter()
+            .find(|c| c.op == Action::SQLITE_UPDATE)
+            .unwrap();
+        assert_eq!(
+            vec![
+                Some(Value::Integer(1)),
+                Some(Value::Real(1.5)),
+                Some(Value::Text("x".to_owned()))
+            ],
+            update.old
+        );
+        assert_eq!(vec![None, Some(Value::Real(2.5)), None], update.new);
+
+        let delete = changes
+            .iter()
+            .find(|c| c.op == Action::SQLITE_DELETE)
+            .unwrap();
+        assert_eq!(Some(Value::Blob(vec![1, 2])), delete.old[1]);
+        assert!(delete.new.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_changeset_from_changes() -> Result<()> {
+        let mut changes = typed_changeset()?.to_changes()?;
+        changes.retain(|c| c.op != Action::SQLITE_DELETE);
+        let changeset = Changeset::from_changes(&changes)?;
+        assert_eq!(changes, changeset.to_changes()?);
+
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE foo(a INTEGER, b, c TEXT, PRIMARY KEY(c, a));
+             INSERT INTO foo VALUES (1, 1.5, 'x');",
+        )?;
+        db.apply(
+            &changeset,
+            None::<fn(&str) -> bool>,
+            |_conflict_type, _item| ConflictAction::SQLITE_CHANGESET_ABORT,
+        )?;
+        let sum: f64 = db.query_row("SELECT SUM(a + IFNULL(b, 0)) FROM foo", [], |r| r.get(0))?;
+        assert_eq!(6.5, sum);
+
+        let empty = Changeset::from_changes(&[])?;
+        assert!(empty.to_changes()?.is_empty());
+
+        changes[0].new.pop();
+        assert!(Changeset::from_changes(&changes).is_err());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "serde", feature = "serde_json"))]
+    fn test_change_json() -> Result<()> {
+        use super::Change;
+
+        let changes = typed_changeset()?.to_changes()?;
+        let json = serde_json::to_string(&changes).unwrap();
+        assert!(json.contains(r#""op":"SQLITE_INSERT""#));
+        assert!(json.contains(r#""new":[null,{"Real":2.5},null]"#));
+        let changes2: Vec<Change> = serde_json::from_str(&json).unwrap();
+        assert_eq!(changes, changes2);
+        Ok(())
+    }
+
     #[test]
     fn test_session_empty() -> Result<()> {
         let db = Connection::open_in_memory()?;
@@ -921,6 +1617,34 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_session_preupdate_hook() -> Result<()> {
+        use crate::hooks::PreUpdateCase;
+
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(t TEXT PRIMARY KEY NOT NULL);")?;
+
+        let mut session = Session::new(&db)?;
+        session.attach(None)?;
+        db.preupdate_hook(Some(|_, _: &str, _: &str, _, _, _: &PreUpdateCase<'_>| {}))
+            .unwrap_err();
+        db.preupdate_hook(None::<fn(Action, &str, &str, i64, i64, &PreUpdateCase<'_>)>)
+            .unwrap_err();
+        db.execute("INSERT INTO foo (t) VALUES (?);", ["bar"])?;
+        assert!(!session.is_empty());
+        drop(session);
+
+        db.preupdate_hook(Some(|_, _: &str, _: &str, _, _, _: &PreUpdateCase<'_>| {}))?;
+        assert!(Session::new(&db).is_err());
+        db.preupdate_hook(None::<fn(Action, &str, &str, i64, i64, &PreUpdateCase<'_>)>)?;
+
+        let mut session = Session::new(&db)?;
+        session.attach(None)?;
+        db.execute("INSERT INTO foo (t) VALUES (?);", ["baz"])?;
+        assert!(!session.is_empty());
+        Ok(())
+    }
+
     #[test]
     fn test_session_set_enabled() -> Result<()> {
         let db = Connection::open_in_memory()?;
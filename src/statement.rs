@@ -10,14 +10,17 @@ use super::{len_as_c_int, str_for_sqlite};
 use super::{
     AndThenRows, Connection, Error, MappedRows, Params, RawStatement, Result, Row, Rows, ValueRef,
 };
+use crate::instrument::StatementSpan;
 use crate::types::{ToSql, ToSqlOutput};
 #[cfg(feature = "array")]
 use crate::vtab::array::{free_array, ARRAY_TYPE};
 
 /// A prepared statement.
 pub struct Statement<'conn> {
-    conn: &'conn Connection,
+    pub(crate) conn: &'conn Connection,
     pub(crate) stmt: RawStatement,
+    #[cfg(feature = "hooks")]
+    pub(crate) timeout: Option<std::time::Duration>,
 }
 
 impl Statement<'_> {
@@ -702,13 +705,39 @@ impl Statement<'_> {
     #[inline]
     fn execute_with_bound_parameters(&mut self) -> Result<usize> {
         self.check_update()?;
-        let r = self.stmt.step();
+        let mut span = StatementSpan::new(self);
+        #[cfg(feature = "hooks")]
+        let deadline = crate::hooks::ExecutionDeadline::start(self);
+        #[cfg(feature = "statement_checker")]
+        let counts = crate::checker::Counts::start(self);
+        let r = span.in_scope(|| {
+            #[cfg(feature = "hooks")]
+            {
+                deadline.step(self, || self.stmt.step())
+            }
+            #[cfg(not(feature = "hooks"))]
+            {
+                self.stmt.step()
+            }
+        });
         self.stmt.reset();
-        match r {
+        #[cfg(feature = "statement_checker")]
+        if let Some(counts) = counts {
+            counts.finish(self);
+        }
+        let r = match r {
             ffi::SQLITE_DONE => Ok(self.conn.changes()),
             ffi::SQLITE_ROW => Err(Error::ExecuteReturnedResults),
             _ => Err(self.conn.decode_result(r).unwrap_err()),
+        };
+        match r {
+            Ok(changes) => span.finish(self, Some(changes)),
+            Err(ref err) => {
+                span.error(err);
+                span.finish(self, None);
+            }
         }
+        r
     }
 
     #[inline]
@@ -781,6 +810,18 @@ impl Statement<'_> {
         self.stmt.get_status(status, true)
     }
 
+    /// `feature = "hooks"` Set the maximum duration of each execution of the
+    /// statement (from the first step until it is reset), after which it is
+    /// interrupted and fails with [`Error::DeadlineExceeded`]. `None` (the
+    /// default) removes the timeout.
+    ///
+    /// See [`Connection::with_deadline`].
+    #[cfg(feature = "hooks")]
+    #[inline]
+    pub fn set_timeout(&mut self, timeout: Option<std::time::Duration>) {
+        self.timeout = timeout;
+    }
+
     #[cfg(feature = "extra_check")]
     #[inline]
     pub(crate) fn check_no_tail(&self) -> Result<()> {
@@ -834,7 +875,12 @@ impl Drop for Statement<'_> {
 impl Statement<'_> {
     #[inline]
     pub(super) fn new(conn: &Connection, stmt: RawStatement) -> Statement<'_> {
-        Statement { conn, stmt }
+        Statement {
+            conn,
+            stmt,
+            #[cfg(feature = "hooks")]
+            timeout: None,
+        }
     }
 
     pub(super) fn value_ref(&self, col: usize) -> ValueRef<'_> {
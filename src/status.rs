@@ -0,0 +1,267 @@
+//! Database connection and global run-time status counters
+
+use std::os::raw::c_int;
+
+use crate::ffi;
+use crate::{Connection, Result};
+
+/// Database connection status counters.
+/// See [Status Parameters for database connections](https://sqlite.org/c3ref/c_dbstatus_options.html) for details.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(i32)]
+#[non_exhaustive]
+pub enum DbStatus {
+    /// Equivalent to SQLITE_DBSTATUS_LOOKASIDE_USED
+    LookasideUsed = 0,
+    /// Equivalent to SQLITE_DBSTATUS_CACHE_USED
+    CacheUsed = 1,
+    /// Equivalent to SQLITE_DBSTATUS_SCHEMA_USED
+    SchemaUsed = 2,
+    /// Equivalent to SQLITE_DBSTATUS_STMT_USED
+    StmtUsed = 3,
+    /// Equivalent to SQLITE_DBSTATUS_LOOKASIDE_HIT
+    LookasideHit = 4,
+    /// Equivalent to SQLITE_DBSTATUS_LOOKASIDE_MISS_SIZE
+    LookasideMissSize = 5,
+    /// Equivalent to SQLITE_DBSTATUS_LOOKASIDE_MISS_FULL
+    LookasideMissFull = 6,
+    /// Equivalent to SQLITE_DBSTATUS_CACHE_HIT
+    CacheHit = 7, // 3.7.9
+    /// Equivalent to SQLITE_DBSTATUS_CACHE_MISS
+    CacheMiss = 8, // 3.7.9
+    /// Equivalent to SQLITE_DBSTATUS_CACHE_WRITE
+    CacheWrite = 9, // 3.7.12
+    /// Equivalent to SQLITE_DBSTATUS_DEFERRED_FKS
+    DeferredFks = 10, // 3.8.0
+    /// Equivalent to SQLITE_DBSTATUS_CACHE_USED_SHARED
+    #[cfg(feature = "modern_sqlite")]
+    CacheUsedShared = 11, // 3.10.0
+    /// Equivalent to SQLITE_DBSTATUS_CACHE_SPILL
+    #[cfg(feature = "modern_sqlite")]
+    CacheSpill = 12, // 3.12.0
+}
+
+/// Global (process-wide) status counters.
+/// See [Status Parameters](https://sqlite.org/c3ref/c_status_malloc_count.html) for details.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(i32)]
+#[non_exhaustive]
+pub enum Status {
+    /// Equivalent to SQLITE_STATUS_MEMORY_USED
+    MemoryUsed = 0,
+    /// Equivalent to SQLITE_STATUS_PAGECACHE_USED
+    PageCacheUsed = 1,
+    /// Equivalent to SQLITE_STATUS_PAGECACHE_OVERFLOW
+    PageCacheOverflow = 2,
+    /// Equivalent to SQLITE_STATUS_MALLOC_SIZE
+    MallocSize = 5,
+    /// Equivalent to SQLITE_STATUS_PARSER_STACK
+    ParserStack = 6,
+    /// Equivalent to SQLITE_STATUS_PAGECACHE_SIZE
+    PageCacheSize = 7,
+    /// Equivalent to SQLITE_STATUS_MALLOC_COUNT
+    MallocCount = 9,
+}
+
+/// The current and highwater values of a status counter.
+///
+/// For counters without a meaningful highwater mark (like
+/// `DbStatus::CacheHit`), `highwater` is always 0.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counter {
+    /// Current value
+    pub current: i64,
+    /// Highest value recorded since the last reset
+    pub highwater: i64,
+}
+
+impl From<(i64, i64)> for Counter {
+    #[inline]
+    fn from((current, highwater): (i64, i64)) -> Counter {
+        Counter { current, highwater }
+    }
+}
+
+/// A snapshot of all the status counters of a connection, along with the
+/// global ones, as returned by [`Connection::status_snapshot`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct StatusSnapshot {
+    /// `DbStatus::LookasideUsed`
+    pub lookaside_used: Counter,
+    /// `DbStatus::LookasideHit`
+    pub lookaside_hit: Counter,
+    /// `DbStatus::LookasideMissSize`
+    pub lookaside_miss_size: Counter,
+    /// `DbStatus::LookasideMissFull`
+    pub lookaside_miss_full: Counter,
+    /// `DbStatus::CacheUsed`
+    pub cache_used: Counter,
+    /// `DbStatus::CacheHit`
+    pub cache_hit: Counter,
+    /// `DbStatus::CacheMiss`
+    pub cache_miss: Counter,
+    /// `DbStatus::CacheWrite`
+    pub cache_write: Counter,
+    /// `DbStatus::SchemaUsed`
+    pub schema_used: Counter,
+    /// `DbStatus::StmtUsed`
+    pub stmt_used: Counter,
+    /// `DbStatus::DeferredFks`
+    pub deferred_fks: Counter,
+    /// `Status::MemoryUsed`
+    pub memory_used: Counter,
+    /// `Status::MallocSize`
+    pub malloc_size: Counter,
+    /// `Status::MallocCount`
+    pub malloc_count: Counter,
+    /// `Status::PageCacheUsed`
+    pub page_cache_used: Counter,
+    /// `Status::PageCacheOverflow`
+    pub page_cache_overflow: Counter,
+    /// `Status::PageCacheSize`
+    pub page_cache_size: Counter,
+    /// `Status::ParserStack`
+    pub parser_stack: Counter,
+}
+
+impl StatusSnapshot {
+    /// Ratio of page cache hits to page cache lookups, or `None` if there
+    /// were no lookups.
+    pub fn cache_hit_ratio(&self) -> Option<f64> {
+        let hit = self.cache_hit.current;
+        let lookups = hit + self.cache_miss.current;
+        if lookups == 0 {
+            None
+        } else {
+            Some(hit as f64 / lookups as f64)
+        }
+    }
+}
+
+/// Returns the current and highwater values of a global status counter,
+/// resetting the highwater mark to the current value if `reset` is true.
+pub fn status(op: Status, reset: bool) -> Result<(i64, i64)> {
+    unsafe {
+        #[cfg(feature = "modern_sqlite")] // 3.10.0
+        let (rc, current, highwater) = {
+            let (mut current, mut highwater) = (0, 0);
+            let rc =
+                ffi::sqlite3_status64(op as c_int, &mut current, &mut highwater, reset as c_int);
+            (rc, current, highwater)
+        };
+        #[cfg(not(feature = "modern_sqlite"))]
+        let (rc, current, highwater) = {
+            let (mut current, mut highwater) = (0, 0);
+            let rc = ffi::sqlite3_status(op as c_int, &mut current, &mut highwater, reset as c_int);
+            (rc, i64::from(current), i64::from(highwater))
+        };
+        if rc != ffi::SQLITE_OK {
+            return Err(crate::error::error_from_sqlite_code(rc, None));
+        }
+        Ok((current, highwater))
+    }
+}
+
+impl Connection {
+    /// Returns the current and highwater values of a status counter of this
+    /// connection, resetting the counter (or its highwater mark) if `reset` is
+    /// true.
+    pub fn db_status(&self, op: DbStatus, reset: bool) -> Result<(i32, i32)> {
+        let c = self.db.borrow();
+        unsafe {
+            let mut current = 0;
+            let mut highwater = 0;
+            check!(ffi::sqlite3_db_status(
+                c.db(),
+                op as c_int,
+                &mut current,
+                &mut highwater,
+                reset as c_int
+            ));
+            Ok((current, highwater))
+        }
+    }
+
+    /// Gathers all the status counters of this connection and the global ones,
+    /// without resetting any of them.
+    pub fn status_snapshot(&self) -> Result<StatusSnapshot> {
+        let db = |op| {
+            self.db_status(op, false)
+                .map(|(cur, hi)| Counter::from((i64::from(cur), i64::from(hi))))
+        };
+        let global = |op| status(op, false).map(Counter::from);
+        Ok(StatusSnapshot {
+            lookaside_used: db(DbStatus::LookasideUsed)?,
+            lookaside_hit: db(DbStatus::LookasideHit)?,
+            lookaside_miss_size: db(DbStatus::LookasideMissSize)?,
+            lookaside_miss_full: db(DbStatus::LookasideMissFull)?,
+            cache_used: db(DbStatus::CacheUsed)?,
+            cache_hit: db(DbStatus::CacheHit)?,
+            cache_miss: db(DbStatus::CacheMiss)?,
+            cache_write: db(DbStatus::CacheWrite)?,
+            schema_used: db(DbStatus::SchemaUsed)?,
+            stmt_used: db(DbStatus::StmtUsed)?,
+            deferred_fks: db(DbStatus::DeferredFks)?,
+            memory_used: global(Status::MemoryUsed)?,
+            malloc_size: global(Status::MallocSize)?,
+            malloc_count: global(Status::MallocCount)?,
+            page_cache_used: global(Status::PageCacheUsed)?,
+            page_cache_overflow: global(Status::PageCacheOverflow)?,
+            page_cache_size: global(Status::PageCacheSize)?,
+            parser_stack: global(Status::ParserStack)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{status, DbStatus, Status};
+    use crate::{Connection, Result};
+
+    #[test]
+    fn test_db_status() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = Connection::open(temp_dir.path().join("status.db3"))?;
+        db.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES (1);")?;
+        let (used, _) = db.db_status(DbStatus::CacheUsed, false)?;
+        assert!(used > 0);
+        let (schema, _) = db.db_status(DbStatus::SchemaUsed, false)?;
+        assert!(schema > 0);
+
+        let (writes, _) = db.db_status(DbStatus::CacheWrite, true)?;
+        assert!(writes > 0);
+        assert_eq!((0, 0), db.db_status(DbStatus::CacheWrite, false)?);
+
+        db.execute_batch("PRAGMA foreign_keys = ON; PRAGMA defer_foreign_keys = ON;")?;
+        assert_eq!(0, db.db_status(DbStatus::DeferredFks, false)?.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_status() -> Result<()> {
+        let _db = Connection::open_in_memory()?;
+        let (current, highwater) = status(Status::MallocCount, false)?;
+        assert!(current > 0);
+        assert!(highwater >= current);
+        Ok(())
+    }
+
+    #[test]
+    fn test_status_snapshot() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES (1);")?;
+        db.db_status(DbStatus::CacheHit, true)?;
+        db.db_status(DbStatus::CacheMiss, true)?;
+        let snapshot = db.status_snapshot()?;
+        assert!(snapshot.cache_used.current > 0);
+        assert!(snapshot.memory_used.current > 0);
+        assert_eq!(None, snapshot.cache_hit_ratio());
+
+        let n: i64 = db.query_row("SELECT COUNT(*) FROM foo", [], |r| r.get(0))?;
+        assert_eq!(1, n);
+        let ratio = db.status_snapshot()?.cache_hit_ratio().unwrap();
+        assert!((0.0..=1.0).contains(&ratio));
+        Ok(())
+    }
+}
@@ -1,14 +1,28 @@
 //! `feature = "trace"` Tracing and profiling functions. Error and warning log.
 
+#[cfg(feature = "modern_sqlite")]
+use std::borrow::Cow;
 use std::ffi::{CStr, CString};
+#[cfg(feature = "modern_sqlite")]
+use std::marker::PhantomData;
 use std::mem;
+#[cfg(feature = "modern_sqlite")]
+use std::os::raw::c_uint;
 use std::os::raw::{c_char, c_int, c_void};
 use std::panic::catch_unwind;
+#[cfg(feature = "modern_sqlite")]
+use std::panic::AssertUnwindSafe;
 use std::ptr;
 use std::time::Duration;
 
 use super::ffi;
 use crate::error::error_from_sqlite_code;
+#[cfg(feature = "modern_sqlite")]
+use crate::inner_connection::InnerConnection;
+#[cfg(feature = "modern_sqlite")]
+use crate::util::SqliteMallocString;
+#[cfg(feature = "modern_sqlite")]
+use crate::StatementStatus;
 use crate::{Connection, Result};
 
 /// `feature = "trace"` Set up the process-wide SQLite error logging callback.
@@ -53,6 +67,44 @@ pub unsafe fn config_log(callback: Option<fn(c_int, &str)>) -> Result<()> {
     }
 }
 
+/// `feature = "trace"` Forward the SQLite error log to the `tracing` crate
+/// (`feature = "tracing"`) and/or to the `log` crate (`feature = "log"`),
+/// under the `rusqlite` target.
+///
+/// The level is derived from the primary result code: `SQLITE_NOTICE` is
+/// logged as info, `SQLITE_WARNING` as warning, `SQLITE_SCHEMA` (statements
+/// recompiled after a schema change) as debug, and any other code as error.
+///
+/// # Safety
+///
+/// See [`config_log`].
+#[cfg(any(feature = "tracing", feature = "log"))]
+pub unsafe fn forward_log() -> Result<()> {
+    config_log(Some(forward_log_callback))
+}
+
+#[cfg(any(feature = "tracing", feature = "log"))]
+fn forward_log_callback(err: c_int, msg: &str) {
+    // Not in the bindings of old SQLite versions.
+    const SQLITE_NOTICE: c_int = 27;
+    const SQLITE_WARNING: c_int = 28;
+
+    macro_rules! forward {
+        ($tracing:ident, $log:ident) => {{
+            #[cfg(feature = "tracing")]
+            tracing::$tracing!(target: "rusqlite", code = err, "{}", msg);
+            #[cfg(feature = "log")]
+            log::$log!(target: "rusqlite", "({}) {}", err, msg);
+        }};
+    }
+    match err & 0xff {
+        SQLITE_NOTICE => forward!(info, info),
+        SQLITE_WARNING => forward!(warn, warn),
+        ffi::SQLITE_SCHEMA => forward!(debug, debug),
+        _ => forward!(error, error),
+    }
+}
+
 /// `feature = "trace"` Write a message into the error log established by
 /// `config_log`.
 #[inline]
@@ -122,6 +174,167 @@ impl Connection {
     }
 }
 
+#[cfg(feature = "modern_sqlite")] // 3.14.0
+bitflags::bitflags! {
+    /// `feature = "trace"` Events traced by [`Connection::trace_v2`].
+    #[repr(C)]
+    pub struct TraceEventCodes: c_uint {
+        /// When a statement starts running (see [`TraceEvent::Stmt`]).
+        const SQLITE_TRACE_STMT = ffi::SQLITE_TRACE_STMT as c_uint;
+        /// When a statement finishes (see [`TraceEvent::Profile`]).
+        const SQLITE_TRACE_PROFILE = ffi::SQLITE_TRACE_PROFILE as c_uint;
+        /// When a statement generates a row (see [`TraceEvent::Row`]).
+        const SQLITE_TRACE_ROW = ffi::SQLITE_TRACE_ROW as c_uint;
+        /// When the connection closes (see [`TraceEvent::Close`]).
+        const SQLITE_TRACE_CLOSE = ffi::SQLITE_TRACE_CLOSE as c_uint;
+    }
+}
+
+/// `feature = "trace"` Statement being traced.
+///
+/// It is only valid for the duration of the trace callback.
+#[cfg(feature = "modern_sqlite")] // 3.14.0
+#[derive(Debug)]
+pub struct StmtRef<'s> {
+    ptr: *mut ffi::sqlite3_stmt,
+    phantom: PhantomData<&'s ()>,
+}
+
+#[cfg(feature = "modern_sqlite")]
+impl StmtRef<'_> {
+    /// Returns the text of the SQL used to prepare the statement.
+    pub fn sql(&self) -> Cow<'_, str> {
+        unsafe { CStr::from_ptr(ffi::sqlite3_sql(self.ptr)) }.to_string_lossy()
+    }
+
+    /// Returns the SQL text of the statement, with its bound parameters
+    /// expanded.
+    pub fn expanded_sql(&self) -> Option<String> {
+        unsafe { SqliteMallocString::from_raw(ffi::sqlite3_expanded_sql(self.ptr)) }
+            .map(|s| s.to_string_lossy().to_string())
+    }
+
+    /// Get the value for one of the status counters for this statement.
+    pub fn get_status(&self, status: StatementStatus) -> i32 {
+        unsafe { ffi::sqlite3_stmt_status(self.ptr, status as i32, 0) }
+    }
+}
+
+/// `feature = "trace"` Event passed to the [`Connection::trace_v2`] callback.
+#[cfg(feature = "modern_sqlite")] // 3.14.0
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TraceEvent<'s> {
+    /// A statement starts running (or a trigger starts, in which case `sql`
+    /// is a comment naming the trigger).
+    Stmt {
+        /// The statement.
+        stmt: StmtRef<'s>,
+        /// The unexpanded SQL text.
+        sql: Cow<'s, str>,
+        /// The SQL text with its bound parameters expanded.
+        expanded: Option<String>,
+    },
+    /// A statement finished.
+    Profile {
+        /// The statement.
+        stmt: StmtRef<'s>,
+        /// An estimate of how long it ran.
+        duration: Duration,
+    },
+    /// A statement generated a row.
+    Row {
+        /// The statement.
+        stmt: StmtRef<'s>,
+    },
+    /// The database connection is closing.
+    Close,
+}
+
+#[cfg(feature = "modern_sqlite")]
+pub(crate) type BoxedTracer = Box<dyn for<'s> FnMut(TraceEvent<'s>) + Send>;
+
+#[cfg(feature = "modern_sqlite")]
+impl Connection {
+    /// `feature = "trace"` Register or clear a callback function called for
+    /// the events selected by `mask`.
+    ///
+    /// There can only be a single tracer defined for each database
+    /// connection. Setting a new tracer clears the old one, as well as any
+    /// callback set with [`trace`](Connection::trace) or
+    /// [`profile`](Connection::profile).
+    pub fn trace_v2<F>(&self, mask: TraceEventCodes, tracer: Option<F>)
+    where
+        F: for<'s> FnMut(TraceEvent<'s>) + Send + 'static,
+    {
+        self.db.borrow_mut().trace_v2(mask, tracer);
+    }
+}
+
+#[cfg(feature = "modern_sqlite")]
+impl InnerConnection {
+    pub(crate) fn trace_v2<F>(&mut self, mask: TraceEventCodes, tracer: Option<F>)
+    where
+        F: for<'s> FnMut(TraceEvent<'s>) + Send + 'static,
+    {
+        unsafe extern "C" fn call_boxed_closure<F>(
+            evt: c_uint,
+            ctx: *mut c_void,
+            p: *mut c_void,
+            x: *mut c_void,
+        ) -> c_int
+        where
+            F: for<'s> FnMut(TraceEvent<'s>),
+        {
+            let stmt = StmtRef {
+                ptr: p as *mut ffi::sqlite3_stmt,
+                phantom: PhantomData,
+            };
+            let event = match evt as c_int {
+                ffi::SQLITE_TRACE_STMT => TraceEvent::Stmt {
+                    expanded: stmt.expanded_sql(),
+                    sql: CStr::from_ptr(x as *const c_char).to_string_lossy(),
+                    stmt,
+                },
+                ffi::SQLITE_TRACE_PROFILE => {
+                    let nanoseconds = *(x as *const i64) as u64;
+                    const NANOS_PER_SEC: u64 = 1_000_000_000;
+                    let duration = Duration::new(
+                        nanoseconds / NANOS_PER_SEC,
+                        (nanoseconds % NANOS_PER_SEC) as u32,
+                    );
+                    TraceEvent::Profile { stmt, duration }
+                }
+                ffi::SQLITE_TRACE_ROW => TraceEvent::Row { stmt },
+                ffi::SQLITE_TRACE_CLOSE => TraceEvent::Close,
+                _ => return 0,
+            };
+            let boxed_tracer = ctx as *mut F;
+            let _ = catch_unwind(AssertUnwindSafe(|| (*boxed_tracer)(event)));
+            0
+        }
+
+        match tracer {
+            Some(tracer) => {
+                let boxed_tracer = Box::new(tracer);
+                unsafe {
+                    ffi::sqlite3_trace_v2(
+                        self.db(),
+                        mask.bits(),
+                        Some(call_boxed_closure::<F>),
+                        &*boxed_tracer as *const F as *mut _,
+                    );
+                }
+                self.tracer = Some(boxed_tracer);
+            }
+            None => {
+                unsafe { ffi::sqlite3_trace_v2(self.db(), 0, None, ptr::null_mut()) };
+                self.tracer = None;
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use lazy_static::lazy_static;
@@ -180,4 +393,61 @@ mod test {
         assert_eq!(profiled[0].0, "PRAGMA application_id = 1");
         Ok(())
     }
+
+    #[test]
+    #[cfg(feature = "modern_sqlite")]
+    fn test_trace_v2() -> Result<()> {
+        use super::{TraceEvent, TraceEventCodes};
+        use crate::StatementStatus;
+        use std::sync::Arc;
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let events2 = events.clone();
+        let db = Connection::open_in_memory()?;
+        db.trace_v2(
+            TraceEventCodes::all(),
+            Some(move |event: TraceEvent<'_>| {
+                let event = match event {
+                    TraceEvent::Stmt { sql, expanded, .. } => {
+                        format!("stmt {} / {}", sql, expanded.unwrap())
+                    }
+                    TraceEvent::Profile { stmt, .. } => format!(
+                        "profile {} {}",
+                        stmt.sql(),
+                        stmt.get_status(StatementStatus::Run)
+                    ),
+                    TraceEvent::Row { stmt } => format!("row {}", stmt.sql()),
+                    TraceEvent::Close => "close".to_owned(),
+                };
+                events2.lock().unwrap().push(event);
+            }),
+        );
+        let _: i32 = db.query_row("SELECT ?", [1], |r| r.get(0))?;
+        db.close().unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(
+            *events,
+            vec![
+                "stmt SELECT ? / SELECT 1",
+                "row SELECT ?",
+                "profile SELECT ? 1",
+                "close",
+            ]
+        );
+
+        let db = Connection::open_in_memory()?;
+        let rows = Arc::new(Mutex::new(0));
+        let rows2 = rows.clone();
+        db.trace_v2(
+            TraceEventCodes::SQLITE_TRACE_ROW,
+            Some(move |_: TraceEvent<'_>| *rows2.lock().unwrap() += 1),
+        );
+        let mut stmt = db.prepare("SELECT 1 UNION ALL SELECT 2")?;
+        assert_eq!(2, stmt.query_map([], |_| Ok(()))?.count());
+        db.trace_v2(TraceEventCodes::empty(), None::<fn(TraceEvent<'_>)>);
+        assert_eq!(2, stmt.query_map([], |_| Ok(()))?.count());
+        assert_eq!(2, *rows.lock().unwrap());
+        Ok(())
+    }
 }
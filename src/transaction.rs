@@ -1,5 +1,8 @@
-use crate::{Connection, Result};
+use crate::busy::Backoff;
+use crate::{Connection, Error, ErrorCode, Result};
 use std::ops::Deref;
+use std::thread;
+use std::time::Duration;
 
 /// Options for transaction behavior. See [BEGIN
 /// TRANSACTION](http://www.sqlite.org/lang_transaction.html) for details.
@@ -17,6 +20,36 @@ pub enum TransactionBehavior {
     Exclusive,
 }
 
+/// How [`Connection::transact`] retries a transaction which fails because the
+/// database is busy or locked.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    backoff: Backoff,
+}
+
+impl RetryPolicy {
+    /// Make at most `max_attempts` attempts (at least one), waiting between
+    /// them according to `backoff`.
+    #[inline]
+    pub fn new(max_attempts: u32, backoff: Backoff) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            backoff,
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    /// 10 attempts, with a jittered exponential backoff from 1ms to 100ms.
+    fn default() -> RetryPolicy {
+        RetryPolicy::new(
+            10,
+            Backoff::new(Duration::from_millis(1), Duration::from_millis(100)),
+        )
+    }
+}
+
 /// Options for how a Transaction or Savepoint should behave when it is dropped.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[non_exhaustive]
@@ -495,11 +528,93 @@ impl Connection {
     pub fn savepoint_with_name<T: Into<String>>(&mut self, name: T) -> Result<Savepoint<'_>> {
         Savepoint::with_name(self, name)
     }
+
+    /// Run `f` in a transaction with the specified behavior, and commit it if
+    /// `f` succeeds or roll it back otherwise.
+    ///
+    /// If beginning the transaction, `f` or the commit fails because the
+    /// database is busy or locked (`SQLITE_BUSY`, including
+    /// `SQLITE_BUSY_SNAPSHOT`, or `SQLITE_LOCKED`), the transaction is rolled
+    /// back and `f` is run again in a new one, according to `policy`. `f` must
+    /// therefore be safe to re-run.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result, RetryPolicy, TransactionBehavior};
+    /// fn transfer(conn: &mut Connection) -> Result<()> {
+    ///     conn.transact(
+    ///         TransactionBehavior::Immediate,
+    ///         RetryPolicy::default(),
+    ///         |tx| {
+    ///             tx.execute("UPDATE account SET balance = balance - 10 WHERE id = 1", [])?;
+    ///             tx.execute("UPDATE account SET balance = balance + 10 WHERE id = 2", [])?;
+    ///             Ok(())
+    ///         },
+    ///     )
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` with the first error which is not retryable, or
+    /// [`Error::RetriesExhausted`] with the number of attempts and the last
+    /// error once `policy` gives up.
+    pub fn transact<T, F>(
+        &mut self,
+        behavior: TransactionBehavior,
+        policy: RetryPolicy,
+        mut f: F,
+    ) -> Result<T>
+    where
+        F: FnMut(&mut Transaction<'_>) -> Result<T>,
+    {
+        let RetryPolicy {
+            max_attempts,
+            mut backoff,
+        } = policy;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let err = match self.transact_once(behavior, &mut f) {
+                Err(err) if is_retryable(&err) => err,
+                r => return r,
+            };
+            let delay = if attempts < max_attempts {
+                backoff.delay(attempts as i32 - 1)
+            } else {
+                None
+            };
+            match delay {
+                Some(delay) => thread::sleep(delay),
+                None => return Err(Error::RetriesExhausted(attempts, Box::new(err))),
+            }
+        }
+    }
+
+    fn transact_once<T, F>(&mut self, behavior: TransactionBehavior, f: &mut F) -> Result<T>
+    where
+        F: FnMut(&mut Transaction<'_>) -> Result<T>,
+    {
+        let mut tx = self.transaction_with_behavior(behavior)?;
+        let value = f(&mut tx)?;
+        tx.commit()?;
+        Ok(value)
+    }
+}
+
+fn is_retryable(err: &Error) -> bool {
+    match err {
+        Error::SqliteFailure(err, _) => {
+            err.code == ErrorCode::DatabaseBusy || err.code == ErrorCode::DatabaseLocked
+        }
+        _ => false,
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::DropBehavior;
+    use super::{DropBehavior, RetryPolicy, TransactionBehavior};
     use crate::{Connection, Error, Result};
 
     fn checked_memory_handle() -> Result<Connection> {
@@ -706,4 +821,106 @@ mod test {
         assert_eq!(x, i);
         Ok(())
     }
+
+    fn busy() -> Error {
+        Error::SqliteFailure(crate::ffi::Error::new(crate::ffi::SQLITE_BUSY), None)
+    }
+
+    fn no_wait(max_attempts: u32) -> RetryPolicy {
+        use crate::busy::Backoff;
+        use std::time::Duration;
+
+        RetryPolicy::new(
+            max_attempts,
+            Backoff::new(Duration::from_millis(0), Duration::from_millis(0)),
+        )
+    }
+
+    #[test]
+    fn test_transact() -> Result<()> {
+        let mut db = checked_memory_handle()?;
+        let n = db.transact(TransactionBehavior::Deferred, no_wait(3), |tx| {
+            tx.execute("INSERT INTO foo VALUES (?)", [1])
+        })?;
+        assert_eq!(1, n);
+
+        let mut attempts = 0;
+        let r: Result<()> = db.transact(TransactionBehavior::Immediate, no_wait(3), |tx| {
+            attempts += 1;
+            tx.execute("INSERT INTO foo VALUES (?)", [2])?;
+            Err(Error::QueryReturnedNoRows)
+        });
+        assert_eq!(Err(Error::QueryReturnedNoRows), r);
+        assert_eq!(1, attempts);
+        assert!(db.is_autocommit());
+        assert_eq!(
+            1,
+            db.query_row("SELECT SUM(x) FROM foo", [], |r| r.get::<_, i32>(0))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_transact_retries() -> Result<()> {
+        let mut db = checked_memory_handle()?;
+        let mut attempts = 0;
+        db.transact(TransactionBehavior::Deferred, no_wait(3), |tx| {
+            attempts += 1;
+            tx.execute("INSERT INTO foo VALUES (?)", [attempts])?;
+            if attempts < 3 {
+                Err(busy())
+            } else {
+                Ok(())
+            }
+        })?;
+        assert_eq!(3, attempts);
+        assert_eq!(
+            3,
+            db.query_row("SELECT SUM(x) FROM foo", [], |r| r.get::<_, i32>(0))?
+        );
+
+        let mut attempts = 0;
+        let r: Result<()> = db.transact(TransactionBehavior::Deferred, no_wait(3), |_| {
+            attempts += 1;
+            Err(busy())
+        });
+        assert_eq!(Err(Error::RetriesExhausted(3, Box::new(busy()))), r);
+        assert_eq!(3, attempts);
+        Ok(())
+    }
+
+    #[test]
+    fn test_transact_locked_database() -> Result<()> {
+        use crate::ErrorCode;
+        use std::time::Duration;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("transact.db3");
+        let mut db1 = Connection::open(&path)?;
+        db1.execute_batch("CREATE TABLE foo (x INTEGER)")?;
+        let mut db2 = Connection::open(&path)?;
+        db2.busy_timeout(Duration::from_millis(0))?;
+
+        let tx1 = db1.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let mut attempts = 0;
+        let r = db2.transact(TransactionBehavior::Immediate, no_wait(2), |tx| {
+            attempts += 1;
+            tx.execute("INSERT INTO foo VALUES (1)", [])
+        });
+        match r.unwrap_err() {
+            Error::RetriesExhausted(2, err) => match *err {
+                Error::SqliteFailure(err, _) => assert_eq!(ErrorCode::DatabaseBusy, err.code),
+                err => panic!("Unexpected error {}", err),
+            },
+            err => panic!("Unexpected error {}", err),
+        }
+        // BEGIN IMMEDIATE failed
+        assert_eq!(0, attempts);
+        tx1.rollback()?;
+
+        db2.transact(TransactionBehavior::Immediate, no_wait(2), |tx| {
+            tx.execute("INSERT INTO foo VALUES (1)", [])
+        })?;
+        Ok(())
+    }
 }
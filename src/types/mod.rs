@@ -69,6 +69,8 @@ pub use self::from_sql::{FromSql, FromSqlError, FromSqlResult};
 pub use self::to_sql::{ToSql, ToSqlOutput};
 pub use self::value::Value;
 pub use self::value_ref::ValueRef;
+#[cfg(feature = "derive")]
+pub use rusqlite_derive::{FromSql, ToSql};
 
 use std::fmt;
 
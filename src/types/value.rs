@@ -5,6 +5,7 @@ use super::{Null, Type};
 ///
 /// See [`ValueRef`](crate::types::ValueRef) for a non-owning dynamic type value.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Value {
     /// The value is a `NULL` value.
     Null,
@@ -133,7 +133,12 @@ where
     }
 }
 
-#[cfg(any(feature = "functions", feature = "session", feature = "vtab"))]
+#[cfg(any(
+    feature = "functions",
+    feature = "preupdate_hook",
+    feature = "session",
+    feature = "vtab"
+))]
 impl<'a> ValueRef<'a> {
     pub(crate) unsafe fn from_value(value: *mut crate::ffi::sqlite3_value) -> ValueRef<'a> {
         use crate::ffi;
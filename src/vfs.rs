@@ -0,0 +1,1109 @@
+//! `feature = "vfs"` Implement a custom Virtual File System in Rust.
+//!
+//! Follow these steps to use your own VFS:
+//! 1. Write implementation of [`Vfs`] and [`VfsFile`] traits.
+//! 2. Register it using [`register_vfs`].
+//! 3. Open connections with
+//!    [`Connection::open_with_flags_and_vfs`](crate::Connection::open_with_flags_and_vfs),
+//!    or with any `open` method if the VFS has been made the default one.
+//!
+//! Methods which are not strictly required to implement a VFS (randomness,
+//! sleep, current time, shared memory) have default implementations.
+//! Loading extensions is delegated to the VFS which was the default when
+//! [`register_vfs`] was called.
+//!
+//! (See [SQLite doc](https://sqlite.org/vfs.html))
+use std::collections::hash_map::RandomState;
+use std::ffi::{CStr, CString};
+use std::hash::{BuildHasher, Hasher};
+use std::mem;
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::ffi;
+use crate::{Error, Result};
+
+bitflags::bitflags! {
+    /// `feature = "vfs"` Flags passed to [`Vfs::open`].
+    ///
+    /// See [sqlite3_vfs.xOpen](https://sqlite.org/c3ref/vfs.html) for details.
+    #[repr(C)]
+    pub struct FileFlags: c_int {
+        /// The file must be opened read-only.
+        const READ_ONLY       = 0x0000_0001;
+        /// The file should be opened for reading and writing.
+        const READ_WRITE      = 0x0000_0002;
+        /// The file should be created if it does not already exist.
+        const CREATE          = 0x0000_0004;
+        /// The file should be deleted when it is closed.
+        const DELETE_ON_CLOSE = 0x0000_0008;
+        /// Used with `CREATE`: opening should fail if the file already exists.
+        const EXCLUSIVE       = 0x0000_0010;
+    }
+}
+
+/// `feature = "vfs"` Kind of file opened by SQLite.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum FileKind {
+    /// Main database file
+    MainDb,
+    /// Rollback journal of a main database
+    MainJournal,
+    /// Temporary database file
+    TempDb,
+    /// Rollback journal of a temporary database
+    TempJournal,
+    /// Transient database (used by some queries to store intermediate results)
+    TransientDb,
+    /// Statement journal
+    SubJournal,
+    /// Super-journal (used by multi-database transactions)
+    SuperJournal,
+    /// Write-ahead log
+    Wal,
+}
+
+impl FileKind {
+    fn from_flags(flags: c_int) -> Option<FileKind> {
+        match flags & 0x0008_7f00 {
+            0x0000_0100 => Some(FileKind::MainDb),
+            0x0000_0200 => Some(FileKind::TempDb),
+            0x0000_0400 => Some(FileKind::TransientDb),
+            0x0000_0800 => Some(FileKind::MainJournal),
+            0x0000_1000 => Some(FileKind::TempJournal),
+            0x0000_2000 => Some(FileKind::SubJournal),
+            0x0000_4000 => Some(FileKind::SuperJournal),
+            0x0008_0000 => Some(FileKind::Wal),
+            _ => None,
+        }
+    }
+}
+
+/// `feature = "vfs"` Kind of check performed by [`Vfs::access`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum AccessKind {
+    /// Does the file exist ?
+    Exists,
+    /// Is the file both readable and writable ?
+    ReadWrite,
+    /// Is the file readable ? (no longer used by SQLite)
+    Read,
+}
+
+/// `feature = "vfs"` File locking levels.
+///
+/// (See [SQLite doc](https://sqlite.org/c3ref/c_lock_exclusive.html))
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[non_exhaustive]
+pub enum LockLevel {
+    /// No lock is held.
+    None,
+    /// The file may be read but not written.
+    Shared,
+    /// The connection plans to write to the file at some point in the future.
+    Reserved,
+    /// The connection wants to write and is waiting for other shared locks to
+    /// clear.
+    Pending,
+    /// The file may be written.
+    Exclusive,
+}
+
+impl From<c_int> for LockLevel {
+    fn from(level: c_int) -> LockLevel {
+        match level {
+            1 => LockLevel::Shared,
+            2 => LockLevel::Reserved,
+            3 => LockLevel::Pending,
+            4 => LockLevel::Exclusive,
+            _ => LockLevel::None,
+        }
+    }
+}
+
+bitflags::bitflags! {
+    /// `feature = "vfs"` Flags passed to [`VfsFile::sync`].
+    ///
+    /// `NORMAL` is the default behaviour, `FULL` is a subset of it which asks
+    /// for a Mac OS X style fullsync.
+    #[repr(C)]
+    pub struct SyncFlags: c_int {
+        /// Normal `fsync`
+        const NORMAL    = 0x0000_0002;
+        /// Full `fsync`
+        const FULL      = 0x0000_0003;
+        /// Only the data of the file, and not its inode, needs to be synced.
+        const DATA_ONLY = 0x0000_0010;
+    }
+}
+
+bitflags::bitflags! {
+    /// `feature = "vfs"` Flags passed to [`VfsFile::shm_lock`].
+    ///
+    /// Exactly one of `LOCK` / `UNLOCK` and one of `SHARED` / `EXCLUSIVE` is
+    /// set.
+    #[repr(C)]
+    pub struct ShmLockFlags: c_int {
+        /// Release the locks
+        const UNLOCK    = 0x0000_0001;
+        /// Acquire the locks
+        const LOCK      = 0x0000_0002;
+        /// Shared locks
+        const SHARED    = 0x0000_0004;
+        /// Exclusive locks
+        const EXCLUSIVE = 0x0000_0008;
+    }
+}
+
+/// `feature = "vfs"` Virtual File System trait.
+///
+/// Implementations are shared by all the connections using the VFS and so
+/// must be thread-safe.
+///
+/// (See [SQLite doc](https://sqlite.org/c3ref/vfs.html))
+pub trait Vfs: Send + Sync + 'static {
+    /// Specific file implementation
+    type File: VfsFile;
+
+    /// Open the file `path`.
+    ///
+    /// `path` is `None` when SQLite needs a temporary file whose name is up to
+    /// the VFS. In that case, `flags` always contains `DELETE_ON_CLOSE`.
+    ///
+    /// Returns the file and the flags with which it was actually opened
+    /// (e.g. `READ_ONLY` if it could not be opened for writing).
+    fn open(
+        &self,
+        path: Option<&str>,
+        kind: FileKind,
+        flags: FileFlags,
+    ) -> Result<(Self::File, FileFlags)>;
+
+    /// Delete the file `path`.
+    ///
+    /// If `sync_dir` is `true`, the directory containing the file should be
+    /// synced before returning.
+    fn delete(&self, path: &str, sync_dir: bool) -> Result<()>;
+
+    /// Check whether the file `path` exists or is readable / writable.
+    fn access(&self, path: &str, kind: AccessKind) -> Result<bool>;
+
+    /// Return the canonical form of `path`.
+    ///
+    /// The default implementation returns `path` unchanged.
+    fn full_pathname(&self, path: &str) -> Result<String> {
+        Ok(path.to_owned())
+    }
+
+    /// Fill `buf` with random bytes, used to seed SQLite's PRNG.
+    ///
+    /// Returns the number of bytes actually filled.
+    fn randomness(&self, buf: &mut [u8]) -> usize {
+        for chunk in buf.chunks_mut(mem::size_of::<u64>()) {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_usize(chunk.as_ptr() as usize);
+            let bytes = hasher.finish().to_ne_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+        buf.len()
+    }
+
+    /// Suspend the calling thread for at least `duration`.
+    ///
+    /// Returns the duration actually slept.
+    fn sleep(&self, duration: Duration) -> Duration {
+        thread::sleep(duration);
+        duration
+    }
+
+    /// Return the current time.
+    fn current_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// `feature = "vfs"` File opened by a [`Vfs`].
+///
+/// Offsets and sizes are in bytes.
+///
+/// (See [SQLite doc](https://sqlite.org/c3ref/io_methods.html))
+pub trait VfsFile: Send + 'static {
+    /// Read `buf.len()` bytes starting at `offset`.
+    ///
+    /// Returns the number of bytes read, which is less than `buf.len()` only
+    /// if the end of the file has been reached.
+    fn read(&mut self, buf: &mut [u8], offset: u64) -> Result<usize>;
+
+    /// Write `buf` starting at `offset`, extending the file if needed.
+    fn write(&mut self, buf: &[u8], offset: u64) -> Result<()>;
+
+    /// Truncate (or extend) the file to `size` bytes.
+    fn truncate(&mut self, size: u64) -> Result<()>;
+
+    /// Flush the file content to persistent storage.
+    fn sync(&mut self, flags: SyncFlags) -> Result<()>;
+
+    /// Return the current size of the file.
+    fn file_size(&mut self) -> Result<u64>;
+
+    /// Upgrade the lock held on the file to `level`.
+    ///
+    /// Returns `false` if the lock is held by someone else (`SQLITE_BUSY`).
+    fn lock(&mut self, level: LockLevel) -> Result<bool>;
+
+    /// Downgrade the lock held on the file to `level`
+    /// ([`LockLevel::Shared`] or [`LockLevel::None`]).
+    fn unlock(&mut self, level: LockLevel) -> Result<()>;
+
+    /// Check whether any connection holds a reserved, pending or exclusive
+    /// lock on the file.
+    fn check_reserved_lock(&mut self) -> Result<bool>;
+
+    /// Handle the [file control](https://sqlite.org/c3ref/c_fcntl_begin_atomic_write.html)
+    /// `op`.
+    ///
+    /// Returns `Ok(false)` for unknown or unsupported opcodes, which is the
+    /// default.
+    ///
+    /// # Safety
+    ///
+    /// `arg` is the raw argument whose type depends on `op`.
+    unsafe fn file_control(&mut self, _op: c_int, _arg: *mut c_void) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Return the sector size of the underlying storage.
+    fn sector_size(&mut self) -> c_int {
+        4096
+    }
+
+    /// Return the `SQLITE_IOCAP_*` characteristics of the underlying storage.
+    fn device_characteristics(&mut self) -> c_int {
+        0
+    }
+
+    /// Whether this file supports the `shm_*` methods, which are required by
+    /// WAL mode (unless `locking_mode=EXCLUSIVE`).
+    ///
+    /// `false` by default.
+    fn has_shm(&self) -> bool {
+        false
+    }
+
+    /// Return a pointer to the shared memory region number `region` of `size`
+    /// bytes, or null if the region does not exist and `extend` is `false`.
+    ///
+    /// All connections to the same database must see the same regions, and a
+    /// region must stay valid until [`shm_unmap`](VfsFile::shm_unmap).
+    fn shm_map(&mut self, _region: usize, _size: usize, _extend: bool) -> Result<*mut u8> {
+        Err(Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_IOERR_SHMMAP),
+            None,
+        ))
+    }
+
+    /// Acquire or release the shared memory locks `offset..offset + n`.
+    ///
+    /// Returns `false` if the locks are held by someone else
+    /// (`SQLITE_BUSY`).
+    fn shm_lock(&mut self, _offset: usize, _n: usize, _flags: ShmLockFlags) -> Result<bool> {
+        Err(Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_IOERR_SHMLOCK),
+            None,
+        ))
+    }
+
+    /// Memory barrier between shared memory accesses.
+    fn shm_barrier(&mut self) {
+        std::sync::atomic::fence(std::sync::atomic::Ordering::SeqCst);
+    }
+
+    /// Unmap the shared memory regions, deleting the underlying storage if
+    /// `delete` is `true`.
+    fn shm_unmap(&mut self, _delete: bool) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// `feature = "vfs"` Register `vfs` under `name`, making it the default VFS
+/// if `make_default` is `true`.
+///
+/// The VFS stays registered for the life of the process. The default VFS at
+/// the time of the registration is used for the operations which are not
+/// part of the [`Vfs`] trait (like loading extensions).
+///
+/// # Failure
+///
+/// Will return `Err` if `name` cannot be converted to a C-compatible string,
+/// if a VFS named `name` is already registered, if there is no default VFS,
+/// or if the underlying SQLite call fails.
+pub fn register_vfs<V: Vfs>(name: &str, vfs: V, make_default: bool) -> Result<()> {
+    // Files are allocated by SQLite with an 8-byte alignment.
+    assert!(mem::align_of::<FileState<V::File>>() <= 8);
+    let c_name = CString::new(name)?;
+    let rc = unsafe { ffi::sqlite3_initialize() };
+    if rc != ffi::SQLITE_OK {
+        return Err(Error::SqliteFailure(ffi::Error::new(rc), None));
+    }
+    if !unsafe { ffi::sqlite3_vfs_find(c_name.as_ptr()) }.is_null() {
+        return Err(Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_MISUSE),
+            Some(format!("VFS {} is already registered", name)),
+        ));
+    }
+    let parent = unsafe { ffi::sqlite3_vfs_find(ptr::null()) };
+    if parent.is_null() {
+        return Err(Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_ERROR),
+            Some("no default VFS to delegate to".to_owned()),
+        ));
+    }
+    let state = Box::new(VfsState { vfs, parent });
+    let base = Box::new(ffi::sqlite3_vfs {
+        iVersion: 2,
+        szOsFile: mem::size_of::<FileState<V::File>>() as c_int,
+        mxPathname: MAX_PATHNAME,
+        pNext: ptr::null_mut(),
+        zName: c_name.into_raw(),
+        pAppData: Box::into_raw(state) as *mut c_void,
+        xOpen: Some(rust_open::<V>),
+        xDelete: Some(rust_delete::<V>),
+        xAccess: Some(rust_access::<V>),
+        xFullPathname: Some(rust_full_pathname::<V>),
+        xDlOpen: Some(rust_dl_open::<V>),
+        xDlError: Some(rust_dl_error::<V>),
+        #[allow(clippy::useless_transmute, clippy::missing_transmute_annotations)]
+        xDlSym: Some(unsafe { mem::transmute::<DlSymFn, _>(rust_dl_sym::<V>) }),
+        xDlClose: Some(rust_dl_close::<V>),
+        xRandomness: Some(rust_randomness::<V>),
+        xSleep: Some(rust_sleep::<V>),
+        xCurrentTime: Some(rust_current_time::<V>),
+        xGetLastError: Some(rust_get_last_error),
+        xCurrentTimeInt64: Some(rust_current_time_int64::<V>),
+        ..ZERO_VFS
+    });
+    // Registered VFS are never freed.
+    let rc = unsafe { ffi::sqlite3_vfs_register(Box::into_raw(base), make_default as c_int) };
+    if rc == ffi::SQLITE_OK {
+        Ok(())
+    } else {
+        Err(Error::SqliteFailure(ffi::Error::new(rc), None))
+    }
+}
+
+const MAX_PATHNAME: c_int = 1024;
+
+// Number of milliseconds between the julian day 0 and the unix epoch
+const UNIX_EPOCH_JULIAN_MS: i64 = 210_866_760_000_000;
+
+union VfsZeroHack {
+    bytes: [u8; mem::size_of::<ffi::sqlite3_vfs>()],
+    vfs: ffi::sqlite3_vfs,
+}
+
+// Used as a trailing initializer for sqlite3_vfs, like `ZERO_MODULE` in the
+// vtab module.
+const ZERO_VFS: ffi::sqlite3_vfs = unsafe {
+    VfsZeroHack {
+        bytes: [0u8; mem::size_of::<ffi::sqlite3_vfs>()],
+    }
+    .vfs
+};
+
+union IoMethodsZeroHack {
+    bytes: [u8; mem::size_of::<ffi::sqlite3_io_methods>()],
+    methods: ffi::sqlite3_io_methods,
+}
+
+const ZERO_IO_METHODS: ffi::sqlite3_io_methods = unsafe {
+    IoMethodsZeroHack {
+        bytes: [0u8; mem::size_of::<ffi::sqlite3_io_methods>()],
+    }
+    .methods
+};
+
+/// `pAppData` of a registered VFS
+struct VfsState<V> {
+    vfs: V,
+    /// Default VFS when this one was registered
+    parent: *mut ffi::sqlite3_vfs,
+}
+
+/// Layout of the `szOsFile` bytes allocated by SQLite for each file.
+#[repr(C)]
+struct FileState<F> {
+    base: ffi::sqlite3_file,
+    file: F,
+}
+
+fn io_methods<F: VfsFile>(shm: bool) -> &'static ffi::sqlite3_io_methods {
+    if shm {
+        &ffi::sqlite3_io_methods {
+            iVersion: 2,
+            xClose: Some(rust_close::<F>),
+            xRead: Some(rust_read::<F>),
+            xWrite: Some(rust_write::<F>),
+            xTruncate: Some(rust_truncate::<F>),
+            xSync: Some(rust_sync::<F>),
+            xFileSize: Some(rust_file_size::<F>),
+            xLock: Some(rust_lock::<F>),
+            xUnlock: Some(rust_unlock::<F>),
+            xCheckReservedLock: Some(rust_check_reserved_lock::<F>),
+            xFileControl: Some(rust_file_control::<F>),
+            xSectorSize: Some(rust_sector_size::<F>),
+            xDeviceCharacteristics: Some(rust_device_characteristics::<F>),
+            xShmMap: Some(rust_shm_map::<F>),
+            xShmLock: Some(rust_shm_lock::<F>),
+            xShmBarrier: Some(rust_shm_barrier::<F>),
+            xShmUnmap: Some(rust_shm_unmap::<F>),
+            ..ZERO_IO_METHODS
+        }
+    } else {
+        &ffi::sqlite3_io_methods {
+            iVersion: 1,
+            xClose: Some(rust_close::<F>),
+            xRead: Some(rust_read::<F>),
+            xWrite: Some(rust_write::<F>),
+            xTruncate: Some(rust_truncate::<F>),
+            xSync: Some(rust_sync::<F>),
+            xFileSize: Some(rust_file_size::<F>),
+            xLock: Some(rust_lock::<F>),
+            xUnlock: Some(rust_unlock::<F>),
+            xCheckReservedLock: Some(rust_check_reserved_lock::<F>),
+            xFileControl: Some(rust_file_control::<F>),
+            xSectorSize: Some(rust_sector_size::<F>),
+            xDeviceCharacteristics: Some(rust_device_characteristics::<F>),
+            ..ZERO_IO_METHODS
+        }
+    }
+}
+
+/// Convert the result of a callback into an SQLite result code, using
+/// `default_code` for non-SQLite errors and panics.
+fn result_code<T>(
+    r: std::thread::Result<Result<T>>,
+    default_code: c_int,
+    f: impl FnOnce(T),
+) -> c_int {
+    match r {
+        Ok(Ok(v)) => {
+            f(v);
+            ffi::SQLITE_OK
+        }
+        Ok(Err(Error::SqliteFailure(err, _))) => err.extended_code,
+        _ => default_code,
+    }
+}
+
+unsafe fn vfs_state<'a, V>(p_vfs: *mut ffi::sqlite3_vfs) -> &'a VfsState<V> {
+    &*((*p_vfs).pAppData as *const VfsState<V>)
+}
+
+unsafe fn file_state<'a, F>(p_file: *mut ffi::sqlite3_file) -> &'a mut F {
+    &mut (*(p_file as *mut FileState<F>)).file
+}
+
+unsafe fn c_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        None
+    } else {
+        CStr::from_ptr(s).to_str().ok()
+    }
+}
+
+unsafe extern "C" fn rust_open<V: Vfs>(
+    p_vfs: *mut ffi::sqlite3_vfs,
+    z_name: *const c_char,
+    p_file: *mut ffi::sqlite3_file,
+    flags: c_int,
+    p_out_flags: *mut c_int,
+) -> c_int {
+    // xClose is only called if pMethods is not null.
+    (*p_file).pMethods = ptr::null();
+    let state = vfs_state::<V>(p_vfs);
+    let kind = match FileKind::from_flags(flags) {
+        Some(kind) => kind,
+        None => return ffi::SQLITE_CANTOPEN,
+    };
+    let path = if z_name.is_null() {
+        None
+    } else {
+        match c_str(z_name) {
+            Some(path) => Some(path),
+            None => return ffi::SQLITE_CANTOPEN,
+        }
+    };
+    let r = catch_unwind(AssertUnwindSafe(|| {
+        state
+            .vfs
+            .open(path, kind, FileFlags::from_bits_truncate(flags))
+    }));
+    result_code(r, ffi::SQLITE_CANTOPEN, |(file, out_flags)| {
+        let shm = file.has_shm();
+        ptr::write(
+            p_file as *mut FileState<V::File>,
+            FileState {
+                base: ffi::sqlite3_file {
+                    pMethods: io_methods::<V::File>(shm),
+                },
+                file,
+            },
+        );
+        if !p_out_flags.is_null() {
+            // Keep the file type bits
+            *p_out_flags = (flags & !FileFlags::all().bits()) | out_flags.bits();
+        }
+    })
+}
+
+unsafe extern "C" fn rust_delete<V: Vfs>(
+    p_vfs: *mut ffi::sqlite3_vfs,
+    z_name: *const c_char,
+    sync_dir: c_int,
+) -> c_int {
+    let state = vfs_state::<V>(p_vfs);
+    let path = match c_str(z_name) {
+        Some(path) => path,
+        None => return ffi::SQLITE_IOERR_DELETE,
+    };
+    let r = catch_unwind(AssertUnwindSafe(|| state.vfs.delete(path, sync_dir != 0)));
+    result_code(r, ffi::SQLITE_IOERR_DELETE, |_| {})
+}
+
+unsafe extern "C" fn rust_access<V: Vfs>(
+    p_vfs: *mut ffi::sqlite3_vfs,
+    z_name: *const c_char,
+    flags: c_int,
+    p_res_out: *mut c_int,
+) -> c_int {
+    let state = vfs_state::<V>(p_vfs);
+    let path = match c_str(z_name) {
+        Some(path) => path,
+        None => return ffi::SQLITE_IOERR_ACCESS,
+    };
+    let kind = match flags {
+        ffi::SQLITE_ACCESS_READWRITE => AccessKind::ReadWrite,
+        ffi::SQLITE_ACCESS_READ => AccessKind::Read,
+        _ => AccessKind::Exists,
+    };
+    let r = catch_unwind(AssertUnwindSafe(|| state.vfs.access(path, kind)));
+    result_code(r, ffi::SQLITE_IOERR_ACCESS, |res| {
+        *p_res_out = res as c_int;
+    })
+}
+
+unsafe extern "C" fn rust_full_pathname<V: Vfs>(
+    p_vfs: *mut ffi::sqlite3_vfs,
+    z_name: *const c_char,
+    n_out: c_int,
+    z_out: *mut c_char,
+) -> c_int {
+    let state = vfs_state::<V>(p_vfs);
+    let path = match c_str(z_name) {
+        Some(path) => path,
+        None => return ffi::SQLITE_CANTOPEN,
+    };
+    let r = catch_unwind(AssertUnwindSafe(|| state.vfs.full_pathname(path)));
+    let full_path = match r {
+        Ok(Ok(full_path)) => full_path,
+        Ok(Err(Error::SqliteFailure(err, _))) => return err.extended_code,
+        _ => return ffi::SQLITE_CANTOPEN,
+    };
+    let bytes = full_path.as_bytes();
+    if bytes.len() >= n_out as usize || bytes.contains(&0) {
+        return ffi::SQLITE_CANTOPEN;
+    }
+    ptr::copy_nonoverlapping(bytes.as_ptr(), z_out as *mut u8, bytes.len());
+    *z_out.add(bytes.len()) = 0;
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn rust_dl_open<V: Vfs>(
+    p_vfs: *mut ffi::sqlite3_vfs,
+    z_filename: *const c_char,
+) -> *mut c_void {
+    let parent = vfs_state::<V>(p_vfs).parent;
+    match (*parent).xDlOpen {
+        Some(f) => f(parent, z_filename),
+        None => ptr::null_mut(),
+    }
+}
+
+unsafe extern "C" fn rust_dl_error<V: Vfs>(
+    p_vfs: *mut ffi::sqlite3_vfs,
+    n_byte: c_int,
+    z_err_msg: *mut c_char,
+) {
+    let parent = vfs_state::<V>(p_vfs).parent;
+    if let Some(f) = (*parent).xDlError {
+        f(parent, n_byte, z_err_msg)
+    }
+}
+
+// The type of the symbols returned by `xDlSym` depends on the bindings
+// (`void (*)(void)` in old ones): they are transmuted from/to this one.
+type DlSym = Option<unsafe extern "C" fn(*mut ffi::sqlite3_vfs, *mut c_void, *const c_char)>;
+type DlSymFn = unsafe extern "C" fn(*mut ffi::sqlite3_vfs, *mut c_void, *const c_char) -> DlSym;
+
+#[allow(clippy::useless_transmute, clippy::missing_transmute_annotations)]
+unsafe extern "C" fn rust_dl_sym<V: Vfs>(
+    p_vfs: *mut ffi::sqlite3_vfs,
+    p_handle: *mut c_void,
+    z_symbol: *const c_char,
+) -> DlSym {
+    let parent = vfs_state::<V>(p_vfs).parent;
+    match (*parent).xDlSym {
+        Some(f) => mem::transmute::<_, DlSym>(f(parent, p_handle, z_symbol)),
+        None => None,
+    }
+}
+
+unsafe extern "C" fn rust_dl_close<V: Vfs>(p_vfs: *mut ffi::sqlite3_vfs, p_handle: *mut c_void) {
+    let parent = vfs_state::<V>(p_vfs).parent;
+    if let Some(f) = (*parent).xDlClose {
+        f(parent, p_handle)
+    }
+}
+
+unsafe extern "C" fn rust_randomness<V: Vfs>(
+    p_vfs: *mut ffi::sqlite3_vfs,
+    n_byte: c_int,
+    z_out: *mut c_char,
+) -> c_int {
+    let state = vfs_state::<V>(p_vfs);
+    let buf = slice::from_raw_parts_mut(z_out as *mut u8, n_byte as usize);
+    catch_unwind(AssertUnwindSafe(|| state.vfs.randomness(buf))).unwrap_or(0) as c_int
+}
+
+unsafe extern "C" fn rust_sleep<V: Vfs>(
+    p_vfs: *mut ffi::sqlite3_vfs,
+    microseconds: c_int,
+) -> c_int {
+    let state = vfs_state::<V>(p_vfs);
+    let duration = Duration::from_micros(microseconds.max(0) as u64);
+    catch_unwind(AssertUnwindSafe(|| state.vfs.sleep(duration)))
+        .map(|slept| slept.as_micros() as c_int)
+        .unwrap_or(0)
+}
+
+unsafe fn current_time_ms<V: Vfs>(p_vfs: *mut ffi::sqlite3_vfs) -> Option<i64> {
+    let state = vfs_state::<V>(p_vfs);
+    let now = catch_unwind(AssertUnwindSafe(|| state.vfs.current_time())).ok()?;
+    let ms = match now.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_millis() as i64,
+        Err(e) => -(e.duration().as_millis() as i64),
+    };
+    Some(UNIX_EPOCH_JULIAN_MS + ms)
+}
+
+unsafe extern "C" fn rust_current_time<V: Vfs>(
+    p_vfs: *mut ffi::sqlite3_vfs,
+    p_time_out: *mut f64,
+) -> c_int {
+    match current_time_ms::<V>(p_vfs) {
+        Some(ms) => {
+            *p_time_out = ms as f64 / 86_400_000.0;
+            ffi::SQLITE_OK
+        }
+        None => ffi::SQLITE_ERROR,
+    }
+}
+
+unsafe extern "C" fn rust_current_time_int64<V: Vfs>(
+    p_vfs: *mut ffi::sqlite3_vfs,
+    p_time_out: *mut ffi::sqlite3_int64,
+) -> c_int {
+    match current_time_ms::<V>(p_vfs) {
+        Some(ms) => {
+            *p_time_out = ms;
+            ffi::SQLITE_OK
+        }
+        None => ffi::SQLITE_ERROR,
+    }
+}
+
+unsafe extern "C" fn rust_get_last_error(
+    _: *mut ffi::sqlite3_vfs,
+    _: c_int,
+    _: *mut c_char,
+) -> c_int {
+    0
+}
+
+unsafe extern "C" fn rust_close<F: VfsFile>(p_file: *mut ffi::sqlite3_file) -> c_int {
+    let r = catch_unwind(AssertUnwindSafe(|| {
+        ptr::drop_in_place(p_file as *mut FileState<F>);
+    }));
+    (*p_file).pMethods = ptr::null();
+    match r {
+        Ok(_) => ffi::SQLITE_OK,
+        Err(_) => ffi::SQLITE_IOERR_CLOSE,
+    }
+}
+
+unsafe extern "C" fn rust_read<F: VfsFile>(
+    p_file: *mut ffi::sqlite3_file,
+    p_buf: *mut c_void,
+    i_amt: c_int,
+    i_ofst: ffi::sqlite3_int64,
+) -> c_int {
+    let file = file_state::<F>(p_file);
+    let buf = slice::from_raw_parts_mut(p_buf as *mut u8, i_amt as usize);
+    let r = catch_unwind(AssertUnwindSafe(|| file.read(buf, i_ofst as u64)));
+    match r {
+        Ok(Ok(n)) if n >= buf.len() => ffi::SQLITE_OK,
+        Ok(Ok(n)) => {
+            // Unread parts of the buffer must be zero-filled.
+            for b in &mut buf[n..] {
+                *b = 0;
+            }
+            ffi::SQLITE_IOERR_SHORT_READ
+        }
+        Ok(Err(Error::SqliteFailure(err, _))) => err.extended_code,
+        _ => ffi::SQLITE_IOERR_READ,
+    }
+}
+
+unsafe extern "C" fn rust_write<F: VfsFile>(
+    p_file: *mut ffi::sqlite3_file,
+    p_buf: *const c_void,
+    i_amt: c_int,
+    i_ofst: ffi::sqlite3_int64,
+) -> c_int {
+    let file = file_state::<F>(p_file);
+    let buf = slice::from_raw_parts(p_buf as *const u8, i_amt as usize);
+    let r = catch_unwind(AssertUnwindSafe(|| file.write(buf, i_ofst as u64)));
+    result_code(r, ffi::SQLITE_IOERR_WRITE, |_| {})
+}
+
+unsafe extern "C" fn rust_truncate<F: VfsFile>(
+    p_file: *mut ffi::sqlite3_file,
+    size: ffi::sqlite3_int64,
+) -> c_int {
+    let file = file_state::<F>(p_file);
+    let r = catch_unwind(AssertUnwindSafe(|| file.truncate(size as u64)));
+    result_code(r, ffi::SQLITE_IOERR_TRUNCATE, |_| {})
+}
+
+unsafe extern "C" fn rust_sync<F: VfsFile>(p_file: *mut ffi::sqlite3_file, flags: c_int) -> c_int {
+    let file = file_state::<F>(p_file);
+    let flags = SyncFlags::from_bits_truncate(flags);
+    let r = catch_unwind(AssertUnwindSafe(|| file.sync(flags)));
+    result_code(r, ffi::SQLITE_IOERR_FSYNC, |_| {})
+}
+
+unsafe extern "C" fn rust_file_size<F: VfsFile>(
+    p_file: *mut ffi::sqlite3_file,
+    p_size: *mut ffi::sqlite3_int64,
+) -> c_int {
+    let file = file_state::<F>(p_file);
+    let r = catch_unwind(AssertUnwindSafe(|| file.file_size()));
+    result_code(r, ffi::SQLITE_IOERR_FSTAT, |size| {
+        *p_size = size as ffi::sqlite3_int64;
+    })
+}
+
+unsafe extern "C" fn rust_lock<F: VfsFile>(p_file: *mut ffi::sqlite3_file, level: c_int) -> c_int {
+    let file = file_state::<F>(p_file);
+    let r = catch_unwind(AssertUnwindSafe(|| file.lock(LockLevel::from(level))));
+    match r {
+        Ok(Ok(true)) => ffi::SQLITE_OK,
+        Ok(Ok(false)) => ffi::SQLITE_BUSY,
+        Ok(Err(Error::SqliteFailure(err, _))) => err.extended_code,
+        _ => ffi::SQLITE_IOERR_LOCK,
+    }
+}
+
+unsafe extern "C" fn rust_unlock<F: VfsFile>(
+    p_file: *mut ffi::sqlite3_file,
+    level: c_int,
+) -> c_int {
+    let file = file_state::<F>(p_file);
+    let r = catch_unwind(AssertUnwindSafe(|| file.unlock(LockLevel::from(level))));
+    result_code(r, ffi::SQLITE_IOERR_UNLOCK, |_| {})
+}
+
+unsafe extern "C" fn rust_check_reserved_lock<F: VfsFile>(
+    p_file: *mut ffi::sqlite3_file,
+    p_res_out: *mut c_int,
+) -> c_int {
+    let file = file_state::<F>(p_file);
+    let r = catch_unwind(AssertUnwindSafe(|| file.check_reserved_lock()));
+    result_code(r, ffi::SQLITE_IOERR_CHECKRESERVEDLOCK, |res| {
+        *p_res_out = res as c_int;
+    })
+}
+
+unsafe extern "C" fn rust_file_control<F: VfsFile>(
+    p_file: *mut ffi::sqlite3_file,
+    op: c_int,
+    p_arg: *mut c_void,
+) -> c_int {
+    let file = file_state::<F>(p_file);
+    let r = catch_unwind(AssertUnwindSafe(|| file.file_control(op, p_arg)));
+    match r {
+        Ok(Ok(true)) => ffi::SQLITE_OK,
+        Ok(Ok(false)) => ffi::SQLITE_NOTFOUND,
+        Ok(Err(Error::SqliteFailure(err, _))) => err.extended_code,
+        _ => ffi::SQLITE_ERROR,
+    }
+}
+
+unsafe extern "C" fn rust_sector_size<F: VfsFile>(p_file: *mut ffi::sqlite3_file) -> c_int {
+    let file = file_state::<F>(p_file);
+    catch_unwind(AssertUnwindSafe(|| file.sector_size())).unwrap_or(4096)
+}
+
+unsafe extern "C" fn rust_device_characteristics<F: VfsFile>(
+    p_file: *mut ffi::sqlite3_file,
+) -> c_int {
+    let file = file_state::<F>(p_file);
+    catch_unwind(AssertUnwindSafe(|| file.device_characteristics())).unwrap_or(0)
+}
+
+unsafe extern "C" fn rust_shm_map<F: VfsFile>(
+    p_file: *mut ffi::sqlite3_file,
+    i_pg: c_int,
+    pgsz: c_int,
+    extend: c_int,
+    pp: *mut *mut c_void,
+) -> c_int {
+    let file = file_state::<F>(p_file);
+    *pp = ptr::null_mut();
+    let r = catch_unwind(AssertUnwindSafe(|| {
+        file.shm_map(i_pg as usize, pgsz as usize, extend != 0)
+    }));
+    result_code(r, ffi::SQLITE_IOERR_SHMMAP, |region| {
+        *pp = region as *mut c_void;
+    })
+}
+
+unsafe extern "C" fn rust_shm_lock<F: VfsFile>(
+    p_file: *mut ffi::sqlite3_file,
+    offset: c_int,
+    n: c_int,
+    flags: c_int,
+) -> c_int {
+    let file = file_state::<F>(p_file);
+    let flags = ShmLockFlags::from_bits_truncate(flags);
+    let r = catch_unwind(AssertUnwindSafe(|| {
+        file.shm_lock(offset as usize, n as usize, flags)
+    }));
+    match r {
+        Ok(Ok(true)) => ffi::SQLITE_OK,
+        Ok(Ok(false)) => ffi::SQLITE_BUSY,
+        Ok(Err(Error::SqliteFailure(err, _))) => err.extended_code,
+        _ => ffi::SQLITE_IOERR_SHMLOCK,
+    }
+}
+
+unsafe extern "C" fn rust_shm_barrier<F: VfsFile>(p_file: *mut ffi::sqlite3_file) {
+    let file = file_state::<F>(p_file);
+    let _ = catch_unwind(AssertUnwindSafe(|| file.shm_barrier()));
+}
+
+unsafe extern "C" fn rust_shm_unmap<F: VfsFile>(
+    p_file: *mut ffi::sqlite3_file,
+    delete_flag: c_int,
+) -> c_int {
+    let file = file_state::<F>(p_file);
+    let r = catch_unwind(AssertUnwindSafe(|| file.shm_unmap(delete_flag != 0)));
+    result_code(r, ffi::SQLITE_IOERR, |_| {})
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        register_vfs, AccessKind, FileFlags, FileKind, LockLevel, SyncFlags, Vfs, VfsFile,
+    };
+    use crate::{Connection, Error, OpenFlags, Result};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    type Files = Arc<Mutex<HashMap<String, Arc<Mutex<Vec<u8>>>>>>;
+
+    /// Keep all files in memory.
+    struct MemVfs {
+        files: Files,
+    }
+
+    struct MemFile {
+        data: Arc<Mutex<Vec<u8>>>,
+        /// Temporary files have no name
+        name: Option<String>,
+        files: Files,
+        delete_on_close: bool,
+    }
+
+    impl Vfs for MemVfs {
+        type File = MemFile;
+
+        fn open(
+            &self,
+            path: Option<&str>,
+            _kind: FileKind,
+            flags: FileFlags,
+        ) -> Result<(MemFile, FileFlags)> {
+            let mut files = self.files.lock().unwrap();
+            let data = match path {
+                Some(path) => match files.get(path) {
+                    Some(data) => data.clone(),
+                    None if flags.contains(FileFlags::CREATE) => {
+                        let data = Arc::new(Mutex::new(Vec::new()));
+                        files.insert(path.to_owned(), data.clone());
+                        data
+                    }
+                    None => {
+                        return Err(Error::SqliteFailure(
+                            crate::ffi::Error::new(crate::ffi::SQLITE_CANTOPEN),
+                            None,
+                        ))
+                    }
+                },
+                None => Arc::new(Mutex::new(Vec::new())),
+            };
+            let file = MemFile {
+                data,
+                name: path.map(str::to_owned),
+                files: self.files.clone(),
+                delete_on_close: flags.contains(FileFlags::DELETE_ON_CLOSE),
+            };
+            Ok((file, flags))
+        }
+
+        fn delete(&self, path: &str, _sync_dir: bool) -> Result<()> {
+            self.files.lock().unwrap().remove(path);
+            Ok(())
+        }
+
+        fn access(&self, path: &str, _kind: AccessKind) -> Result<bool> {
+            Ok(self.files.lock().unwrap().contains_key(path))
+        }
+    }
+
+    impl VfsFile for MemFile {
+        fn read(&mut self, buf: &mut [u8], offset: u64) -> Result<usize> {
+            let data = self.data.lock().unwrap();
+            let offset = offset as usize;
+            if offset >= data.len() {
+                return Ok(0);
+            }
+            let n = buf.len().min(data.len() - offset);
+            buf[..n].copy_from_slice(&data[offset..offset + n]);
+            Ok(n)
+        }
+
+        fn write(&mut self, buf: &[u8], offset: u64) -> Result<()> {
+            let mut data = self.data.lock().unwrap();
+            let offset = offset as usize;
+            if data.len() < offset + buf.len() {
+                data.resize(offset + buf.len(), 0);
+            }
+            data[offset..offset + buf.len()].copy_from_slice(buf);
+            Ok(())
+        }
+
+        fn truncate(&mut self, size: u64) -> Result<()> {
+            self.data.lock().unwrap().resize(size as usize, 0);
+            Ok(())
+        }
+
+        fn sync(&mut self, _flags: SyncFlags) -> Result<()> {
+            Ok(())
+        }
+
+        fn file_size(&mut self) -> Result<u64> {
+            Ok(self.data.lock().unwrap().len() as u64)
+        }
+
+        fn lock(&mut self, _level: LockLevel) -> Result<bool> {
+            Ok(true)
+        }
+
+        fn unlock(&mut self, _level: LockLevel) -> Result<()> {
+            Ok(())
+        }
+
+        fn check_reserved_lock(&mut self) -> Result<bool> {
+            Ok(false)
+        }
+    }
+
+    impl Drop for MemFile {
+        fn drop(&mut self) {
+            if self.delete_on_close {
+                if let Some(ref name) = self.name {
+                    self.files.lock().unwrap().remove(name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_mem_vfs() -> Result<()> {
+        let files = Files::default();
+        register_vfs(
+            "test_mem_vfs",
+            MemVfs {
+                files: files.clone(),
+            },
+            false,
+        )?;
+
+        let db =
+            Connection::open_with_flags_and_vfs("test.db", OpenFlags::default(), "test_mem_vfs")?;
+        db.execute_batch(
+            "CREATE TABLE foo(x INTEGER);
+             BEGIN; INSERT INTO foo VALUES(42); INSERT INTO foo VALUES(43); COMMIT;",
+        )?;
+        assert!(files.lock().unwrap().contains_key("test.db"));
+        // The rollback journal has been deleted
+        assert!(!files.lock().unwrap().contains_key("test.db-journal"));
+        drop(db);
+
+        let db = Connection::open_with_flags_and_vfs(
+            "test.db",
+            OpenFlags::SQLITE_OPEN_READ_ONLY,
+            "test_mem_vfs",
+        )?;
+        let sum: i64 = db.query_row("SELECT sum(x) FROM foo", [], |r| r.get(0))?;
+        assert_eq!(85, sum);
+        let now: f64 = db.query_row("SELECT julianday('now')", [], |r| r.get(0))?;
+        assert!(now > 2_459_000.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_missing_file() -> Result<()> {
+        register_vfs(
+            "test_mem_vfs_missing",
+            MemVfs {
+                files: Files::default(),
+            },
+            false,
+        )?;
+        let r = Connection::open_with_flags_and_vfs(
+            "missing.db",
+            OpenFlags::SQLITE_OPEN_READ_WRITE,
+            "test_mem_vfs_missing",
+        );
+        assert!(r.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_twice() -> Result<()> {
+        let vfs = || MemVfs {
+            files: Files::default(),
+        };
+        register_vfs("test_mem_vfs_twice", vfs(), false)?;
+        assert!(register_vfs("test_mem_vfs_twice", vfs(), false).is_err());
+        Ok(())
+    }
+}
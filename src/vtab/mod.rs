@@ -1,7 +1,8 @@
 //! `feature = "vtab"` Create virtual tables.
 //!
 //! Follow these steps to create your own virtual table:
-//! 1. Write implemenation of [`VTab`] and [`VTabCursor`] traits.
+//! 1. Write implemenation of [`VTab`] and [`VTabCursor`] traits (and
+//!    [`UpdateVTab`] for a writable virtual table).
 //! 2. Create an instance of the [`Module`] structure specialized for [`VTab`] impl.
 //! from step 1.
 //! 3. Register your [`Module`] structure using [`Connection::create_module`].
@@ -161,13 +162,127 @@ pub fn eponymous_only_module<'vtab, T: VTab<'vtab>>() -> &'static Module<'vtab,
     }
 }
 
+/// `feature = "vtab"` Create a modifiable virtual table implementation.
+///
+/// Step 2 of [Creating New Virtual Table Implementations](https://sqlite.org/vtab.html#creating_new_virtual_table_implementations).
+pub fn update_module<'vtab, T: UpdateVTab<'vtab>>() -> &'static Module<'vtab, T> {
+    &Module {
+        base: ffi::sqlite3_module {
+            // We don't use V3
+            iVersion: 2,
+            xCreate: Some(rust_create::<T>),
+            xConnect: Some(rust_connect::<T>),
+            xBestIndex: Some(rust_best_index::<T>),
+            xDisconnect: Some(rust_disconnect::<T>),
+            xDestroy: Some(rust_destroy::<T>),
+            xOpen: Some(rust_open::<T>),
+            xClose: Some(rust_close::<T::Cursor>),
+            xFilter: Some(rust_filter::<T::Cursor>),
+            xNext: Some(rust_next::<T::Cursor>),
+            xEof: Some(rust_eof::<T::Cursor>),
+            xColumn: Some(rust_column::<T::Cursor>),
+            xRowid: Some(rust_rowid::<T::Cursor>),
+            xUpdate: Some(rust_update::<T>),
+            xBegin: None,
+            xSync: None,
+            xCommit: None,
+            xRollback: None,
+            xFindFunction: None,
+            xRename: None,
+            xSavepoint: None,
+            xRelease: None,
+            xRollbackTo: None,
+            ..ZERO_MODULE
+        },
+        phantom: PhantomData::<&'vtab T>,
+    }
+}
+
+/// `feature = "vtab"` Create a modifiable virtual table implementation which
+/// takes part in transactions.
+///
+/// Step 2 of [Creating New Virtual Table Implementations](https://sqlite.org/vtab.html#creating_new_virtual_table_implementations).
+pub fn transaction_module<'vtab, T: TransactionVTab<'vtab>>() -> &'static Module<'vtab, T> {
+    &Module {
+        base: ffi::sqlite3_module {
+            // We don't use V3
+            iVersion: 2, // xSavepoint, xRelease and xRollbackTo
+            xCreate: Some(rust_create::<T>),
+            xConnect: Some(rust_connect::<T>),
+            xBestIndex: Some(rust_best_index::<T>),
+            xDisconnect: Some(rust_disconnect::<T>),
+            xDestroy: Some(rust_destroy::<T>),
+            xOpen: Some(rust_open::<T>),
+            xClose: Some(rust_close::<T::Cursor>),
+            xFilter: Some(rust_filter::<T::Cursor>),
+            xNext: Some(rust_next::<T::Cursor>),
+            xEof: Some(rust_eof::<T::Cursor>),
+            xColumn: Some(rust_column::<T::Cursor>),
+            xRowid: Some(rust_rowid::<T::Cursor>),
+            xUpdate: Some(rust_update::<T>),
+            xBegin: Some(rust_begin::<T>),
+            xSync: Some(rust_sync::<T>),
+            xCommit: Some(rust_commit::<T>),
+            xRollback: Some(rust_rollback::<T>),
+            xFindFunction: None,
+            xRename: None,
+            xSavepoint: Some(rust_savepoint::<T>),
+            xRelease: Some(rust_release::<T>),
+            xRollbackTo: Some(rust_rollback_to::<T>),
+            ..ZERO_MODULE
+        },
+        phantom: PhantomData::<&'vtab T>,
+    }
+}
+
+/// `feature = "vtab"` Conflict resolution mode of the statement being
+/// evaluated by [`UpdateVTab`].
+///
+/// (See [SQLite doc](https://sqlite.org/c3ref/c_fail.html))
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[allow(non_snake_case, non_camel_case_types, missing_docs)]
+#[non_exhaustive]
+pub enum ConflictMode {
+    SQLITE_ROLLBACK,
+    SQLITE_IGNORE,
+    SQLITE_FAIL,
+    SQLITE_ABORT,
+    SQLITE_REPLACE,
+}
+
+impl From<c_int> for ConflictMode {
+    fn from(code: c_int) -> ConflictMode {
+        match code {
+            ffi::SQLITE_ROLLBACK => ConflictMode::SQLITE_ROLLBACK,
+            ffi::SQLITE_IGNORE => ConflictMode::SQLITE_IGNORE,
+            ffi::SQLITE_FAIL => ConflictMode::SQLITE_FAIL,
+            ffi::SQLITE_REPLACE => ConflictMode::SQLITE_REPLACE,
+            // SQLITE_ABORT is the default
+            _ => ConflictMode::SQLITE_ABORT,
+        }
+    }
+}
+
 /// `feature = "vtab"`
+///
+/// A virtual table may keep a clone of the connection given to
+/// [`VTab::connect`] / [`CreateVTab::create`], for example to query
+/// [`on_conflict`](VTabConnection::on_conflict) from [`UpdateVTab`] methods.
+#[derive(Clone)]
 pub struct VTabConnection(*mut ffi::sqlite3);
 
 impl VTabConnection {
     // TODO sqlite3_vtab_config (http://sqlite.org/c3ref/vtab_config.html)
 
-    // TODO sqlite3_vtab_on_conflict (http://sqlite.org/c3ref/vtab_on_conflict.html)
+    /// Determine the conflict resolution mode of the statement that invoked
+    /// the current [`UpdateVTab`] method.
+    ///
+    /// Only meaningful from within [`UpdateVTab::insert`] or
+    /// [`UpdateVTab::update`].
+    /// (See [SQLite doc](http://sqlite.org/c3ref/vtab_on_conflict.html))
+    pub fn on_conflict(&mut self) -> ConflictMode {
+        ConflictMode::from(unsafe { ffi::sqlite3_vtab_on_conflict(self.0) })
+    }
 
     /// Get access to the underlying SQLite database connection handle.
     ///
@@ -257,6 +372,71 @@ pub trait CreateVTab<'vtab>: VTab<'vtab> {
     }
 }
 
+/// `feature = "vtab"` Writable virtual table instance trait.
+///
+/// (See [SQLite doc](https://sqlite.org/vtab.html#xupdate))
+pub trait UpdateVTab<'vtab>: CreateVTab<'vtab> {
+    /// Delete the row identified by `rowid`.
+    fn delete(&mut self, rowid: ValueRef<'_>) -> Result<()>;
+    /// Insert a new row.
+    ///
+    /// `args[0]` is NULL, `args[1]` is the rowid requested for the new row
+    /// (NULL to let the virtual table choose one) and `args[2..]` are the
+    /// column values.
+    ///
+    /// Returns the rowid of the inserted row.
+    fn insert(&mut self, args: &Values<'_>) -> Result<i64>;
+    /// Update an existing row.
+    ///
+    /// `args[0]` is the rowid of the row to update, `args[1]` is its new rowid
+    /// (equal to `args[0]` unless the rowid is being modified) and `args[2..]`
+    /// are the new column values.
+    fn update(&mut self, args: &Values<'_>) -> Result<()>;
+}
+
+/// `feature = "vtab"` Writable virtual table instance trait with transaction
+/// support.
+///
+/// All methods do nothing by default.
+/// (See [SQLite doc](https://sqlite.org/vtab.html#the_xbegin_method))
+pub trait TransactionVTab<'vtab>: UpdateVTab<'vtab> {
+    /// Begin a transaction on the virtual table.
+    /// (See [SQLite doc](https://sqlite.org/vtab.html#the_xbegin_method))
+    fn begin(&mut self) -> Result<()> {
+        Ok(())
+    }
+    /// Start the first phase of a two-phase commit.
+    /// (See [SQLite doc](https://sqlite.org/vtab.html#the_xsync_method))
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+    /// Commit the current transaction.
+    /// (See [SQLite doc](https://sqlite.org/vtab.html#the_xcommit_method))
+    fn commit(&mut self) -> Result<()> {
+        Ok(())
+    }
+    /// Rollback the current transaction.
+    /// (See [SQLite doc](https://sqlite.org/vtab.html#the_xrollback_method))
+    fn rollback(&mut self) -> Result<()> {
+        Ok(())
+    }
+    /// Save the current state of the virtual table as savepoint `n`.
+    /// (See [SQLite doc](https://sqlite.org/vtab.html#the_xsavepoint_xrelease_and_xrollbackto_methods))
+    fn savepoint(&mut self, _n: c_int) -> Result<()> {
+        Ok(())
+    }
+    /// Release savepoint `n` and all subsequent ones.
+    /// (See [SQLite doc](https://sqlite.org/vtab.html#the_xsavepoint_xrelease_and_xrollbackto_methods))
+    fn release(&mut self, _n: c_int) -> Result<()> {
+        Ok(())
+    }
+    /// Restore the state of the virtual table saved by savepoint `n`.
+    /// (See [SQLite doc](https://sqlite.org/vtab.html#the_xsavepoint_xrelease_and_xrollbackto_methods))
+    fn rollback_to(&mut self, _n: c_int) -> Result<()> {
+        Ok(())
+    }
+}
+
 /// `feature = "vtab"` Index constraint operator.
 /// See [Virtual Table Constraint Operator Codes](https://sqlite.org/c3ref/c_index_constraint_eq.html) for details.
 #[derive(Debug, PartialEq)]
@@ -529,7 +709,7 @@ impl Context {
 }
 
 /// `feature = "vtab"` Wrapper to [`VTabCursor::filter`] arguments, the values
-/// requested by [`VTab::best_index`].
+/// requested by [`VTab::best_index`], or to [`UpdateVTab`] arguments.
 pub struct Values<'a> {
     args: &'a [*mut ffi::sqlite3_value],
 }
@@ -1001,6 +1181,105 @@ where
     }
 }
 
+unsafe extern "C" fn rust_update<'vtab, T>(
+    vtab: *mut ffi::sqlite3_vtab,
+    argc: c_int,
+    argv: *mut *mut ffi::sqlite3_value,
+    p_rowid: *mut ffi::sqlite3_int64,
+) -> c_int
+where
+    T: UpdateVTab<'vtab>,
+{
+    let args = slice::from_raw_parts_mut(argv, argc as usize);
+    let vt = vtab as *mut T;
+    let r = if args.len() == 1 {
+        (*vt).delete(ValueRef::from_value(args[0]))
+    } else if ffi::sqlite3_value_type(args[0]) == ffi::SQLITE_NULL {
+        let values = Values { args };
+        (*vt).insert(&values).map(|rowid| {
+            *p_rowid = rowid;
+        })
+    } else {
+        let values = Values { args };
+        (*vt).update(&values)
+    };
+    vtab_error(vtab, r)
+}
+
+unsafe extern "C" fn rust_begin<'vtab, T>(vtab: *mut ffi::sqlite3_vtab) -> c_int
+where
+    T: TransactionVTab<'vtab>,
+{
+    let vt = vtab as *mut T;
+    vtab_error(vtab, (*vt).begin())
+}
+
+unsafe extern "C" fn rust_sync<'vtab, T>(vtab: *mut ffi::sqlite3_vtab) -> c_int
+where
+    T: TransactionVTab<'vtab>,
+{
+    let vt = vtab as *mut T;
+    vtab_error(vtab, (*vt).sync())
+}
+
+unsafe extern "C" fn rust_commit<'vtab, T>(vtab: *mut ffi::sqlite3_vtab) -> c_int
+where
+    T: TransactionVTab<'vtab>,
+{
+    let vt = vtab as *mut T;
+    vtab_error(vtab, (*vt).commit())
+}
+
+unsafe extern "C" fn rust_rollback<'vtab, T>(vtab: *mut ffi::sqlite3_vtab) -> c_int
+where
+    T: TransactionVTab<'vtab>,
+{
+    let vt = vtab as *mut T;
+    vtab_error(vtab, (*vt).rollback())
+}
+
+unsafe extern "C" fn rust_savepoint<'vtab, T>(vtab: *mut ffi::sqlite3_vtab, n: c_int) -> c_int
+where
+    T: TransactionVTab<'vtab>,
+{
+    let vt = vtab as *mut T;
+    vtab_error(vtab, (*vt).savepoint(n))
+}
+
+unsafe extern "C" fn rust_release<'vtab, T>(vtab: *mut ffi::sqlite3_vtab, n: c_int) -> c_int
+where
+    T: TransactionVTab<'vtab>,
+{
+    let vt = vtab as *mut T;
+    vtab_error(vtab, (*vt).release(n))
+}
+
+unsafe extern "C" fn rust_rollback_to<'vtab, T>(vtab: *mut ffi::sqlite3_vtab, n: c_int) -> c_int
+where
+    T: TransactionVTab<'vtab>,
+{
+    let vt = vtab as *mut T;
+    vtab_error(vtab, (*vt).rollback_to(n))
+}
+
+/// Virtual tables methods can set an error message by assigning a string to
+/// `zErrMsg`.
+unsafe fn vtab_error<T>(vtab: *mut ffi::sqlite3_vtab, result: Result<T>) -> c_int {
+    match result {
+        Ok(_) => ffi::SQLITE_OK,
+        Err(Error::SqliteFailure(err, s)) => {
+            if let Some(err_msg) = s {
+                set_err_msg(vtab, &err_msg);
+            }
+            err.extended_code
+        }
+        Err(err) => {
+            set_err_msg(vtab, &err.to_string());
+            ffi::SQLITE_ERROR
+        }
+    }
+}
+
 /// Virtual table cursors can set an error message by assigning a string to
 /// `zErrMsg`.
 #[cold]
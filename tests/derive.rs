@@ -0,0 +1,130 @@
+//! Ensure the derive macros can be used outside `rusqlite` crate.
+#![cfg(feature = "derive")]
+
+use rusqlite::types::{FromSql, ToSql};
+use rusqlite::{params, Connection, Error, FromRow, Result};
+
+#[derive(Debug, PartialEq, FromSql, ToSql)]
+struct PersonId(i64);
+
+#[derive(Debug, PartialEq, FromSql, ToSql)]
+struct Name {
+    value: String,
+}
+
+#[derive(Debug, PartialEq, FromSql, ToSql)]
+enum Kind {
+    Admin,
+    #[sql(rename = "guest")]
+    Guest,
+}
+
+#[derive(Debug, PartialEq, FromSql, ToSql)]
+#[sql(integer)]
+enum Level {
+    Low = 1,
+    High = 10,
+}
+
+#[derive(Debug, Default, PartialEq, FromRow)]
+struct Audit {
+    #[sql(rename = "created")]
+    created_at: i64,
+}
+
+#[derive(Debug, PartialEq, FromRow)]
+struct Person {
+    id: PersonId,
+    name: Name,
+    kind: Kind,
+    #[sql(index = 3)]
+    level: Level,
+    #[sql(default)]
+    nickname: Option<String>,
+    #[sql(flatten)]
+    audit: Audit,
+}
+
+#[derive(Debug, PartialEq, FromRow)]
+struct Pair(String, Kind);
+
+fn checked_memory_handle() -> Result<Connection> {
+    let db = Connection::open_in_memory()?;
+    db.execute_batch(
+        "CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT, kind TEXT, level INTEGER, \
+         created INTEGER)",
+    )?;
+    Ok(db)
+}
+
+#[test]
+fn test_from_sql_to_sql() -> Result<()> {
+    let db = checked_memory_handle()?;
+    db.execute(
+        "INSERT INTO person VALUES (?, ?, ?, ?, 0)",
+        params![
+            PersonId(1),
+            Name {
+                value: "Alice".to_owned(),
+            },
+            Kind::Guest,
+            Level::High,
+        ],
+    )?;
+    let (kind, level): (String, i64) = db.query_row("SELECT kind, level FROM person", [], |r| {
+        Ok((r.get(0)?, r.get(1)?))
+    })?;
+    assert_eq!(("guest", 10), (&*kind, level));
+
+    let (kind, level): (Kind, Level) = db.query_row("SELECT kind, level FROM person", [], |r| {
+        Ok((r.get(0)?, r.get(1)?))
+    })?;
+    assert_eq!((Kind::Guest, Level::High), (kind, level));
+
+    match db.query_row("SELECT 'admin'", [], |r| r.get::<_, Kind>(0)) {
+        Err(Error::FromSqlConversionFailure(0, _, _)) => {}
+        r => panic!("unexpected result {:?}", r),
+    }
+    match db.query_row("SELECT 2", [], |r| r.get::<_, Level>(0)) {
+        Err(Error::IntegralValueOutOfRange(0, 2)) => {}
+        r => panic!("unexpected result {:?}", r),
+    }
+    assert_eq!(
+        Kind::Admin,
+        Kind::column_result(rusqlite::types::ValueRef::Text(b"Admin")).unwrap()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_from_row() -> Result<()> {
+    let db = checked_memory_handle()?;
+    db.execute_batch("INSERT INTO person VALUES (1, 'Alice', 'Admin', 1, 42)")?;
+
+    let mut stmt = db.prepare("SELECT id, name, kind, level, created FROM person")?;
+    let people = stmt
+        .query_map([], Person::from_row)?
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(
+        vec![Person {
+            id: PersonId(1),
+            name: Name {
+                value: "Alice".to_owned()
+            },
+            kind: Kind::Admin,
+            level: Level::Low,
+            nickname: None,
+            audit: Audit { created_at: 42 },
+        }],
+        people
+    );
+
+    let pair = db.query_row("SELECT name, kind FROM person", [], Pair::from_row)?;
+    assert_eq!(Pair("Alice".to_owned(), Kind::Admin), pair);
+
+    match db.query_row("SELECT id, name FROM person", [], Person::from_row) {
+        Err(Error::InvalidColumnName(name)) => assert_eq!("kind", name),
+        r => panic!("unexpected result {:?}", r),
+    }
+    Ok(())
+}
@@ -99,3 +99,320 @@ fn test_dummy_module() -> rusqlite::Result<()> {
     assert_eq!(1, dummy);
     Ok(())
 }
+
+#[cfg(feature = "vtab")]
+#[test]
+fn test_update_module() -> rusqlite::Result<()> {
+    use rusqlite::types::ValueRef;
+    use rusqlite::vtab::{
+        sqlite3_vtab, sqlite3_vtab_cursor, update_module, ConflictMode, Context, CreateVTab,
+        IndexInfo, UpdateVTab, VTab, VTabConnection, VTabCursor, Values,
+    };
+    use rusqlite::{Connection, Error, Result};
+    use std::os::raw::c_int;
+
+    #[repr(C)]
+    struct KeyValueTab {
+        /// Base class. Must be first
+        base: sqlite3_vtab,
+        db: VTabConnection,
+        rows: Vec<(i64, i64)>,
+    }
+
+    impl KeyValueTab {
+        fn position(&self, rowid: i64) -> Result<usize> {
+            self.rows
+                .iter()
+                .position(|&(id, _)| id == rowid)
+                .ok_or_else(|| Error::ModuleError(format!("no such rowid: {}", rowid)))
+        }
+    }
+
+    unsafe impl<'vtab> VTab<'vtab> for KeyValueTab {
+        type Aux = ();
+        type Cursor = KeyValueTabCursor;
+
+        fn connect(
+            db: &mut VTabConnection,
+            _aux: Option<&()>,
+            _args: &[&[u8]],
+        ) -> Result<(String, KeyValueTab)> {
+            let vtab = KeyValueTab {
+                base: sqlite3_vtab::default(),
+                db: db.clone(),
+                rows: Vec::new(),
+            };
+            Ok(("CREATE TABLE x(value)".to_owned(), vtab))
+        }
+
+        fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
+            info.set_estimated_cost(1.);
+            Ok(())
+        }
+
+        fn open(&'vtab self) -> Result<KeyValueTabCursor> {
+            Ok(KeyValueTabCursor {
+                base: sqlite3_vtab_cursor::default(),
+                rows: self.rows.clone(),
+                pos: 0,
+            })
+        }
+    }
+
+    impl CreateVTab<'_> for KeyValueTab {}
+
+    impl UpdateVTab<'_> for KeyValueTab {
+        fn delete(&mut self, rowid: ValueRef<'_>) -> Result<()> {
+            let pos = self.position(rowid.as_i64()?)?;
+            self.rows.remove(pos);
+            Ok(())
+        }
+
+        fn insert(&mut self, args: &Values<'_>) -> Result<i64> {
+            assert_eq!(ConflictMode::SQLITE_ABORT, self.db.on_conflict());
+            let rowid = match args.get::<Option<i64>>(1)? {
+                Some(rowid) => rowid,
+                None => self.rows.iter().map(|&(id, _)| id).max().unwrap_or(0) + 1,
+            };
+            self.rows.push((rowid, args.get(2)?));
+            Ok(rowid)
+        }
+
+        fn update(&mut self, args: &Values<'_>) -> Result<()> {
+            let pos = self.position(args.get(0)?)?;
+            self.rows[pos] = (args.get(1)?, args.get(2)?);
+            Ok(())
+        }
+    }
+
+    #[repr(C)]
+    struct KeyValueTabCursor {
+        /// Base class. Must be first
+        base: sqlite3_vtab_cursor,
+        /// Snapshot of the rows taken when the cursor was opened
+        rows: Vec<(i64, i64)>,
+        pos: usize,
+    }
+
+    unsafe impl VTabCursor for KeyValueTabCursor {
+        fn filter(
+            &mut self,
+            _idx_num: c_int,
+            _idx_str: Option<&str>,
+            _args: &Values<'_>,
+        ) -> Result<()> {
+            self.pos = 0;
+            Ok(())
+        }
+
+        fn next(&mut self) -> Result<()> {
+            self.pos += 1;
+            Ok(())
+        }
+
+        fn eof(&self) -> bool {
+            self.pos >= self.rows.len()
+        }
+
+        fn column(&self, ctx: &mut Context, _: c_int) -> Result<()> {
+            ctx.set_result(&self.rows[self.pos].1)
+        }
+
+        fn rowid(&self) -> Result<i64> {
+            Ok(self.rows[self.pos].0)
+        }
+    }
+
+    let db = Connection::open_in_memory()?;
+    db.create_module::<KeyValueTab>("kv", update_module(), None)?;
+    db.execute_batch("CREATE VIRTUAL TABLE temp.kv USING kv()")?;
+
+    db.execute("INSERT INTO kv (value) VALUES (?)", [10])?;
+    db.execute("INSERT INTO kv (rowid, value) VALUES (?, ?)", [5, 20])?;
+    db.execute("INSERT INTO kv (value) VALUES (?)", [30])?;
+    assert_eq!(6, db.last_insert_rowid());
+
+    assert_eq!(
+        1,
+        db.execute("UPDATE kv SET value = value + 1 WHERE rowid = 5", [])?
+    );
+    assert_eq!(
+        1,
+        db.execute("UPDATE kv SET rowid = 2 WHERE rowid = 1", [])?
+    );
+    assert_eq!(1, db.execute("DELETE FROM kv WHERE value = 30", [])?);
+
+    let mut stmt = db.prepare("SELECT rowid, value FROM kv ORDER BY rowid")?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))?
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(vec![(2, 10), (5, 21)], rows);
+
+    assert_eq!(
+        0,
+        db.execute("UPDATE kv SET value = 0 WHERE rowid = 42", [])?
+    );
+    Ok(())
+}
+
+#[cfg(feature = "vtab")]
+#[test]
+fn test_transaction_module() -> rusqlite::Result<()> {
+    use rusqlite::types::ValueRef;
+    use rusqlite::vtab::{
+        sqlite3_vtab, sqlite3_vtab_cursor, transaction_module, Context, CreateVTab, IndexInfo,
+        TransactionVTab, UpdateVTab, VTab, VTabConnection, VTabCursor, Values,
+    };
+    use rusqlite::{Connection, Result};
+    use std::os::raw::c_int;
+    use std::sync::{Arc, Mutex};
+
+    type Calls = Arc<Mutex<Vec<String>>>;
+
+    #[repr(C)]
+    struct LogTab {
+        /// Base class. Must be first
+        base: sqlite3_vtab,
+        calls: Calls,
+    }
+
+    impl LogTab {
+        fn call(&self, call: String) -> Result<()> {
+            self.calls.lock().unwrap().push(call);
+            Ok(())
+        }
+    }
+
+    unsafe impl<'vtab> VTab<'vtab> for LogTab {
+        type Aux = Calls;
+        type Cursor = LogTabCursor;
+
+        fn connect(
+            _: &mut VTabConnection,
+            aux: Option<&Calls>,
+            _args: &[&[u8]],
+        ) -> Result<(String, LogTab)> {
+            let vtab = LogTab {
+                base: sqlite3_vtab::default(),
+                calls: aux.unwrap().clone(),
+            };
+            Ok(("CREATE TABLE x(value)".to_owned(), vtab))
+        }
+
+        fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
+            info.set_estimated_cost(1.);
+            Ok(())
+        }
+
+        fn open(&'vtab self) -> Result<LogTabCursor> {
+            Ok(LogTabCursor {
+                base: sqlite3_vtab_cursor::default(),
+            })
+        }
+    }
+
+    impl CreateVTab<'_> for LogTab {}
+
+    impl UpdateVTab<'_> for LogTab {
+        fn delete(&mut self, _: ValueRef<'_>) -> Result<()> {
+            self.call("delete".to_owned())
+        }
+
+        fn insert(&mut self, _: &Values<'_>) -> Result<i64> {
+            self.call("insert".to_owned())?;
+            Ok(1)
+        }
+
+        fn update(&mut self, _: &Values<'_>) -> Result<()> {
+            self.call("update".to_owned())
+        }
+    }
+
+    impl TransactionVTab<'_> for LogTab {
+        fn begin(&mut self) -> Result<()> {
+            self.call("begin".to_owned())
+        }
+
+        fn sync(&mut self) -> Result<()> {
+            self.call("sync".to_owned())
+        }
+
+        fn commit(&mut self) -> Result<()> {
+            self.call("commit".to_owned())
+        }
+
+        fn rollback(&mut self) -> Result<()> {
+            self.call("rollback".to_owned())
+        }
+
+        fn savepoint(&mut self, n: c_int) -> Result<()> {
+            self.call(format!("savepoint {}", n))
+        }
+
+        fn release(&mut self, n: c_int) -> Result<()> {
+            self.call(format!("release {}", n))
+        }
+
+        fn rollback_to(&mut self, n: c_int) -> Result<()> {
+            self.call(format!("rollback_to {}", n))
+        }
+    }
+
+    #[repr(C)]
+    struct LogTabCursor {
+        /// Base class. Must be first
+        base: sqlite3_vtab_cursor,
+    }
+
+    unsafe impl VTabCursor for LogTabCursor {
+        fn filter(&mut self, _: c_int, _: Option<&str>, _: &Values<'_>) -> Result<()> {
+            Ok(())
+        }
+
+        fn next(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn eof(&self) -> bool {
+            true
+        }
+
+        fn column(&self, _: &mut Context, _: c_int) -> Result<()> {
+            Ok(())
+        }
+
+        fn rowid(&self) -> Result<i64> {
+            Ok(1)
+        }
+    }
+
+    let calls = Calls::default();
+    let db = Connection::open_in_memory()?;
+    db.create_module::<LogTab>("log", transaction_module(), Some(calls.clone()))?;
+    db.execute_batch("CREATE VIRTUAL TABLE temp.log USING log()")?;
+    let take = || std::mem::take(&mut *calls.lock().unwrap());
+    take();
+
+    db.execute_batch("BEGIN; INSERT INTO log VALUES (1); COMMIT;")?;
+    assert_eq!(vec!["begin", "insert", "sync", "commit"], take());
+    db.execute_batch("BEGIN; INSERT INTO log VALUES (1); ROLLBACK;")?;
+    assert_eq!(vec!["begin", "insert", "rollback"], take());
+    db.execute_batch(
+        "BEGIN; INSERT INTO log VALUES (1); SAVEPOINT a; INSERT INTO log VALUES (2);
+         ROLLBACK TO a; RELEASE a; COMMIT;",
+    )?;
+    assert_eq!(
+        vec![
+            "begin",
+            "insert",
+            "savepoint 0",
+            "insert",
+            "rollback_to 0",
+            "release 0",
+            "sync",
+            "commit"
+        ],
+        take()
+    );
+    Ok(())
+}